@@ -0,0 +1,148 @@
+/*!
+Large blobs (command output, screenshots, etc) attached to tasks. The content
+is stored out of band by an [AttachmentStore] and only a reference to it (a
+path or a URL) is added to task data, tagged with `#attachment`, so reporters
+can link to it without keeping the content in memory.
+
+There is no store by default, it has to be set with
+[TaskTree::set_attachment_store()](crate::task_tree::TaskTree::set_attachment_store)
+before anything can be attached.
+
+```no_run
+use ll::attachment::DirStore;
+use ll::task_tree::TASK_TREE;
+use ll::Task;
+use std::sync::Arc;
+
+# #[tokio::main]
+# async fn main() -> anyhow::Result<()> {
+TASK_TREE.set_attachment_store(Arc::new(DirStore::new("/var/log/my-daemon/attachments")));
+let task = Task::create_new("build");
+task.attach("stdout", b"compiling...".to_vec())?;
+# Ok(())
+# }
+```
+*/
+use crate::retention::RetentionPolicy;
+use crate::uniq_id::UniqID;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+pub const ATTACHMENT_TAG: &str = "attachment";
+
+pub enum AttachmentContent {
+    Bytes(Vec<u8>),
+    /// A file that already exists. The default store references it in place
+    /// instead of copying it.
+    Path(PathBuf),
+}
+
+impl From<Vec<u8>> for AttachmentContent {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for AttachmentContent {
+    fn from(bytes: &[u8]) -> Self {
+        Self::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<String> for AttachmentContent {
+    fn from(string: String) -> Self {
+        Self::Bytes(string.into_bytes())
+    }
+}
+
+impl From<PathBuf> for AttachmentContent {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<&Path> for AttachmentContent {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_path_buf())
+    }
+}
+
+/// see [TaskTree::set_attachment_store()](crate::task_tree::TaskTree::set_attachment_store)
+pub trait AttachmentStore: Send + Sync {
+    /// Store the content and return a reference to it that ends up in task
+    /// data
+    fn store(&self, task_id: UniqID, name: &str, content: AttachmentContent) -> Result<String>;
+}
+
+/// Writes attachments into files in a directory
+pub struct DirStore {
+    dir: PathBuf,
+    retention: Option<RetentionPolicy>,
+    /// Set for [temp()](DirStore::temp) stores
+    remove_on_drop: bool,
+}
+
+impl DirStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            retention: None,
+            remove_on_drop: false,
+        }
+    }
+
+    /// Apply the policy to the directory after every stored attachment
+    pub fn retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = Some(policy);
+        self
+    }
+
+    /// A store in `ll-attachments-<pid>` in the system temp directory. The
+    /// directory is removed when the store is dropped, together with the
+    /// last tree that uses it. Since the global
+    /// [TASK_TREE](crate::task_tree::TASK_TREE) is never dropped, use a
+    /// directory that is cleaned up some other way for it.
+    pub fn temp() -> Self {
+        let mut store =
+            Self::new(std::env::temp_dir().join(format!("ll-attachments-{}", std::process::id())));
+        store.remove_on_drop = true;
+        store
+    }
+}
+
+impl Drop for DirStore {
+    fn drop(&mut self) {
+        if self.remove_on_drop {
+            std::fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+}
+
+impl AttachmentStore for DirStore {
+    fn store(&self, task_id: UniqID, name: &str, content: AttachmentContent) -> Result<String> {
+        match content {
+            AttachmentContent::Bytes(bytes) => {
+                std::fs::create_dir_all(&self.dir)
+                    .with_context(|| format!("can't create {}", self.dir.display()))?;
+                let file_name = name
+                    .chars()
+                    .map(|c| {
+                        if c.is_alphanumeric() || c == '.' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect::<String>();
+                let path = self.dir.join(format!("{}-{}", task_id, file_name));
+                std::fs::write(&path, bytes)
+                    .with_context(|| format!("can't write {}", path.display()))?;
+                if let Some(retention) = &self.retention {
+                    retention.cleanup(&self.dir)?;
+                }
+                Ok(path.display().to_string())
+            }
+            AttachmentContent::Path(path) => Ok(path.display().to_string()),
+        }
+    }
+}
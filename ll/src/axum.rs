@@ -0,0 +1,42 @@
+/*!
+[axum](https://docs.rs/axum) integration, enabled with the `axum` feature.
+
+Add [TaskLayer](crate::tower::TaskLayer) to the router and use the
+[RequestTask] extractor in handlers to spawn subtasks under the task of the
+current request.
+
+```ignore
+async fn handler(RequestTask(task): RequestTask) -> Result<String, StatusCode> {
+    task.spawn("load_user", |_| async { Ok(()) }).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok("hi".into())
+}
+
+let app = Router::new()
+    .route("/", get(handler))
+    .layer(ll::tower::TaskLayer::new(&root));
+```
+*/
+use crate::task::Task;
+use axum_core::extract::FromRequestParts;
+use http::request::Parts;
+use http::StatusCode;
+
+/// Task of the current request. Requires
+/// [TaskLayer](crate::tower::TaskLayer) to be installed.
+pub struct RequestTask(pub Task);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestTask {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Task>()
+            .cloned()
+            .map(RequestTask)
+            .ok_or((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ll::tower::TaskLayer is not installed",
+            ))
+    }
+}
@@ -0,0 +1,142 @@
+//! Re-render [JsonlReporter](ll::reporters::JsonlReporter) output as if it
+//! was logged to the console.
+//!
+//! ```text
+//! ll-tail [--tree] [--level <l0..l3>] [FILE]
+//! ```
+//!
+//! Reads from STDIN if no file is given. By default every event is printed
+//! using the same text format as `StdioReporter`. With `--tree` the events
+//! are replayed into a task tree and the running tasks are shown with the
+//! live term status, which is useful for following a process that is still
+//! writing its log (`tail -f out.jsonl | ll-tail --tree`).
+use anyhow::{bail, Context, Result};
+use ll::reporters::jsonl::JsonlEvent;
+use ll::reporters::{Level, Reporter, StdioReporter, TaskReportType};
+use ll::{Task, TaskGuard};
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::sync::Arc;
+
+struct Args {
+    tree: bool,
+    level: Level,
+    path: Option<String>,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        tree: false,
+        level: Level::L3,
+        path: None,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--tree" => args.tree = true,
+            "--level" => {
+                let level = iter.next().context("--level requires a value")?;
+                args.level = level.parse()?;
+            }
+            "-h" | "--help" => {
+                println!("usage: ll-tail [--tree] [--level <l0..l3>] [FILE]");
+                std::process::exit(0);
+            }
+            _ if arg.starts_with('-') => bail!("unknown argument `{}`", arg),
+            _ => args.path = Some(arg),
+        }
+    }
+    Ok(args)
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    let input: Box<dyn BufRead> = match &args.path {
+        Some(path) => Box::new(std::io::BufReader::new(
+            std::fs::File::open(path).with_context(|| format!("can't open {}", path))?,
+        )),
+        None => Box::new(std::io::stdin().lock()),
+    };
+
+    let mut reporter = StdioReporter::new();
+    reporter.log_task_start = true;
+    reporter.use_stdout = true;
+    reporter.max_log_level = args.level;
+
+    if args.tree {
+        replay_tree(input, reporter)
+    } else {
+        render_text(input, reporter)
+    }
+}
+
+fn events(input: Box<dyn BufRead>) -> impl Iterator<Item = Result<JsonlEvent>> {
+    input.lines().filter_map(|line| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(JsonlEvent::parse(&line)),
+        Err(err) => Some(Err(err.into())),
+    })
+}
+
+fn render_text(input: Box<dyn BufRead>, reporter: StdioReporter) -> Result<()> {
+    for event in events(input) {
+        let event = event?;
+        let task = Arc::new(event.to_task_internal());
+        match event.report_type() {
+            TaskReportType::Start => reporter.task_start(task),
+            TaskReportType::Stalled => reporter.task_stalled(task),
+            TaskReportType::End => reporter.task_end(task),
+        }
+    }
+    Ok(())
+}
+
+/// Events are replayed as they are read, so timings shown in the tree are
+/// the time it took for events to arrive rather than the original ones.
+fn replay_tree(input: Box<dyn BufRead>, reporter: StdioReporter) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let _guard = runtime.enter();
+
+    ll::add_reporter(Arc::new(reporter));
+    ll::task_tree::TASK_TREE.set_force_flush(true);
+    ll::reporters::term_status::show();
+
+    let mut running: BTreeMap<u64, TaskGuard> = BTreeMap::new();
+    for event in events(input) {
+        let event = event?;
+        match event.report_type() {
+            TaskReportType::Start => {
+                let mut builder = Task::builder(event.name.as_str()).tags(event.tags.iter());
+                if let Some(parent) = event.parent_id.and_then(|id| running.get(&id)) {
+                    builder = builder.parent(parent);
+                }
+                for (key, value) in &event.data {
+                    builder = builder.data(key.as_str(), value.clone());
+                }
+                let guard = builder.start().fail_on_drop("no end event in the input");
+                for (key, value) in &event.data_transitive {
+                    guard.data_transitive(key, value.clone());
+                }
+                running.insert(event.id, guard);
+            }
+            TaskReportType::Stalled => {}
+            TaskReportType::End => {
+                if let Some(guard) = running.remove(&event.id) {
+                    for (key, value) in &event.data {
+                        guard.data(key, value.clone());
+                    }
+                    // the error was already formatted by the original process
+                    match event.error {
+                        Some(error) => drop(guard.fail_on_drop(error)),
+                        None => guard.success(),
+                    }
+                }
+            }
+        }
+    }
+
+    drop(running);
+    ll::task_tree::TASK_TREE.flush_blocking();
+    ll::reporters::term_status::hide();
+    Ok(())
+}
@@ -1,15 +1,31 @@
 use crate::level::Level;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
+use std::time::SystemTime;
+
+/// Only the latest changes are kept in [Data::timeline], so tasks that keep
+/// updating the same keys don't grow forever
+pub const MAX_DATA_TIMELINE_LEN: usize = 100;
 
 #[derive(Debug, Clone, Default)]
 pub struct Data {
     pub map: BTreeMap<String, DataEntry>,
+    /// Every time a value was added after the task was created, oldest first
+    pub timeline: Vec<DataChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataChange {
+    pub key: String,
+    pub value: DataValue,
+    pub at: SystemTime,
 }
 
 impl Data {
     pub fn empty() -> Self {
         Self {
             map: BTreeMap::new(),
+            timeline: vec![],
         }
     }
 }
@@ -21,6 +37,40 @@ impl Data {
         self.map.insert(key, data_entry);
     }
 
+    /// Same as [add()](Data::add), but also records the change in the
+    /// [timeline](Data::timeline)
+    pub fn add_at<S: Into<String>, V: Into<DataValue>>(
+        &mut self,
+        key: S,
+        value: V,
+        at: SystemTime,
+    ) {
+        let (key, tags) = crate::utils::extract_tags(key.into());
+        let value = value.into();
+        if self.timeline.len() >= MAX_DATA_TIMELINE_LEN {
+            self.timeline.remove(0);
+        }
+        self.timeline.push(DataChange {
+            key: key.clone(),
+            value: value.clone(),
+            at,
+        });
+        self.map.insert(key, DataEntry(value, tags));
+    }
+
+    /// When the value of `key` was last added, if it's in the timeline
+    pub fn added_at(&self, key: &str) -> Option<SystemTime> {
+        self.timeline
+            .iter()
+            .rev()
+            .find(|change| change.key == key)
+            .map(|change| change.at)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<DataEntry> {
+        self.map.remove(key)
+    }
+
     pub fn merge(&mut self, other: &Data) {
         for (k, v) in &other.map {
             self.map.insert(k.clone(), v.clone());
@@ -55,12 +105,156 @@ impl Data {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum DataValue {
     String(String),
     Int(i64),
     Float(f64),
     None,
+    /// A number with a unit. Text reporters print it human readable
+    /// (`1.4 GiB`, `320ms`), while serialized output keeps the raw number
+    /// as `{"value": 1503238553.6, "unit": "bytes"}`.
+    #[serde(
+        serialize_with = "serialize_quantity",
+        deserialize_with = "deserialize_quantity"
+    )]
+    Quantity(f64, Unit),
+    /// Structured value (object or array), e.g. from
+    /// [Task::data_serde()](crate::Task::data_serde). Printed as compact JSON.
+    Json(serde_json::Value),
+    /// Value that is always printed and serialized as `***`.
+    /// see [Secret]
+    #[serde(skip_deserializing)]
+    Secret(Secret<Box<DataValue>>),
+}
+
+/// Wrapper for values that must never show up in logs (tokens, passwords).
+/// Reporters, error contexts and serialized output all see `***`. Reporters
+/// that are explicitly trusted with the real value can get it with
+/// [reveal()](Secret::reveal) or [DataValue::reveal()].
+/// ```
+/// # #[tokio::main]
+/// # async fn main() {
+/// use ll::data::Secret;
+///
+/// let task = ll::Task::create_new("login");
+/// task.data("token", Secret::new("hunter2"));
+/// assert_eq!(task.get_data("token").unwrap().to_string(), "***");
+/// # }
+/// ```
+#[derive(Clone, PartialEq)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn reveal(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> std::fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+impl<T> std::fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "***")
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("***")
+    }
+}
+
+impl<T: Into<DataValue>> From<Secret<T>> for DataValue {
+    fn from(secret: Secret<T>) -> Self {
+        DataValue::Secret(Secret(Box::new(secret.0.into())))
+    }
+}
+
+impl DataValue {
+    /// The real value behind a [Secret], or the value itself if it isn't one.
+    /// Only meant for reporters that are trusted with credentials.
+    pub fn reveal(&self) -> &DataValue {
+        match self {
+            DataValue::Secret(secret) => secret.reveal().reveal(),
+            value => value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Bytes,
+    #[serde(rename = "ms")]
+    Milliseconds,
+    Items,
+}
+
+#[derive(Serialize, Deserialize)]
+struct QuantityRepr {
+    value: f64,
+    unit: Unit,
+}
+
+fn serialize_quantity<S: serde::Serializer>(
+    value: &f64,
+    unit: &Unit,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    QuantityRepr {
+        value: *value,
+        unit: *unit,
+    }
+    .serialize(serializer)
+}
+
+fn deserialize_quantity<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<(f64, Unit), D::Error> {
+    let repr = QuantityRepr::deserialize(deserializer)?;
+    Ok((repr.value, repr.unit))
+}
+
+fn format_quantity(value: f64, unit: Unit) -> String {
+    match unit {
+        Unit::Bytes => {
+            const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+            if value.abs() < 1024.0 {
+                return format!("{} B", value);
+            }
+            let mut value = value / 1024.0;
+            let mut unit = UNITS[0];
+            for next in &UNITS[1..] {
+                if value.abs() < 1024.0 {
+                    break;
+                }
+                value /= 1024.0;
+                unit = next;
+            }
+            format!("{:.1} {}", value, unit)
+        }
+        Unit::Milliseconds => {
+            if value.abs() < 1000.0 {
+                format!("{}ms", value.round())
+            } else if value.abs() < 60_000.0 {
+                format!("{:.1}s", value / 1000.0)
+            } else {
+                let secs = (value / 1000.0).round() as i64;
+                format!("{}m {}s", secs / 60, secs % 60)
+            }
+        }
+        Unit::Items => format!("{} items", value),
+    }
 }
 
 impl std::fmt::Display for Data {
@@ -80,6 +274,9 @@ impl std::fmt::Display for DataValue {
             DataValue::Int(i) => format!("{}", i),
             DataValue::Float(f) => format!("{}", f),
             DataValue::None => String::new(),
+            DataValue::Quantity(value, unit) => format_quantity(*value, *unit),
+            DataValue::Json(value) => value.to_string(),
+            DataValue::Secret(secret) => secret.to_string(),
         };
         write!(f, "{}", result)
     }
@@ -136,6 +333,75 @@ impl From<&Option<String>> for DataValue {
     }
 }
 
+/// How [Task::data_serde()](crate::Task::data_serde) turns a serialized
+/// value into data entries.
+/// see [TaskTree::set_serde_data_mode()](crate::TaskTree::set_serde_data_mode)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SerdeDataMode {
+    /// The whole value is stored under the given key as [DataValue::Json]
+    Nested,
+    /// Every leaf field gets its own key, e.g. `request.headers.host`
+    Flatten,
+}
+
+/// Key/value pairs to add to the task data for a serialized `value`. Tags in
+/// `key` (`request#dontprint`) are carried over to every flattened key.
+pub(crate) fn serde_data_entries<T: Serialize + ?Sized>(
+    key: &str,
+    value: &T,
+    mode: SerdeDataMode,
+) -> Result<Vec<(String, DataValue)>, serde_json::Error> {
+    let value = serde_json::to_value(value)?;
+    let (key, tags) = match key.find('#') {
+        Some(i) => key.split_at(i),
+        None => (key, ""),
+    };
+    let mut entries = vec![];
+    match mode {
+        SerdeDataMode::Nested => entries.push((key.to_string(), json_to_data_value(value))),
+        SerdeDataMode::Flatten => flatten_json(key.to_string(), value, &mut entries),
+    }
+    for (key, _) in &mut entries {
+        key.push_str(tags);
+    }
+    Ok(entries)
+}
+
+fn flatten_json(prefix: String, value: serde_json::Value, out: &mut Vec<(String, DataValue)>) {
+    match value {
+        serde_json::Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                flatten_json(format!("{}.{}", prefix, key), value, out);
+            }
+        }
+        serde_json::Value::Array(items) if !items.is_empty() => {
+            for (i, value) in items.into_iter().enumerate() {
+                flatten_json(format!("{}.{}", prefix, i), value, out);
+            }
+        }
+        value => out.push((prefix, json_to_data_value(value))),
+    }
+}
+
+fn json_to_data_value(value: serde_json::Value) -> DataValue {
+    match value {
+        serde_json::Value::Null => DataValue::None,
+        serde_json::Value::Bool(b) => b.into(),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => DataValue::Int(i),
+            None => DataValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(s) => DataValue::String(s),
+        value => DataValue::Json(value),
+    }
+}
+
+impl From<std::time::Duration> for DataValue {
+    fn from(duration: std::time::Duration) -> Self {
+        DataValue::Quantity(duration.as_secs_f64() * 1000.0, Unit::Milliseconds)
+    }
+}
+
 macro_rules! from_int_types {
     ( $( $t:ty ),* ) => {
         $(
@@ -149,3 +415,69 @@ macro_rules! from_int_types {
 }
 
 from_int_types!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k9::*;
+
+    #[test]
+    fn quantity_test() {
+        let display = |value: f64, unit: Unit| DataValue::Quantity(value, unit).to_string();
+        assert_equal!(display(512.0, Unit::Bytes), "512 B");
+        assert_equal!(
+            display(1.4 * 1024.0 * 1024.0 * 1024.0, Unit::Bytes),
+            "1.4 GiB"
+        );
+        assert_equal!(display(320.4, Unit::Milliseconds), "320ms");
+        assert_equal!(display(2345.0, Unit::Milliseconds), "2.3s");
+        assert_equal!(display(125_000.0, Unit::Milliseconds), "2m 5s");
+        assert_equal!(display(3.0, Unit::Items), "3 items");
+
+        let value = DataValue::Quantity(1024.0, Unit::Bytes);
+        let json = serde_json::to_string(&value).unwrap();
+        assert_equal!(json, r#"{"value":1024.0,"unit":"bytes"}"#);
+        assert_equal!(serde_json::from_str::<DataValue>(&json).unwrap(), value);
+        assert_equal!(
+            DataValue::from(std::time::Duration::from_millis(320)),
+            DataValue::Quantity(320.0, Unit::Milliseconds)
+        );
+    }
+
+    #[test]
+    fn secret_test() {
+        let value = DataValue::from(Secret::new("hunter2"));
+        assert_equal!(value.to_string(), "***");
+        assert_equal!(format!("{:?}", value), "Secret(Secret(***))");
+        assert_equal!(serde_json::to_string(&value).unwrap(), r#""***""#);
+        assert_equal!(value.reveal(), &DataValue::from("hunter2"));
+    }
+
+    #[test]
+    fn serde_data_entries_test() {
+        let value = serde_json::json!({"url": "/", "headers": {"host": "a.com"}, "ids": [1, 2]});
+        let nested =
+            serde_data_entries("request#dontprint", &value, SerdeDataMode::Nested).unwrap();
+        assert_equal!(
+            nested,
+            vec![(
+                "request#dontprint".to_string(),
+                DataValue::Json(value.clone())
+            )]
+        );
+        let flat = serde_data_entries("request", &value, SerdeDataMode::Flatten).unwrap();
+        assert_equal!(
+            flat,
+            vec![
+                ("request.headers.host".to_string(), "a.com".into()),
+                ("request.ids.0".to_string(), 1.into()),
+                ("request.ids.1".to_string(), 2.into()),
+                ("request.url".to_string(), "/".into()),
+            ]
+        );
+        assert_equal!(
+            serde_data_entries("n", &5, SerdeDataMode::Nested).unwrap(),
+            vec![("n".to_string(), DataValue::Int(5))]
+        );
+    }
+}
@@ -0,0 +1,90 @@
+/*!
+Transitive data that is isolated to one request or tenant. Data added to a
+[DataScope] is inherited by tasks created inside the scope, but never by
+tasks outside of it, even when it's added with
+[TaskTree::add_data_transitive()] while the scope is entered.
+
+```
+# #[tokio::main]
+# async fn main() {
+let tree = ll::TaskTree::new();
+let scope = tree.open_data_scope();
+scope.add_data_transitive("tenant_id", 5);
+let request = scope.create_task("request");
+assert_eq!(request.get_data("tenant_id"), Some(5.into()));
+
+let other = tree.create_task("other_request");
+assert_eq!(other.get_data("tenant_id"), None);
+# }
+```
+*/
+use crate::data::DataValue;
+use crate::task::Task;
+use crate::task_tree::TaskTree;
+use crate::uniq_id::UniqID;
+use std::future::Future;
+use std::sync::Arc;
+
+tokio::task_local! {
+    /// Address of the task tree and id of the entered scope
+    static CURRENT_DATA_SCOPE: (usize, UniqID);
+}
+
+/// see [module docs](self). Closed when dropped, tasks that were already
+/// created keep its data.
+pub struct DataScope {
+    id: UniqID,
+    task_tree: Arc<TaskTree>,
+}
+
+impl DataScope {
+    pub(crate) fn new(id: UniqID, task_tree: Arc<TaskTree>) -> Self {
+        Self { id, task_tree }
+    }
+
+    pub fn add_data_transitive<S: Into<String>, D: Into<DataValue>>(&self, key: S, value: D) {
+        self.task_tree
+            .add_data_transitive_for_scope(self.id, key.into(), value.into());
+    }
+
+    /// Create a root task that inherits the data of this scope
+    pub fn create_task(&self, name: &str) -> Task {
+        self.enter(|| self.task_tree.create_task(name))
+    }
+
+    /// Run `f` inside the scope. Root tasks created in it inherit the data
+    /// of this scope, and transitive data added to the task tree goes to
+    /// this scope instead.
+    pub fn enter<F: FnOnce() -> T, T>(&self, f: F) -> T {
+        CURRENT_DATA_SCOPE.sync_scope((tree_address(&self.task_tree), self.id), f)
+    }
+
+    /// Async version of [enter()](DataScope::enter). Tasks spawned with
+    /// `tokio::spawn` don't inherit the scope, since it's local to the
+    /// current tokio task.
+    pub async fn run<F: Future>(&self, f: F) -> F::Output {
+        CURRENT_DATA_SCOPE
+            .scope((tree_address(&self.task_tree), self.id), f)
+            .await
+    }
+
+    pub fn close(self) {}
+}
+
+impl Drop for DataScope {
+    fn drop(&mut self) {
+        self.task_tree.close_data_scope(self.id);
+    }
+}
+
+fn tree_address(task_tree: &TaskTree) -> usize {
+    task_tree as *const TaskTree as usize
+}
+
+/// Scope of `task_tree` entered in the current (tokio) task, if any
+pub(crate) fn current_data_scope(task_tree: &TaskTree) -> Option<UniqID> {
+    CURRENT_DATA_SCOPE
+        .try_with(|(address, id)| (*address == tree_address(task_tree)).then_some(*id))
+        .ok()
+        .flatten()
+}
@@ -0,0 +1,100 @@
+/*!
+Database query instrumentation.
+
+```
+# #[tokio::main]
+# async fn main() -> anyhow::Result<()> {
+let root = ll::Task::create_new("root");
+let sql = "SELECT id FROM users WHERE active";
+let users: Vec<u64> = ll::instrument_query(&root, sql, async {
+    anyhow::Ok(vec![1, 2, 3])
+})
+.await?;
+# Ok(())
+# }
+```
+*/
+use crate::tag::Tag;
+use crate::task::Task;
+use anyhow::Result;
+use std::future::Future;
+
+pub const DB: Tag = Tag::new("db");
+
+/// SQL longer than this is truncated in task data
+pub const MAX_SQL_LENGTH: usize = 200;
+
+/// Number of rows returned or affected by a query, recorded as `rows` data
+/// on the query task.
+pub trait RowCount {
+    fn row_count(&self) -> Option<u64>;
+}
+
+impl<T> RowCount for Vec<T> {
+    fn row_count(&self) -> Option<u64> {
+        Some(self.len() as u64)
+    }
+}
+
+impl<T> RowCount for Option<T> {
+    fn row_count(&self) -> Option<u64> {
+        Some(self.is_some() as u64)
+    }
+}
+
+/// Rows affected
+impl RowCount for u64 {
+    fn row_count(&self) -> Option<u64> {
+        Some(*self)
+    }
+}
+
+impl RowCount for () {
+    fn row_count(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl RowCount for sqlx_core::any::AnyQueryResult {
+    fn row_count(&self) -> Option<u64> {
+        Some(self.rows_affected())
+    }
+}
+
+/// Run a query future in a `#db` tagged subtask of `task`, named after the
+/// SQL statement type (`select`, `insert`, ...), with the (truncated) SQL and
+/// the row count as data.
+pub async fn instrument_query<F, T, E>(task: &Task, sql: &str, query: F) -> Result<T>
+where
+    F: Future<Output = std::result::Result<T, E>> + Send,
+    E: Into<anyhow::Error>,
+    T: RowCount + Send,
+{
+    let statement = sql
+        .split_whitespace()
+        .next()
+        .map(|word| word.to_lowercase())
+        .unwrap_or_else(|| "query".to_string());
+
+    Task::builder(statement)
+        .parent(task)
+        .tag(DB)
+        .data("sql", truncate(sql))
+        .spawn(|task| async move {
+            let result = query.await.map_err(Into::into)?;
+            if let Some(rows) = result.row_count() {
+                task.data("rows", rows as i64);
+            }
+            Ok(result)
+        })
+        .await
+}
+
+fn truncate(sql: &str) -> String {
+    let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+    match sql.char_indices().nth(MAX_SQL_LENGTH) {
+        Some((i, _)) => format!("{}...", &sql[..i]),
+        None => sql,
+    }
+}
@@ -0,0 +1,98 @@
+/*!
+[eyre](https://docs.rs/eyre) compatibility, enabled with the `eyre` feature.
+
+Task closures can return `eyre::Result` by using the `*_eyre` variants of
+spawn functions. eyre reports are carried through the task tree inside of
+anyhow errors, and are formatted with their own (possibly customized) eyre
+handler when tasks fail.
+*/
+use crate::task::Task;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+
+/// eyre report wrapped to be usable as an `anyhow::Error` source
+pub(crate) struct EyreError(pub(crate) eyre::Report);
+
+impl fmt::Display for EyreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Debug for EyreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl Error for EyreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        let report: &(dyn Error + 'static) = self.0.as_ref();
+        report.source()
+    }
+}
+
+/// anyhow error wrapped to be usable as an `eyre::Report` source
+struct AnyhowError(anyhow::Error);
+
+impl fmt::Display for AnyhowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Debug for AnyhowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl Error for AnyhowError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+fn to_anyhow<T>(result: eyre::Result<T>) -> anyhow::Result<T> {
+    result.map_err(|report| anyhow::Error::new(EyreError(report)))
+}
+
+fn to_eyre<T>(result: anyhow::Result<T>) -> eyre::Result<T> {
+    result.map_err(|err| eyre::Report::new(AnyhowError(err)))
+}
+
+/// If the error came from an eyre report, format it with the eyre handler
+/// (after the task context that was attached to it).
+pub(crate) fn format_error(err: &anyhow::Error) -> Option<String> {
+    err.downcast_ref::<EyreError>()
+        .map(|eyre_error| format!("{}\n\n{:?}", err, eyre_error.0))
+}
+
+impl Task {
+    /// Same as [spawn()](Task::spawn) for closures returning `eyre::Result`
+    pub async fn spawn_eyre<F, FT, T, S: Into<String>>(&self, name: S, f: F) -> eyre::Result<T>
+    where
+        F: FnOnce(Task) -> FT,
+        FT: Future<Output = eyre::Result<T>> + Send,
+        T: Send,
+    {
+        let result = self
+            .spawn(name, |task| {
+                let future = f(task);
+                async move { to_anyhow(future.await) }
+            })
+            .await;
+        to_eyre(result)
+    }
+
+    /// Same as [spawn_sync()](Task::spawn_sync) for closures returning
+    /// `eyre::Result`
+    pub fn spawn_sync_eyre<F, T, S: Into<String>>(&self, name: S, f: F) -> eyre::Result<T>
+    where
+        F: FnOnce(Task) -> eyre::Result<T>,
+        T: Send,
+    {
+        to_eyre(self.spawn_sync(name, |task| to_anyhow(f(task))))
+    }
+}
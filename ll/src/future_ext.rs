@@ -0,0 +1,71 @@
+use crate::task::Task;
+use crate::task_tree::TASK_TREE;
+use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Run existing futures as tasks without wrapping them into closures.
+///
+/// ```
+/// use ll::LlFutureExt;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// async fn fetch() -> anyhow::Result<u32> {
+///     Ok(42)
+/// }
+///
+/// let root = ll::Task::create_new("root");
+/// let value = fetch().in_task(&root, "fetch").await?;
+/// assert_eq!(value, 42);
+/// # Ok(())
+/// # }
+/// ```
+pub trait LlFutureExt<T>: Future<Output = Result<T>> + Sized {
+    /// Run this future as a subtask of `parent`.
+    fn in_task<'a, S: Into<String>>(
+        self,
+        parent: &Task,
+        name: S,
+    ) -> Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>
+    where
+        Self: Send + 'a,
+        T: Send + 'a;
+
+    /// Run this future as a new top level task.
+    fn in_new_task<'a, S: Into<String>>(
+        self,
+        name: S,
+    ) -> Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>
+    where
+        Self: Send + 'a,
+        T: Send + 'a;
+}
+
+impl<T, F: Future<Output = Result<T>>> LlFutureExt<T> for F {
+    fn in_task<'a, S: Into<String>>(
+        self,
+        parent: &Task,
+        name: S,
+    ) -> Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>
+    where
+        Self: Send + 'a,
+        T: Send + 'a,
+    {
+        let parent = parent.clone();
+        let name = name.into();
+        Box::pin(async move { parent.spawn(name, |_| self).await })
+    }
+
+    fn in_new_task<'a, S: Into<String>>(
+        self,
+        name: S,
+    ) -> Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>
+    where
+        Self: Send + 'a,
+        T: Send + 'a,
+    {
+        let name = name.into();
+        Box::pin(async move { TASK_TREE.spawn(name, |_| self, None).await })
+    }
+}
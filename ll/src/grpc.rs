@@ -0,0 +1,119 @@
+/*!
+[tonic](https://docs.rs/tonic) gRPC instrumentation, enabled with the `tonic`
+feature.
+
+[client_call()] and [server_call()] run an RPC in its own task named after
+the RPC method, with the status code and encoded message sizes as data. The
+task fails if the RPC returns a non-OK status. Trace context is carried in
+the `traceparent` metadata entry: it is added to outgoing requests and
+continued from incoming ones.
+
+[TraceContextInterceptor] can be used with generated clients instead, to
+only propagate trace context of a task without creating RPC tasks.
+*/
+// tonic::Status is large, but it's the error type tonic APIs use
+#![allow(clippy::result_large_err)]
+use crate::task::{Task, TaskGuard};
+use crate::trace_context::{continue_trace, traceparent, TRACEPARENT_HEADER};
+use std::future::Future;
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Response, Status};
+
+/// Wrap a client RPC, e.g.
+/// `client_call(&task, "users.Users/Get", request, |req| client.get(req))`
+pub async fn client_call<M, R, F, FT>(
+    task: &Task,
+    method: &str,
+    mut request: Request<M>,
+    f: F,
+) -> Result<Response<R>, Status>
+where
+    M: prost::Message,
+    R: prost::Message,
+    F: FnOnce(Request<M>) -> FT,
+    FT: Future<Output = Result<Response<R>, Status>>,
+{
+    let task = rpc_task(task, method, request.get_ref());
+    insert_traceparent(request.metadata_mut(), &task);
+    finish(task, f(request).await)
+}
+
+/// Wrap a server RPC handler. The handler gets the RPC task to spawn
+/// subtasks under.
+pub async fn server_call<M, R, F, FT>(
+    parent: &Task,
+    method: &str,
+    request: Request<M>,
+    f: F,
+) -> Result<Response<R>, Status>
+where
+    M: prost::Message,
+    R: prost::Message,
+    F: FnOnce(Task, Request<M>) -> FT,
+    FT: Future<Output = Result<Response<R>, Status>>,
+{
+    let task = rpc_task(parent, method, request.get_ref());
+    if let Some(header) = request
+        .metadata()
+        .get(TRACEPARENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        continue_trace(&task, header);
+    }
+    let result = f(Task::clone(&task), request).await;
+    finish(task, result)
+}
+
+fn insert_traceparent(metadata: &mut MetadataMap, task: &Task) {
+    if let Ok(value) = traceparent(task).parse() {
+        metadata.insert(TRACEPARENT_HEADER, value);
+    }
+}
+
+fn rpc_task<M: prost::Message>(parent: &Task, method: &str, message: &M) -> TaskGuard {
+    Task::builder(method)
+        .parent(parent)
+        .data("request_bytes", message.encoded_len() as i64)
+        .start()
+}
+
+fn finish<R: prost::Message>(
+    task: TaskGuard,
+    result: Result<Response<R>, Status>,
+) -> Result<Response<R>, Status> {
+    match &result {
+        Ok(response) => {
+            task.data("status", format!("{:?}", tonic::Code::Ok));
+            task.data("response_bytes", response.get_ref().encoded_len() as i64);
+            task.success();
+        }
+        Err(status) => {
+            task.data("status", format!("{:?}", status.code()));
+            task.fail(anyhow::anyhow!(
+                "RPC failed with {:?}: {}",
+                status.code(),
+                status.message()
+            ));
+        }
+    }
+    result
+}
+
+/// Client interceptor that adds trace context of `task` to every request
+#[derive(Clone)]
+pub struct TraceContextInterceptor {
+    task: Task,
+}
+
+impl TraceContextInterceptor {
+    pub fn new(task: &Task) -> Self {
+        Self { task: task.clone() }
+    }
+}
+
+impl tonic::service::Interceptor for TraceContextInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        insert_traceparent(request.metadata_mut(), &self.task);
+        Ok(request)
+    }
+}
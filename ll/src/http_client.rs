@@ -0,0 +1,104 @@
+/*!
+Instrumented [reqwest](https://docs.rs/reqwest) client, enabled with the
+`reqwest` feature.
+
+Every request runs in its own subtask with the URL, method, response status,
+size and number of retries as data, and carries a `traceparent` header so the
+receiving service can continue the trace of the calling task.
+*/
+use crate::task::Task;
+use crate::task_tree::RetryPolicy;
+use crate::trace_context::{traceparent, TRACEPARENT_HEADER};
+use anyhow::Result;
+
+#[derive(Clone)]
+pub struct Client {
+    inner: reqwest::Client,
+    retry_policy: RetryPolicy,
+}
+
+impl Client {
+    /// Requests aren't retried by default, see
+    /// [with_retry_policy()](Client::with_retry_policy)
+    pub fn new(inner: reqwest::Client) -> Self {
+        Self {
+            inner,
+            retry_policy: RetryPolicy {
+                max_retries: 0,
+                ..RetryPolicy::default()
+            },
+        }
+    }
+
+    /// Retry requests that failed to send or got a 5xx response. Requests
+    /// with streaming bodies can't be retried. If the last attempt still gets
+    /// a 5xx response, the request fails.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn inner(&self) -> &reqwest::Client {
+        &self.inner
+    }
+
+    pub async fn get(&self, task: &Task, url: &str) -> Result<reqwest::Response> {
+        let request = self.inner.get(url).build()?;
+        self.execute(task, request).await
+    }
+
+    /// Send `request` in a subtask of `task`
+    pub async fn execute(
+        &self,
+        task: &Task,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response> {
+        let name = format!("{} {}", request.method(), request.url().path());
+        Task::builder(name)
+            .parent(task)
+            .data("method", request.method().as_str())
+            .data("url", request.url().as_str())
+            .spawn(|task| async move {
+                let mut request = request;
+                request.headers_mut().insert(
+                    TRACEPARENT_HEADER,
+                    reqwest::header::HeaderValue::from_str(&traceparent(&task))?,
+                );
+
+                let mut backoff = self.retry_policy.initial_backoff;
+                let mut retries = 0;
+                let response = loop {
+                    let retry = match retries < self.retry_policy.max_retries {
+                        true => request.try_clone(),
+                        false => None,
+                    };
+                    let result = self.inner.execute(request).await;
+                    let should_retry = match &result {
+                        Ok(response) => response.status().is_server_error(),
+                        Err(_) => true,
+                    };
+                    match retry {
+                        Some(retry) if should_retry => {
+                            tokio::time::sleep(backoff).await;
+                            backoff = std::cmp::min(backoff * 2, self.retry_policy.max_backoff);
+                            retries += 1;
+                            request = retry;
+                        }
+                        _ => break result,
+                    }
+                };
+
+                task.data("retries", retries as i64);
+                let response = response?;
+                task.data("status", response.status().as_u16() as i64);
+                if let Some(bytes) = response.content_length() {
+                    task.data("bytes", bytes as i64);
+                }
+                if self.retry_policy.max_retries > 0 && response.status().is_server_error() {
+                    anyhow::bail!("{} after {} retries", response.status(), retries);
+                }
+                Ok(response)
+            })
+            .await
+    }
+}
@@ -0,0 +1,178 @@
+/*!
+One call setup for applications that don't need custom reporters.
+
+```
+# #[tokio::main]
+# async fn main() -> anyhow::Result<()> {
+let root = ll::init("my_cli");
+root.spawn_sync("do_work", |_| Ok(()))?;
+# Ok(())
+# }
+```
+*/
+use crate::redaction::RedactionRules;
+#[cfg(feature = "term-status")]
+use crate::reporters::term_status::TERM_STATUS;
+use crate::reporters::text::TimestampFormat;
+use crate::reporters::{Level, StdioReporter};
+use crate::task::Task;
+use crate::task_tree::TASK_TREE;
+use anyhow::{Context, Result};
+use std::sync::Arc;
+
+/// Environment variable that overrides the log level, e.g. `LL_LEVEL=l2`
+pub const LEVEL_ENV_VAR: &str = "LL_LEVEL";
+
+/// Install a [StdioReporter] (and, with the `term-status` feature, show the
+/// terminal status if STDERR is a TTY) on the global task tree and create a root task called `name`.
+/// Redaction rules from `LL_REDACTION_RULES` are applied, see
+/// [redaction](crate::redaction).
+/// see [builder()] to configure it, and [InitBuilder::init()] for how invalid
+/// environment variables are handled.
+pub fn init(name: &str) -> Task {
+    builder().init(name)
+}
+
+pub fn builder() -> InitBuilder {
+    InitBuilder {
+        level: Level::default(),
+        #[cfg(feature = "term-status")]
+        term_status: true,
+        log_task_start: false,
+        start_level: None,
+        use_stdout: false,
+        timestamp_format: None,
+    }
+}
+
+pub struct InitBuilder {
+    level: Level,
+    #[cfg(feature = "term-status")]
+    term_status: bool,
+    log_task_start: bool,
+    start_level: Option<Level>,
+    use_stdout: bool,
+    timestamp_format: Option<TimestampFormat>,
+}
+
+impl InitBuilder {
+    /// Maximum level of tasks that get reported. `LL_LEVEL` environment
+    /// variable takes precedence over it, so verbosity can be changed without
+    /// recompiling.
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Show the terminal status when STDERR is a TTY (on by default)
+    #[cfg(feature = "term-status")]
+    pub fn term_status(mut self, enabled: bool) -> Self {
+        self.term_status = enabled;
+        self
+    }
+
+    pub fn log_task_start(mut self, enabled: bool) -> Self {
+        self.log_task_start = enabled;
+        self
+    }
+
+    /// Max level of logged task starts, see
+    /// [StdioReporter::max_start_log_level]
+    pub fn start_level(mut self, level: Level) -> Self {
+        self.start_level = Some(level);
+        self
+    }
+
+    pub fn use_stdout(mut self, enabled: bool) -> Self {
+        self.use_stdout = enabled;
+        self
+    }
+
+    pub fn timestamp_format(mut self, format: Option<TimestampFormat>) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+
+    /// Invalid `LL_LEVEL` or `LL_REDACTION_RULES` values are ignored and
+    /// reported as a failed `ll_init` subtask of the returned root task.
+    /// see [try_init()](InitBuilder::try_init) to fail instead.
+    pub fn init(self, name: &str) -> Task {
+        let default_level = self.level;
+        let level = resolve_level(self.level, std::env::var(LEVEL_ENV_VAR).ok());
+        let redaction_rules = RedactionRules::from_env();
+        let root = self.install(
+            name,
+            *level.as_ref().unwrap_or(&default_level),
+            redaction_rules.as_ref().ok().cloned().flatten(),
+        );
+        // reported through the reporters that were just installed
+        for err in vec![level.err(), redaction_rules.err()]
+            .into_iter()
+            .flatten()
+        {
+            root.spawn_sync("ll_init", |_| Err::<(), _>(err)).ok();
+        }
+        root
+    }
+
+    /// Same as [init()](InitBuilder::init), but fails without setting
+    /// anything up if `LL_LEVEL` or `LL_REDACTION_RULES` are invalid
+    pub fn try_init(self, name: &str) -> Result<Task> {
+        let level = resolve_level(self.level, std::env::var(LEVEL_ENV_VAR).ok())?;
+        let redaction_rules = RedactionRules::from_env()?;
+        Ok(self.install(name, level, redaction_rules))
+    }
+
+    fn install(self, name: &str, level: Level, redaction_rules: Option<RedactionRules>) -> Task {
+        let mut reporter = StdioReporter::new();
+        reporter.max_log_level = level;
+        reporter.log_task_start = self.log_task_start;
+        reporter.max_start_log_level = self.start_level;
+        reporter.use_stdout = self.use_stdout;
+        reporter.timestamp_format = self.timestamp_format;
+        TASK_TREE.add_reporter(Arc::new(reporter));
+
+        if let Some(rules) = redaction_rules {
+            TASK_TREE.set_redaction_rules(rules);
+        }
+
+        #[cfg(feature = "term-status")]
+        if self.term_status {
+            TERM_STATUS.set_max_log_level(level);
+            crate::reporters::term_status::show();
+        }
+
+        Task::create_new(name)
+    }
+}
+
+fn resolve_level(level: Level, env_value: Option<String>) -> Result<Level> {
+    match env_value {
+        Some(value) => value
+            .parse::<Level>()
+            .with_context(|| format!("in {}", LEVEL_ENV_VAR)),
+        None => Ok(level),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k9::*;
+
+    #[test]
+    fn test_resolve_level() {
+        assert_equal!(resolve_level(Level::L2, None).unwrap(), Level::L2);
+        assert_equal!(
+            resolve_level(Level::L2, Some("L3".into())).unwrap(),
+            Level::L3
+        );
+        assert_equal!(
+            format!(
+                "{:#}",
+                resolve_level(Level::L2, Some("verbose".into())).unwrap_err()
+            ),
+            "in LL_LEVEL: invalid log level `verbose`, expected one of l0, l1, l2, l3"
+        );
+    }
+}
@@ -22,6 +22,7 @@ Example
 use ll::Task;
 
 async fn do_something() {
+    # #[cfg(feature = "term-status")]
     ll::reporters::term_status::show();
 
     let root_task = Task::create_new("root_task");
@@ -35,17 +36,64 @@ async fn do_something() {
 }
 ```
 
+## Features
+
+No features are enabled by default, so the core library doesn't pull in any
+terminal dependencies. Enable what you need, e.g.
+`ll = { version = "...", features = ["term-status"] }` for a CLI:
+
+- `color`: colored output of the text reporters (`colored`)
+- `term-status`: the live `TermStatus` tree (`crossterm`), implies `color`
+- `ratatui`: render the status tree as a `ratatui` widget
+- `axum`, `tower`, `tonic`, `reqwest`, `sqlx`, `rayon`, `eyre`: integrations
+- `audit`, `compression`, `zstd`, `encryption`: reporter middleware
+- `tail`: the `ll-tail` binary
+
  */
 #![allow(clippy::new_without_default)]
 
+pub mod attachment;
+#[cfg(feature = "axum")]
+pub mod axum;
+pub mod clock;
 pub mod data;
+pub mod data_scope;
+pub mod db;
+#[cfg(feature = "eyre")]
+pub mod eyre_compat;
+pub mod future_ext;
+#[cfg(feature = "tonic")]
+pub mod grpc;
+#[cfg(feature = "reqwest")]
+pub mod http_client;
+pub mod init;
 pub mod level;
+pub mod output_schema;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod process;
+pub mod progress;
+pub mod recurring;
+pub mod redaction;
+pub mod retention;
+pub mod schema;
+pub mod shutdown;
+pub mod snapshot;
+pub mod tag;
 pub mod task;
+pub mod task_builder;
 pub mod task_tree;
+pub mod test;
+#[cfg(feature = "tower")]
+pub mod tower;
+pub mod trace;
+pub mod trace_context;
 pub mod uniq_id;
 pub mod utils;
 
-pub use task::Task;
+pub use tag::Tag;
+pub use task::{Scope, Task, TaskGuard, TaskJoinHandle};
+pub use task_builder::TaskBuilder;
 
 pub mod reporters;
 pub use task_tree::add_reporter;
@@ -53,7 +101,12 @@ pub use task_tree::add_reporter;
 #[cfg(test)]
 mod tests;
 
-pub use data::{Data, DataEntry, DataValue};
+pub use data::{Data, DataEntry, DataValue, Secret, SerdeDataMode, Unit};
+pub use data_scope::DataScope;
+pub use db::instrument_query;
+pub use future_ext::LlFutureExt;
+pub use init::{builder, init};
+#[cfg(feature = "term-status")]
 pub use reporters::term_status::TermStatus;
 pub use reporters::text::StdioReporter;
 pub use reporters::text::StringReporter;
@@ -36,19 +36,13 @@ async fn main() {
     root_task
         .spawn("task_1 #randomtag", |task| async move {
             tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-            let t_clone = task.clone();
 
-            tokio::spawn(async move {
-                t_clone
-                    .spawn("detached_async_task", |task| async move {
-                        for i in 0..=1000 {
-                            task.progress(i, 1000);
-                            tokio::time::sleep(tokio::time::Duration::from_millis(8)).await;
-                        }
-                        Ok(())
-                    })
-                    .await
-                    .ok();
+            task.spawn_detached("detached_async_task", |task| async move {
+                for i in 0..=1000 {
+                    task.progress(i, 1000);
+                    tokio::time::sleep(tokio::time::Duration::from_millis(8)).await;
+                }
+                Ok(())
             });
 
             let (a, b) = tokio::join!(
@@ -77,14 +71,14 @@ async fn main() {
                     task.spawn("task_4", |task| async move {
                         task.spawn("will_error", |task| async move {
                             task.spawn_sync("hello", |_task| Ok(()))?;
-                            tokio::spawn(async move {
-                                task.spawn("will run longer that parent", |_task| async move {
+                            task.spawn_detached(
+                                "will run longer that parent",
+                                |_task| async move {
                                     tokio::time::sleep(tokio::time::Duration::from_millis(12000))
                                         .await;
                                     Ok(())
-                                })
-                                .await
-                            });
+                                },
+                            );
 
                             if FAIL_SOME {
                                 anyhow::bail!("omg no i failed");
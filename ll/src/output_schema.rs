@@ -0,0 +1,62 @@
+/*!
+Versioning of machine-readable output, so tools built on it can tell which
+format they are reading. Every [JSONL event](crate::reporters::jsonl::JsonlEvent)
+and [trace](crate::trace::Trace) has a `schema_version` field.
+
+Compatibility guarantees within a schema version:
+- fields are only added, never removed or renamed, and added fields have
+  defaults, so older output still parses.
+- the meaning and units of existing fields don't change.
+
+Anything else bumps [SCHEMA_VERSION]. Readers in this crate
+([JsonlEvent::parse()](crate::reporters::jsonl::JsonlEvent::parse),
+[Trace::from_json()](crate::trace::Trace::from_json)) accept the current and
+all older versions, and return an error for output of newer versions instead
+of misreading it. Output written before versioning was introduced has no
+`schema_version` field and is read as version 1.
+*/
+use anyhow::{bail, Result};
+
+/// Version of the output written by this crate version
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Used for output that has no `schema_version` field
+pub(crate) fn unversioned() -> u32 {
+    1
+}
+
+pub(crate) fn check_schema_version(version: u32) -> Result<()> {
+    if version > SCHEMA_VERSION {
+        bail!(
+            "output schema version {} is newer than the supported version {}, upgrade ll to read it",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reporters::jsonl::JsonlEvent;
+    use crate::trace::Trace;
+
+    const LINE: &str = r#"{"event":"start","id":1,"parent_id":null,"name":"root","parent_names":[],"tags":[],"data":{},"data_transitive":{},"started_at_ms":0,"finished_at_ms":null}"#;
+
+    #[test]
+    fn schema_version_test() {
+        k9::assert_equal!(JsonlEvent::parse(LINE).unwrap().schema_version, 1);
+        let newer = LINE.replacen('{', r#"{"schema_version":2,"#, 1);
+        k9::snapshot!(
+            JsonlEvent::parse(&newer).unwrap_err().to_string(),
+            "output schema version 2 is newer than the supported version 1, upgrade ll to read it"
+        );
+
+        let trace = Trace::default();
+        k9::assert_equal!(Trace::from_json(&trace.to_json_pretty()).unwrap(), trace);
+        k9::assert_equal!(
+            Trace::from_json(r#"{"tasks":[]}"#).unwrap().schema_version,
+            1
+        );
+    }
+}
@@ -0,0 +1,71 @@
+/*!
+[rayon](https://docs.rs/rayon) integration, enabled with the `rayon` feature.
+
+Rayon runs closures on its own thread pool, so tasks are passed to them
+explicitly instead of relying on anything thread local, which keeps the
+parent linkage correct no matter which thread picks up the work.
+*/
+use crate::task::Task;
+use anyhow::Result;
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+impl Task {
+    /// Process `items` in parallel under a new subtask called `name` that
+    /// reports how many items are done as its progress. If `chunk_size` is
+    /// set, every chunk of items is additionally processed in its own
+    /// subtask (`chunk_0`, `chunk_1`, ...) and the progress of the parent is
+    /// aggregated from them. Results are returned in the order of `items`.
+    pub fn par_spawn_each<I, F, T>(
+        &self,
+        name: &str,
+        items: I,
+        chunk_size: Option<usize>,
+        f: F,
+    ) -> Result<Vec<T>>
+    where
+        I: IntoParallelIterator,
+        I::Iter: IndexedParallelIterator,
+        F: Fn(&Task, I::Item) -> Result<T> + Send + Sync,
+        T: Send,
+    {
+        self.spawn_sync(name, |parent| {
+            let items = items.into_par_iter();
+            let total = items.len() as i64;
+            match chunk_size {
+                None => {
+                    let done = AtomicI64::new(0);
+                    parent.progress(0, total);
+                    items
+                        .map(|item| {
+                            let result = f(&parent, item);
+                            let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+                            parent.progress(done, total);
+                            result
+                        })
+                        .collect()
+                }
+                Some(chunk_size) => {
+                    parent.aggregate_progress(true);
+                    let chunks = items
+                        .chunks(chunk_size)
+                        .enumerate()
+                        .map(|(i, chunk)| {
+                            parent.spawn_sync(format!("chunk_{}", i), |task| {
+                                let len = chunk.len() as i64;
+                                task.progress(0, len);
+                                let mut results = vec![];
+                                for (done, item) in chunk.into_iter().enumerate() {
+                                    results.push(f(&task, item)?);
+                                    task.progress(done as i64 + 1, len);
+                                }
+                                Ok(results)
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    Ok(chunks.into_iter().flatten().collect())
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,259 @@
+/*!
+Child processes whose output is captured into a task.
+
+[Task::run_command()] runs a command in its own `#command` subtask, with
+timeouts and retries from [CommandOptions]. While it runs, the terminal
+status shows the last line of its output.
+
+```
+# #[tokio::main]
+# async fn main() -> anyhow::Result<()> {
+let root = ll::Task::create_new("root");
+let mut command = tokio::process::Command::new("echo");
+command.arg("fetching");
+root.run_command("fetch", command).await?;
+# Ok(())
+# }
+```
+
+[Task::capture_child()] captures the output of a process into an existing
+task instead:
+
+```
+# #[tokio::main]
+# async fn main() -> anyhow::Result<()> {
+let root = ll::Task::create_new("root");
+root.spawn("list_files", |task| async move {
+    let mut command = tokio::process::Command::new("ls");
+    task.capture_child(&mut command).await
+})
+.await?;
+# Ok(())
+# }
+```
+*/
+use crate::tag::Tag;
+use crate::task::Task;
+use crate::task_tree::RetryPolicy;
+use anyhow::{bail, Context, Result};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+
+pub const COMMAND: Tag = Tag::new("command");
+
+/// Data key that every line the process writes to stdout is recorded under
+pub const STDOUT_KEY: &str = "stdout";
+pub const STDERR_KEY: &str = "stderr";
+pub const EXIT_CODE_KEY: &str = "exit_code";
+/// Data key of [run_command()](Task::run_command) tasks that holds the last
+/// line of output while the command runs and the (truncated) output once
+/// it's done
+pub const OUTPUT_KEY: &str = "output";
+
+pub const DEFAULT_MAX_OUTPUT_LEN: usize = 4096;
+
+/// Options of [Task::run_command_with()]
+#[derive(Clone, Copy, Debug)]
+pub struct CommandOptions {
+    /// Kill the process if it runs for longer than this. Every attempt gets
+    /// the full timeout.
+    pub timeout: Option<Duration>,
+    /// Retry runs that failed or timed out. Not retried by default.
+    pub retry_policy: RetryPolicy,
+    /// Only the last `max_output_len` bytes of stdout and stderr are kept in
+    /// the `output` data
+    pub max_output_len: usize,
+}
+
+impl Default for CommandOptions {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            retry_policy: RetryPolicy {
+                max_retries: 0,
+                ..RetryPolicy::default()
+            },
+            max_output_len: DEFAULT_MAX_OUTPUT_LEN,
+        }
+    }
+}
+
+/// see [Task::capture_child()]
+pub(crate) async fn capture_child(task: &Task, command: &mut Command) -> Result<ExitStatus> {
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("can't run `{}`", program))?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let (stdout_result, stderr_result, status) = tokio::join!(
+        read_lines(stdout, |line| task.data(STDOUT_KEY, line)),
+        read_lines(stderr, |line| task.data(STDERR_KEY, line)),
+        child.wait()
+    );
+    stdout_result?;
+    stderr_result?;
+    let status = status.with_context(|| format!("can't wait for `{}`", program))?;
+
+    if let Some(code) = status.code() {
+        task.data(EXIT_CODE_KEY, code as i64);
+    }
+    if !status.success() {
+        bail!("`{}` exited with {}", program, status);
+    }
+    Ok(status)
+}
+
+/// see [Task::run_command_with()]
+pub(crate) async fn run_command(
+    task: &Task,
+    name: String,
+    mut command: Command,
+    options: CommandOptions,
+) -> Result<ExitStatus> {
+    let command_line = format_command(&command);
+    Task::builder(name)
+        .parent(task)
+        .tag(COMMAND)
+        .data("command", command_line)
+        .spawn(|task| async move {
+            let retry_policy = options.retry_policy;
+            let mut backoff = retry_policy.initial_backoff;
+            let mut retries = 0;
+            loop {
+                match run_once(&task, &mut command, &options).await {
+                    Err(_) if retries < retry_policy.max_retries => {
+                        retries += 1;
+                        task.data("retries", retries as i64);
+                        tokio::time::sleep(backoff).await;
+                        backoff = std::cmp::min(backoff * 2, retry_policy.max_backoff);
+                    }
+                    result => return result,
+                }
+            }
+        })
+        .await
+}
+
+async fn run_once(
+    task: &Task,
+    command: &mut Command,
+    options: &CommandOptions,
+) -> Result<ExitStatus> {
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+    let started = Instant::now();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("can't run `{}`", program))?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let output = Mutex::new(OutputTail::new(options.max_output_len));
+    let record = |line: String| {
+        task.data(OUTPUT_KEY, line.as_str());
+        output.lock().unwrap().push(&line);
+    };
+    let running = async {
+        tokio::join!(
+            read_lines(stdout, record),
+            read_lines(stderr, record),
+            child.wait()
+        )
+    };
+    let result = match options.timeout {
+        Some(timeout) => tokio::time::timeout(timeout, running).await.ok(),
+        None => Some(running.await),
+    };
+
+    task.data(OUTPUT_KEY, output.into_inner().unwrap().text);
+    task.data("duration_ms", started.elapsed().as_millis() as i64);
+    let Some((stdout_result, stderr_result, status)) = result else {
+        child.kill().await.ok();
+        bail!(
+            "`{}` timed out after {:?}",
+            program,
+            options.timeout.unwrap_or_default()
+        );
+    };
+    stdout_result?;
+    stderr_result?;
+    let status = status.with_context(|| format!("can't wait for `{}`", program))?;
+
+    if let Some(code) = status.code() {
+        task.data(EXIT_CODE_KEY, code as i64);
+    }
+    if !status.success() {
+        bail!("`{}` exited with {}", program, status);
+    }
+    Ok(status)
+}
+
+/// Last `max_len` bytes of output
+struct OutputTail {
+    text: String,
+    max_len: usize,
+}
+
+impl OutputTail {
+    fn new(max_len: usize) -> Self {
+        Self {
+            text: String::new(),
+            max_len,
+        }
+    }
+
+    fn push(&mut self, line: &str) {
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        self.text.push_str(line);
+        if self.text.len() > self.max_len {
+            let mut start = self.text.len() - self.max_len;
+            while !self.text.is_char_boundary(start) {
+                start += 1;
+            }
+            self.text.drain(..start);
+        }
+    }
+}
+
+fn format_command(command: &Command) -> String {
+    let command = command.as_std();
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Calls `on_line` for every line of `output`. Invalid UTF-8 is replaced
+/// rather than failing, which would close the pipe and kill the process
+/// with SIGPIPE.
+async fn read_lines<R, F>(output: R, mut on_line: F) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    F: FnMut(String),
+{
+    let mut output = BufReader::new(output);
+    let mut line = vec![];
+    while output.read_until(b'\n', &mut line).await? > 0 {
+        if line.ends_with(b"\n") {
+            line.pop();
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+        }
+        on_line(String::from_utf8_lossy(&line).into_owned());
+        line.clear();
+    }
+    Ok(())
+}
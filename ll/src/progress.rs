@@ -0,0 +1,107 @@
+/*!
+Adapters that report progress of a task as items of an iterator or a stream
+are consumed.
+
+```
+use ll::progress::ProgressIteratorExt;
+
+# #[tokio::main]
+# async fn main() {
+let task = ll::Task::create_new("process_files");
+let files = vec!["a.txt", "b.txt", "c.txt"];
+for _file in files.iter().ll_progress(&task, 3) {
+    // process the file
+}
+# }
+```
+*/
+use crate::task::Task;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+pub trait ProgressIteratorExt: Iterator + Sized {
+    /// Report `(yielded items, total)` as progress of `task` every time an
+    /// item is yielded.
+    fn ll_progress(self, task: &Task, total: i64) -> ProgressIter<Self> {
+        task.progress(0, total);
+        ProgressIter {
+            inner: self,
+            task: task.clone(),
+            done: 0,
+            total,
+        }
+    }
+}
+
+impl<I: Iterator> ProgressIteratorExt for I {}
+
+pub struct ProgressIter<I> {
+    inner: I,
+    task: Task,
+    done: i64,
+    total: i64,
+}
+
+impl<I: Iterator> Iterator for ProgressIter<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let item = self.inner.next()?;
+        self.done += 1;
+        self.task.progress(self.done, self.total);
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+pub trait ProgressStreamExt: Stream + Sized {
+    /// Report progress of `task` every time an item is yielded. The total is
+    /// taken from the stream's size hint and grows with the number of yielded
+    /// items if the hint turns out to be too low.
+    fn ll_progress(self, task: &Task) -> ProgressStream<Self> {
+        ProgressStream {
+            inner: self,
+            task: task.clone(),
+            done: 0,
+        }
+    }
+}
+
+impl<S: Stream> ProgressStreamExt for S {}
+
+pin_project_lite::pin_project! {
+    pub struct ProgressStream<S> {
+        #[pin]
+        inner: S,
+        task: Task,
+        done: i64,
+    }
+}
+
+impl<S: Stream> Stream for ProgressStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let mut this = self.project();
+        let poll = this.inner.as_mut().poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(_)) => {
+                *this.done += 1;
+                let (lower, upper) = this.inner.size_hint();
+                let remaining = upper.unwrap_or(lower) as i64;
+                this.task.progress(*this.done, *this.done + remaining);
+            }
+            Poll::Ready(None) => this.task.progress(*this.done, *this.done),
+            Poll::Pending => {}
+        }
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
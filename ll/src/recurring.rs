@@ -0,0 +1,102 @@
+/*!
+Tasks that run the same piece of work over and over (polling a queue,
+handling heartbeats). Every iteration is a lightweight occurrence under one
+long lived task, and reporters collapse them into a count and an average
+duration (`poll_queue ×1523, avg 4ms`) instead of printing every one of
+them. Failed occurrences are still reported individually.
+
+```
+# #[tokio::main]
+# async fn main() -> anyhow::Result<()> {
+let root = ll::Task::create_new("daemon");
+let poll_queue = root.recurring("poll_queue");
+for _ in 0..3 {
+    poll_queue.run(|_task| async { Ok(()) }).await?;
+}
+# Ok(())
+# }
+```
+*/
+use crate::data::{DataValue, Unit};
+use crate::task::Task;
+use anyhow::Result;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Tag of the long lived task that holds all occurrences
+pub const RECURRING_TAG: &str = "recurring";
+/// Tag of a single occurrence of a recurring task
+pub const OCCURRENCE_TAG: &str = "occurrence";
+
+/// Data key with the number of finished occurrences
+pub const OCCURRENCES_KEY: &str = "occurrences";
+/// Data key with the average duration of finished occurrences
+pub const AVG_DURATION_KEY: &str = "avg";
+
+/// see [Task::recurring()]
+#[derive(Clone)]
+pub struct Recurring {
+    task: Task,
+    count: Arc<AtomicU64>,
+    total_us: Arc<AtomicU64>,
+}
+
+impl Recurring {
+    pub(crate) fn new(task: Task) -> Self {
+        Self {
+            task,
+            count: Arc::new(AtomicU64::new(0)),
+            total_us: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The long lived task occurrences are created under. It finishes when
+    /// the last clone of this handle is dropped.
+    pub fn task(&self) -> &Task {
+        &self.task
+    }
+
+    pub async fn run<F, FT, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(Task) -> FT,
+        FT: Future<Output = Result<T>> + Send,
+        T: Send,
+    {
+        let started = Instant::now();
+        let result = self.task.spawn(self.occurrence_name(), f).await;
+        self.record(started.elapsed());
+        result
+    }
+
+    pub fn run_sync<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(Task) -> Result<T>,
+        T: Send,
+    {
+        let started = Instant::now();
+        let result = self.task.spawn_sync(self.occurrence_name(), f);
+        self.record(started.elapsed());
+        result
+    }
+
+    fn occurrence_name(&self) -> String {
+        let n = self.count.load(Ordering::SeqCst) + 1;
+        format!("{} #{} #nostatus", n, OCCURRENCE_TAG)
+    }
+
+    fn record(&self, duration: Duration) {
+        let count = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        let total_us = self
+            .total_us
+            .fetch_add(duration.as_micros() as u64, Ordering::SeqCst)
+            + duration.as_micros() as u64;
+        let avg_ms = total_us as f64 / count as f64 / 1000.0;
+        self.task.data(OCCURRENCES_KEY, count);
+        self.task.data(
+            AVG_DURATION_KEY,
+            DataValue::Quantity(avg_ms, Unit::Milliseconds),
+        );
+    }
+}
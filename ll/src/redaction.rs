@@ -0,0 +1,180 @@
+/*!
+Privacy rules for task data that can be changed at runtime. Values of data
+keys that are denied (or, once any allow rule exists, not allowed) are
+replaced with [REDACTED] before tasks reach reporters.
+
+Rules are written one per line (or separated by commas) as `deny:<pattern>`
+or `allow:<pattern>`, where `*` in the pattern matches any sequence of
+characters. Lines starting with `#` are comments.
+
+```text
+# never report credentials
+deny:*_token
+deny:password*
+```
+
+```
+use ll::redaction::RedactionRules;
+
+# #[tokio::main]
+# async fn main() -> anyhow::Result<()> {
+let tree = ll::TaskTree::new();
+tree.set_redaction_rules(RedactionRules::parse("deny:*_token, deny:email")?);
+# Ok(())
+# }
+```
+
+Rules can also be read from the `LL_REDACTION_RULES` env variable
+([from_env()](RedactionRules::from_env)) or a file, which
+[reload_on_sighup()] reloads without restarting the process.
+*/
+use crate::data::Data;
+use crate::test::name_matches;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+/// Environment variable read by [RedactionRules::from_env()]
+pub const REDACTION_RULES_ENV_VAR: &str = "LL_REDACTION_RULES";
+
+/// Value that redacted data is replaced with
+pub const REDACTED: &str = "<redacted>";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RedactionRules {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl RedactionRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only report data keys that match one of the allow patterns
+    pub fn allow<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.allow.push(pattern.into());
+        self
+    }
+
+    /// Never report data keys that match the pattern. Takes precedence over
+    /// allow rules.
+    pub fn deny<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.deny.push(pattern.into());
+        self
+    }
+
+    pub fn parse(rules: &str) -> Result<Self> {
+        let mut result = Self::new();
+        for rule in rules.lines().flat_map(|line| line.split(',')) {
+            let rule = rule.trim();
+            if rule.is_empty() || rule.starts_with('#') {
+                continue;
+            }
+            match rule.split_once(':') {
+                Some(("allow", pattern)) => result.allow.push(pattern.trim().to_string()),
+                Some(("deny", pattern)) => result.deny.push(pattern.trim().to_string()),
+                _ => bail!(
+                    "invalid redaction rule `{}`, expected `allow:<pattern>` or `deny:<pattern>`",
+                    rule
+                ),
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let rules = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read redaction rules from {}", path.display()))?;
+        Self::parse(&rules).with_context(|| format!("in {}", path.display()))
+    }
+
+    /// Rules from the `LL_REDACTION_RULES` env variable, if it's set
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(REDACTION_RULES_ENV_VAR) {
+            Ok(rules) => Self::parse(&rules)
+                .with_context(|| format!("in {}", REDACTION_RULES_ENV_VAR))
+                .map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn redacts(&self, key: &str) -> bool {
+        let matches = |patterns: &Vec<String>| patterns.iter().any(|p| name_matches(p, key));
+        matches(&self.deny) || (!self.allow.is_empty() && !matches(&self.allow))
+    }
+
+    /// Replace the values of redacted keys, including their past values in
+    /// the timeline
+    pub(crate) fn apply(&self, data: &mut Data) {
+        for (key, entry) in data.map.iter_mut() {
+            if self.redacts(key) {
+                entry.0 = REDACTED.into();
+            }
+        }
+        for change in data.timeline.iter_mut() {
+            if self.redacts(&change.key) {
+                change.value = REDACTED.into();
+            }
+        }
+    }
+}
+
+/// Load rules from `path` into the task tree now and again every time the
+/// process receives SIGHUP. If reloading fails, the previous rules stay in
+/// place and the error is printed to stderr. Requires a tokio runtime.
+#[cfg(unix)]
+pub fn reload_on_sighup<P: Into<std::path::PathBuf>>(
+    task_tree: std::sync::Arc<crate::TaskTree>,
+    path: P,
+) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let path = path.into();
+    task_tree.set_redaction_rules(RedactionRules::from_file(&path)?);
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match RedactionRules::from_file(&path) {
+                Ok(rules) => task_tree.set_redaction_rules(rules),
+                Err(err) => eprintln!("[ll] keeping previous redaction rules: {:?}", err),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() -> Result<()> {
+        let rules = RedactionRules::parse(
+            "
+# comment
+deny:*_token
+allow: user_*, allow:request_id
+",
+        )?;
+        k9::assert_equal!(
+            rules,
+            RedactionRules::new()
+                .deny("*_token")
+                .allow("user_*")
+                .allow("request_id")
+        );
+        k9::assert_equal!(rules.redacts("user_id"), false);
+        k9::assert_equal!(rules.redacts("user_token"), true);
+        k9::assert_equal!(rules.redacts("email"), true);
+        k9::assert_equal!(
+            RedactionRules::parse("hide:email").unwrap_err().to_string(),
+            "invalid redaction rule `hide:email`, expected `allow:<pattern>` or `deny:<pattern>`"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use super::{Reporter, TaskReportType};
+use crate::task_tree::TaskInternal;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::runtime::RuntimeFlavor;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+
+/// Async version of the [Reporter](crate::reporters::Reporter) trait, for
+/// reporters that need to `.await` when delivering events (e.g. sending them
+/// over network).
+/// see [add_async_reporter()](crate::task_tree::TaskTree::add_async_reporter)
+#[async_trait]
+pub trait AsyncReporter: Send + Sync {
+    async fn task_start(&self, _task: Arc<TaskInternal>) {}
+    async fn task_end(&self, _task: Arc<TaskInternal>) {}
+    async fn task_stalled(&self, _task: Arc<TaskInternal>) {}
+}
+
+enum Message {
+    Report(TaskReportType, Arc<TaskInternal>),
+    /// Sent back once all reports queued before it are delivered
+    Flush(oneshot::Sender<()>),
+}
+
+/// Sits between the task tree and an async reporter. Reports are sent into a
+/// bounded channel that is drained by a tokio task calling the async reporter.
+/// When the channel is full, the reporting thread blocks until there's space
+/// again, so a slow reporter slows down reporting instead of buffering
+/// everything in memory. The only exception is reporting from a
+/// `current_thread` runtime, which can't drain the channel while it's
+/// blocked. There the report fails right away with [BufferFull], which is
+/// not retried and counts as dropped in
+/// [reporter_stats()](crate::task_tree::TaskTree::reporter_stats).
+pub(crate) struct AsyncReporterBridge {
+    sender: mpsc::Sender<Message>,
+}
+
+/// Error of reports that didn't fit into the buffer of an async reporter and
+/// couldn't wait for it, see [AsyncReporterBridge]
+#[derive(Debug)]
+pub(crate) struct BufferFull;
+
+impl std::fmt::Display for BufferFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "async reporter buffer is full and a current_thread runtime can't wait for it"
+        )
+    }
+}
+
+impl std::error::Error for BufferFull {}
+
+impl AsyncReporterBridge {
+    /// A `buffer_size` of 0 is treated as 1
+    pub(crate) fn new(reporter: Arc<dyn AsyncReporter>, buffer_size: usize) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<Message>(buffer_size.max(1));
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    Message::Report(TaskReportType::Start, task) => reporter.task_start(task).await,
+                    Message::Report(TaskReportType::Stalled, task) => {
+                        reporter.task_stalled(task).await
+                    }
+                    Message::Report(TaskReportType::End, task) => reporter.task_end(task).await,
+                    Message::Flush(done) => {
+                        done.send(()).ok();
+                    }
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    /// Resolves once everything sent to the async reporter so far is
+    /// delivered.
+    pub(crate) async fn flush(&self) {
+        let (sender, receiver) = oneshot::channel();
+        if self.sender.send(Message::Flush(sender)).await.is_ok() {
+            receiver.await.ok();
+        }
+    }
+
+    /// Blocking version of [flush()](AsyncReporterBridge::flush). Must not be
+    /// called from within a tokio runtime.
+    pub(crate) fn flush_blocking(&self) {
+        let (sender, receiver) = oneshot::channel();
+        if self.sender.blocking_send(Message::Flush(sender)).is_ok() {
+            receiver.blocking_recv().ok();
+        }
+    }
+
+    fn send(&self, report_type: TaskReportType, task: Arc<TaskInternal>) -> Result<()> {
+        let msg = match self.sender.try_send(Message::Report(report_type, task)) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Closed(_)) => anyhow::bail!("async reporter was shut down"),
+            Err(TrySendError::Full(msg)) => msg,
+        };
+        let blocking_send = || {
+            self.sender
+                .blocking_send(msg)
+                .map_err(|_| anyhow::anyhow!("async reporter was shut down"))
+        };
+        match tokio::runtime::Handle::try_current().map(|handle| handle.runtime_flavor()) {
+            Err(_) => blocking_send(),
+            // Other workers keep draining the channel while this one waits
+            Ok(RuntimeFlavor::MultiThread) => tokio::task::block_in_place(blocking_send),
+            // Blocking the only thread of the runtime (e.g. when force
+            // flushing from a task) would deadlock it
+            Ok(_) => Err(BufferFull.into()),
+        }
+    }
+}
+
+impl Reporter for AsyncReporterBridge {
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.send(TaskReportType::Start, task)
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.send(TaskReportType::End, task)
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.send(TaskReportType::Stalled, task)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_tree::TaskTree;
+
+    struct Stuck;
+
+    #[async_trait]
+    impl AsyncReporter for Stuck {
+        async fn task_start(&self, _task: Arc<TaskInternal>) {
+            std::future::pending::<()>().await
+        }
+    }
+
+    #[tokio::test]
+    async fn full_buffer_on_current_thread_test() {
+        let tt = TaskTree::new();
+        let task = tt.create_task("root");
+        let task = Arc::new(
+            tt.tree_internal
+                .read()
+                .unwrap()
+                .get_task(task.0.id)
+                .unwrap()
+                .clone(),
+        );
+
+        // a buffer of 0 holds one report, the drain task doesn't get to run
+        // before the second one
+        let bridge = AsyncReporterBridge::new(Arc::new(Stuck), 0);
+        assert!(bridge.try_task_start(task.clone()).is_ok());
+        let err = bridge.try_task_start(task).unwrap_err();
+        assert!(err.is::<BufferFull>());
+    }
+}
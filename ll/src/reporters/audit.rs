@@ -0,0 +1,180 @@
+/*!
+Tamper-evident, append-only log of reports. Every line is a JSON record
+holding a [JsonlEvent], a sequence number and the hash of the previous
+record, so removing, reordering or editing any record breaks the chain,
+which [verify()] detects.
+
+```no_run
+use ll::reporters::audit::{self, AuditReporter};
+use std::sync::Arc;
+
+# fn main() -> anyhow::Result<()> {
+ll::add_reporter(Arc::new(AuditReporter::open("audit.log")?));
+
+// later, e.g. in a compliance check
+let file = std::fs::File::open("audit.log")?;
+let records = audit::verify(std::io::BufReader::new(file))?;
+# Ok(())
+# }
+```
+*/
+use super::jsonl::JsonlEvent;
+use super::{Reporter, TaskReportType};
+use crate::task_tree::TaskInternal;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// `prev_hash` of the first record in a chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+pub struct AuditReporter {
+    chain: Mutex<Chain>,
+}
+
+struct Chain {
+    writer: Box<dyn Write + Send>,
+    seq: u64,
+    prev_hash: String,
+}
+
+/// Hashed part of a record. The line written out is this record with a
+/// `hash` field appended, see [record_hash()].
+#[derive(Serialize)]
+struct Record<'a> {
+    seq: u64,
+    prev_hash: &'a str,
+    event: JsonlEvent,
+}
+
+#[derive(Deserialize)]
+struct RecordHeader {
+    seq: u64,
+    prev_hash: String,
+}
+
+impl AuditReporter {
+    /// Appends to the log at `path`, continuing its chain if the file
+    /// already has records.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let (seq, prev_hash) = match std::fs::File::open(path) {
+            Ok(file) => last_link(BufReader::new(file))
+                .with_context(|| format!("reading audit log {}", path.display()))?,
+            Err(_) => (0, GENESIS_HASH.to_string()),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::with_chain(Box::new(file), seq, prev_hash))
+    }
+
+    /// Starts a new chain in `writer`
+    pub fn with_writer(writer: Box<dyn Write + Send>) -> Self {
+        Self::with_chain(writer, 0, GENESIS_HASH.to_string())
+    }
+
+    fn with_chain(writer: Box<dyn Write + Send>, seq: u64, prev_hash: String) -> Self {
+        Self {
+            chain: Mutex::new(Chain {
+                writer,
+                seq,
+                prev_hash,
+            }),
+        }
+    }
+
+    fn write_event(&self, task: &TaskInternal, report_type: TaskReportType) -> Result<()> {
+        let mut chain = self.chain.lock().unwrap();
+        let seq = chain.seq + 1;
+        let record = serde_json::to_string(&Record {
+            seq,
+            prev_hash: &chain.prev_hash,
+            event: JsonlEvent::new(task, report_type),
+        })?;
+        let hash = record_hash(&record);
+        // `record` always ends with the closing brace of the object
+        let line = format!("{},\"hash\":\"{}\"}}", &record[..record.len() - 1], hash);
+        writeln!(chain.writer, "{}", line)?;
+        chain.writer.flush()?;
+        // only advance once the record is written, so a failed write that
+        // gets retried doesn't leave a gap in the chain
+        chain.seq = seq;
+        chain.prev_hash = hash;
+        Ok(())
+    }
+}
+
+impl Reporter for AuditReporter {
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.write_event(&task, TaskReportType::Start)
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.write_event(&task, TaskReportType::End)
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.write_event(&task, TaskReportType::Stalled)
+    }
+}
+
+/// Hex encoded SHA-256 of a serialized record (without its `hash` field)
+fn record_hash(record: &str) -> String {
+    Sha256::digest(record.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Splits a line into the hashed record and the hash stored with it
+fn split_line(line: &str) -> Option<(String, &str)> {
+    let (record, hash) = line.rsplit_once(",\"hash\":\"")?;
+    let hash = hash.strip_suffix("\"}")?;
+    Some((format!("{}}}", record), hash))
+}
+
+/// Checks that every record of the log is intact and links to the one
+/// before it. Returns the number of records.
+pub fn verify<R: BufRead>(reader: R) -> Result<u64> {
+    let (seq, _) = last_link(reader)?;
+    Ok(seq)
+}
+
+/// Verifies the chain and returns the sequence number and hash of its last
+/// record
+fn last_link<R: BufRead>(reader: R) -> Result<(u64, String)> {
+    let mut seq = 0;
+    let mut prev_hash = GENESIS_HASH.to_string();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = i + 1;
+        let Some((record, hash)) = split_line(&line) else {
+            bail!("line {}: not an audit record", line_number);
+        };
+        let header: RecordHeader = serde_json::from_str(&record)
+            .with_context(|| format!("line {}: not an audit record", line_number))?;
+        if header.seq != seq + 1 {
+            bail!(
+                "line {}: expected record {}, found {}",
+                line_number,
+                seq + 1,
+                header.seq
+            );
+        }
+        if header.prev_hash != prev_hash {
+            bail!("line {}: does not link to the previous record", line_number);
+        }
+        if record_hash(&record) != hash {
+            bail!(
+                "line {}: hash mismatch, the record was modified",
+                line_number
+            );
+        }
+        seq = header.seq;
+        prev_hash = hash.to_string();
+    }
+    Ok((seq, prev_hash))
+}
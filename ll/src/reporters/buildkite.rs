@@ -0,0 +1,62 @@
+use super::text::{make_string, DurationFormat, TimestampFormat};
+use super::{Reporter, TaskReportType, DONTPRINT_TAG};
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Reporter that prints Buildkite collapsible log sections. Every task at
+/// `group_depth` starts a collapsed `---` section. Buildkite sections end
+/// where the next one begins, so when a task fails its section is expanded
+/// with `^^^ +++` to make the error visible.
+pub struct BuildkiteReporter {
+    /// Depth of tasks that start a section, where 0 is root tasks
+    pub group_depth: usize,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl BuildkiteReporter {
+    pub fn new() -> Self {
+        Self::with_writer(Box::new(std::io::stdout()))
+    }
+
+    pub fn with_writer(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            group_depth: 0,
+            writer: Mutex::new(writer),
+        }
+    }
+
+    fn write_lines(&self, lines: &[String]) {
+        let mut writer = self.writer.lock().unwrap();
+        for line in lines {
+            writeln!(writer, "{}", line).ok();
+        }
+        writer.flush().ok();
+    }
+}
+
+impl Reporter for BuildkiteReporter {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        if task.parent_names.len() == self.group_depth && !task.tags.contains(DONTPRINT_TAG) {
+            self.write_lines(&[format!("--- {}", task.full_name())]);
+        }
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        if task.tags.contains(DONTPRINT_TAG) {
+            return;
+        }
+        let mut lines = vec![];
+        let failed = matches!(task.status, TaskStatus::Finished(TaskResult::Failure(_), _));
+        if failed && task.parent_names.len() >= self.group_depth {
+            lines.push("^^^ +++".to_string());
+        }
+        lines.push(make_string(
+            &task,
+            TimestampFormat::None,
+            DurationFormat::Milliseconds,
+            TaskReportType::End,
+        ));
+        self.write_lines(&lines);
+    }
+}
@@ -0,0 +1,98 @@
+use super::{Reporter, TaskReportType};
+use crate::data::DataValue;
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use crate::trace::Trace;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Reporter that records all reports as typed values, so tests can assert on
+/// logging behavior without comparing formatted strings.
+#[derive(Clone, Default)]
+pub struct CaptureReporter {
+    records: Arc<Mutex<Vec<TaskRecord>>>,
+}
+
+#[derive(Clone)]
+pub struct TaskRecord {
+    pub report_type: TaskReportType,
+    pub task: Arc<TaskInternal>,
+}
+
+impl CaptureReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All records in the order they were reported
+    pub fn records(&self) -> Vec<TaskRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// Finished task with the given name. Both short (`child`) and full
+    /// (`parent:child`) names can be used. If multiple tasks match, the first
+    /// one to finish is returned.
+    pub fn finished(&self, name: &str) -> Option<Arc<TaskInternal>> {
+        self.ended()
+            .into_iter()
+            .find(|task| task.name == name || task.full_name() == name)
+    }
+
+    /// All tasks that finished with an error
+    pub fn failed(&self) -> Vec<Arc<TaskInternal>> {
+        self.ended()
+            .into_iter()
+            .filter(|task| matches!(task.status, TaskStatus::Finished(TaskResult::Failure(_), _)))
+            .collect()
+    }
+
+    /// Data (including transitive data) of a finished task, see
+    /// [finished()](CaptureReporter::finished)
+    pub fn data_of(&self, name: &str) -> Option<BTreeMap<String, DataValue>> {
+        let task = self.finished(name)?;
+        Some(
+            task.all_data()
+                .map(|(k, entry)| (k.clone(), entry.0.clone()))
+                .collect(),
+        )
+    }
+
+    /// Task tree built from all records so far
+    pub fn trace(&self) -> Trace {
+        Trace::from_records(&self.records.lock().unwrap())
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+
+    fn ended(&self) -> Vec<Arc<TaskInternal>> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| record.report_type == TaskReportType::End)
+            .map(|record| record.task.clone())
+            .collect()
+    }
+
+    fn record(&self, task: Arc<TaskInternal>, report_type: TaskReportType) {
+        self.records
+            .lock()
+            .unwrap()
+            .push(TaskRecord { report_type, task });
+    }
+}
+
+impl Reporter for CaptureReporter {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        self.record(task, TaskReportType::Start);
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        self.record(task, TaskReportType::End);
+    }
+
+    fn task_stalled(&self, task: Arc<TaskInternal>) {
+        self.record(task, TaskReportType::Stalled);
+    }
+}
@@ -0,0 +1,76 @@
+use super::capture::TaskRecord;
+use super::{Reporter, TaskReportType};
+use crate::task_tree::TaskInternal;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Reporter that forwards reports into a tokio broadcast channel, so tests
+/// and applications can `.await` task events instead of polling.
+/// Only reports that happen after [subscribe()](ChannelReporter::subscribe)
+/// is called are received.
+#[derive(Clone)]
+pub struct ChannelReporter {
+    sender: broadcast::Sender<TaskRecord>,
+}
+
+/// Receiving end of a [ChannelReporter]
+pub struct TaskEvents(broadcast::Receiver<TaskRecord>);
+
+impl ChannelReporter {
+    /// `capacity` is how many reports are buffered for a subscriber that
+    /// doesn't keep up. When it's exceeded, the oldest reports are dropped for
+    /// that subscriber.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> TaskEvents {
+        TaskEvents(self.sender.subscribe())
+    }
+
+    fn send(&self, task: Arc<TaskInternal>, report_type: TaskReportType) {
+        // error only means there are no subscribers right now
+        self.sender.send(TaskRecord { report_type, task }).ok();
+    }
+}
+
+impl TaskEvents {
+    /// Next report, or None if the reporter was dropped
+    pub async fn next(&mut self) -> Option<TaskRecord> {
+        loop {
+            match self.0.recv().await {
+                Ok(record) => return Some(record),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Wait until a task with the given name (short or full) finishes
+    pub async fn finished(&mut self, name: &str) -> Option<Arc<TaskInternal>> {
+        while let Some(record) = self.next().await {
+            let task = record.task;
+            if record.report_type == TaskReportType::End
+                && (task.name == name || task.full_name() == name)
+            {
+                return Some(task);
+            }
+        }
+        None
+    }
+}
+
+impl Reporter for ChannelReporter {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        self.send(task, TaskReportType::Start);
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        self.send(task, TaskReportType::End);
+    }
+
+    fn task_stalled(&self, task: Arc<TaskInternal>) {
+        self.send(task, TaskReportType::Stalled);
+    }
+}
@@ -0,0 +1,256 @@
+/*!
+Streaming compression of reporter output. [CompressedWriter] wraps the
+writer of a file-based reporter (e.g. [JsonlReporter](super::JsonlReporter))
+and compresses everything written to it with gzip (or zstd with the `zstd`
+feature).
+
+```no_run
+use ll::reporters::compression::CompressedWriter;
+use ll::reporters::JsonlReporter;
+use std::sync::Arc;
+use std::time::Duration;
+
+# fn main() -> anyhow::Result<()> {
+let file = std::fs::File::create("log.jsonl.gz")?;
+ll::add_reporter(Arc::new(JsonlReporter::with_writer(Box::new(
+    CompressedWriter::gzip(file).flush_interval(Duration::from_secs(5)),
+))));
+# Ok(())
+# }
+```
+
+Reporters flush after every report, which would ruin the compression ratio,
+so flushes are only passed on to the compressor once per flush interval. A
+flush that is held back is passed on by a background thread once the
+interval is over, so everything written is at a flush point at most one
+flush interval after it was flushed, even if nothing else is written
+afterwards. Everything before the last flush point can be decompressed even
+if the process dies before the stream is finished.
+*/
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The background thread never checks for held back flushes more often than
+/// this, so a zero flush interval doesn't make it spin
+const MIN_FLUSH_TICK: Duration = Duration::from_millis(10);
+
+enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+pub struct CompressedWriter<W: Write> {
+    inner: Arc<Mutex<Inner<W>>>,
+}
+
+struct Inner<W: Write> {
+    encoder: Encoder<W>,
+    flush_interval: Duration,
+    last_flush: Instant,
+    /// A flush was held back and is due at `last_flush + flush_interval`
+    flush_pending: bool,
+}
+
+/// see [CompressedWriter::get_ref()]
+pub struct WriterRef<'a, W: Write>(MutexGuard<'a, Inner<W>>);
+
+impl<W: Write> Deref for WriterRef<'_, W> {
+    type Target = W;
+
+    fn deref(&self) -> &W {
+        match &self.0.encoder {
+            Encoder::Gzip(encoder) => encoder.get_ref(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.get_ref(),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> CompressedWriter<W> {
+    pub fn gzip(writer: W) -> Self {
+        Self::with_encoder(Encoder::Gzip(GzEncoder::new(
+            writer,
+            Compression::default(),
+        )))
+    }
+
+    /// zstd compression with the given level (1-22, 0 for the default)
+    #[cfg(feature = "zstd")]
+    pub fn zstd(writer: W, level: i32) -> std::io::Result<Self> {
+        let encoder = zstd::stream::write::Encoder::new(writer, level)?;
+        Ok(Self::with_encoder(Encoder::Zstd(encoder)))
+    }
+
+    fn with_encoder(encoder: Encoder<W>) -> Self {
+        let inner = Arc::new(Mutex::new(Inner {
+            encoder,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            last_flush: Instant::now(),
+            flush_pending: false,
+        }));
+        let weak = Arc::downgrade(&inner);
+        std::thread::spawn(move || flush_when_due(weak));
+        Self { inner }
+    }
+}
+
+impl<W: Write> CompressedWriter<W> {
+    /// How often flushes reach the compressor, see the [module docs](self).
+    /// With a zero interval every flush does.
+    pub fn flush_interval(self, interval: Duration) -> Self {
+        self.inner.lock().unwrap().flush_interval = interval;
+        self
+    }
+
+    /// The underlying (compressed) writer
+    pub fn get_ref(&self) -> WriterRef<'_, W> {
+        WriterRef(self.inner.lock().unwrap())
+    }
+
+    /// Writes the end of the compressed stream. Also done on drop.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.flush_pending = false;
+        match &mut inner.encoder {
+            Encoder::Gzip(encoder) => encoder.try_finish(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.do_finish(),
+        }
+    }
+}
+
+impl<W: Write> Inner<W> {
+    fn flush_encoder(&mut self) -> std::io::Result<()> {
+        self.last_flush = Instant::now();
+        self.flush_pending = false;
+        match &mut self.encoder {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Passes held back flushes on to the compressor once they are due. Exits
+/// when the writer is dropped.
+fn flush_when_due<W: Write>(inner: Weak<Mutex<Inner<W>>>) {
+    loop {
+        let sleep_for = {
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            let inner = inner.lock().unwrap();
+            let due_in = if inner.flush_pending {
+                (inner.last_flush + inner.flush_interval).saturating_duration_since(Instant::now())
+            } else {
+                inner.flush_interval
+            };
+            due_in.max(MIN_FLUSH_TICK)
+        };
+        std::thread::sleep(sleep_for);
+
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        let mut inner = inner.lock().unwrap();
+        if inner.flush_pending && inner.last_flush.elapsed() >= inner.flush_interval {
+            // there's nowhere to report the error to, the next write or
+            // flush will most likely fail with it too
+            inner.flush_encoder().ok();
+        }
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.inner.lock().unwrap().encoder {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.last_flush.elapsed() < inner.flush_interval {
+            inner.flush_pending = true;
+            return Ok(());
+        }
+        inner.flush_encoder()
+    }
+}
+
+impl<W: Write> Drop for CompressedWriter<W> {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    const LINE: &[u8] = b"{\"event\":\"end\",\"name\":\"request\"}\n";
+
+    #[test]
+    fn gzip_flush_points_test() -> std::io::Result<()> {
+        let mut writer = CompressedWriter::gzip(vec![]).flush_interval(Duration::from_secs(60));
+        writer.write_all(LINE)?;
+        writer.flush()?;
+        let before_flush_point = writer.get_ref().len();
+
+        writer = writer.flush_interval(Duration::ZERO);
+        writer.flush()?;
+        assert!(writer.get_ref().len() > before_flush_point);
+
+        for _ in 0..99 {
+            writer.write_all(LINE)?;
+        }
+        writer.finish()?;
+        let mut decompressed = vec![];
+        flate2::read::GzDecoder::new(writer.get_ref().as_slice()).read_to_end(&mut decompressed)?;
+        k9::assert_equal!(decompressed, LINE.repeat(100));
+        Ok(())
+    }
+
+    #[test]
+    fn held_back_flush_test() -> std::io::Result<()> {
+        let mut writer = CompressedWriter::gzip(vec![]).flush_interval(Duration::from_millis(50));
+        writer.write_all(LINE)?;
+        writer.flush()?;
+        // only the gzip header, the line is still in the compressor
+        let before = writer.get_ref().len();
+
+        // nothing else is written, the background thread flushes it
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while writer.get_ref().len() == before && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let after = writer.get_ref().len();
+        assert!(after > before);
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_test() -> std::io::Result<()> {
+        let mut writer = CompressedWriter::zstd(vec![], 0)?;
+        for _ in 0..100 {
+            writer.write_all(LINE)?;
+        }
+        writer.finish()?;
+        k9::assert_equal!(
+            zstd::decode_all(writer.get_ref().as_slice())?,
+            LINE.repeat(100)
+        );
+        Ok(())
+    }
+}
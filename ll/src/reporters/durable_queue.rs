@@ -0,0 +1,335 @@
+use super::jsonl::JsonlEvent;
+use super::middleware::deliver;
+use super::{Reporter, TaskReportType};
+use crate::task_tree::TaskInternal;
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// How many undelivered events a queue keeps by default before dropping the
+/// oldest ones
+pub const DEFAULT_MAX_QUEUED_EVENTS: usize = 10_000;
+
+/// Delay before retrying after the reporter rejected an event. Doubles with
+/// every failed retry up to [MAX_RETRY_DELAY].
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// see [ReporterExt::durable_queue()](super::ReporterExt::durable_queue).
+/// Every event is appended to the queue file and delivered from a
+/// background thread, so a slow or failing reporter never holds up the
+/// reporting thread. Events stay in the file until the reporter accepts
+/// them. Events that a reporter rejects (e.g. because its network sink is
+/// down) are retried in order with an exponential backoff (or right away
+/// with [replay()](DurableQueue::replay)), and events left over by a
+/// process that crashed are replayed when the queue is opened again.
+///
+/// Delivered events are removed from the file in batches: it is truncated
+/// whenever the queue runs empty, rewritten once it holds more delivered
+/// than pending events, and when the queue is dropped.
+///
+/// Reporters get tasks rebuilt from the queued [JsonlEvent]s
+/// (see [JsonlEvent::to_task_internal()]).
+///
+/// Delivery is at least once: after a crash, events that were delivered but
+/// not removed from the file yet are replayed.
+pub struct DurableQueue<R> {
+    shared: Arc<Shared<R>>,
+    /// Wakes up the delivery thread. It exits once this is dropped.
+    wake: Mutex<mpsc::Sender<()>>,
+}
+
+struct Shared<R> {
+    reporter: R,
+    path: PathBuf,
+    state: Mutex<QueueState>,
+    /// Held while events are delivered, so the delivery thread and
+    /// [replay()](DurableQueue::replay) don't deliver the same event twice
+    delivery: Mutex<()>,
+}
+
+struct QueueState {
+    file: File,
+    pending: VecDeque<JsonlEvent>,
+    /// Events removed from the front of `pending` so far, delivered or
+    /// dropped. Tells whether the event that is being delivered is still
+    /// the first one once the delivery returns.
+    removed: u64,
+    /// Events in the file, including the ones that were already removed
+    /// from `pending`
+    file_events: usize,
+    dropped: u64,
+    max_events: usize,
+    sync: bool,
+}
+
+impl<R: Reporter + 'static> DurableQueue<R> {
+    pub(super) fn open(reporter: R, path: PathBuf) -> Result<Self> {
+        let (pending, lines) = read_events(&path)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("can't open event queue {}", path.display()))?;
+        let shared = Arc::new(Shared {
+            reporter,
+            path,
+            state: Mutex::new(QueueState {
+                file,
+                pending,
+                removed: 0,
+                file_events: lines,
+                dropped: 0,
+                max_events: DEFAULT_MAX_QUEUED_EVENTS,
+                sync: false,
+            }),
+            delivery: Mutex::new(()),
+        });
+        {
+            let mut state = shared.state.lock().unwrap();
+            if state.file_events != state.pending.len() {
+                // drop lines that were cut off by a crash, otherwise the
+                // next event would be appended to one of them
+                shared.rewrite(&mut state)?;
+            }
+        }
+
+        let (wake, receiver) = mpsc::channel();
+        let weak = Arc::downgrade(&shared);
+        std::thread::spawn(move || deliver_in_background(weak, receiver));
+        // replay events left over by a previous run
+        wake.send(()).ok();
+        Ok(Self {
+            shared,
+            wake: Mutex::new(wake),
+        })
+    }
+}
+
+impl<R: Reporter> DurableQueue<R> {
+    /// Undelivered events above this limit are dropped, oldest first.
+    /// [DEFAULT_MAX_QUEUED_EVENTS] by default.
+    pub fn max_events(self, max_events: usize) -> Self {
+        self.shared.state.lock().unwrap().max_events = max_events;
+        self
+    }
+
+    /// `fsync` the queue file after every event. Without it events survive
+    /// the process crashing, but not the machine losing power.
+    pub fn sync(self, enabled: bool) -> Self {
+        self.shared.state.lock().unwrap().sync = enabled;
+        self
+    }
+
+    /// Number of events waiting to be delivered
+    pub fn pending(&self) -> usize {
+        self.shared.state.lock().unwrap().pending.len()
+    }
+
+    /// Number of events dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+
+    /// Deliver queued events in order right away instead of waiting for the
+    /// next retry, stopping at the first one the reporter rejects. Blocks
+    /// the calling thread. Returns the number of events still queued.
+    pub fn replay(&self) -> Result<usize> {
+        self.shared.drain()?;
+        Ok(self.pending())
+    }
+
+    fn report(&self, task: Arc<TaskInternal>, report_type: TaskReportType) -> Result<()> {
+        let event = JsonlEvent::new(&task, report_type);
+        let line = serde_json::to_string(&event)?;
+        let mut state = self.shared.state.lock().unwrap();
+        writeln!(state.file, "{}", line)
+            .and_then(|_| state.file.flush())
+            .with_context(|| {
+                format!("can't write to event queue {}", self.shared.path.display())
+            })?;
+        if state.sync {
+            state.file.sync_data()?;
+        }
+        state.file_events += 1;
+        state.pending.push_back(event);
+        while state.pending.len() > state.max_events {
+            state.pending.pop_front();
+            state.removed += 1;
+            state.dropped += 1;
+        }
+        drop(state);
+        // the event is safe on disk, delivery failures are retried by the
+        // delivery thread instead of being reported to the task tree
+        self.wake.lock().unwrap().send(()).ok();
+        Ok(())
+    }
+}
+
+impl<R: Reporter> Shared<R> {
+    /// Deliver pending events in order and remove the delivered ones from
+    /// the file. Returns whether the queue is empty, i.e. the reporter
+    /// didn't reject any event.
+    fn drain(&self) -> Result<bool> {
+        let _delivery = self.delivery.lock().unwrap();
+        let mut empty = true;
+        loop {
+            // not holding the state lock while the reporter is busy, so
+            // new events can still be queued
+            let (event, index) = {
+                let state = self.state.lock().unwrap();
+                match state.pending.front() {
+                    Some(event) => (event.clone(), state.removed),
+                    None => break,
+                }
+            };
+            let task = Arc::new(event.to_task_internal());
+            if deliver(&self.reporter, task, event.report_type()).is_err() {
+                empty = false;
+                break;
+            }
+            let mut state = self.state.lock().unwrap();
+            // unless the queue overflowed and dropped it in the meantime
+            if state.removed == index {
+                state.pending.pop_front();
+                state.removed += 1;
+            }
+        }
+        self.compact(&mut self.state.lock().unwrap(), false)?;
+        Ok(empty)
+    }
+}
+
+impl<R> Shared<R> {
+    /// Remove delivered and dropped events from the file once there are at
+    /// least as many of them as pending events (or any of them with `all`),
+    /// so every event is written a bounded number of times on average
+    fn compact(&self, state: &mut QueueState, all: bool) -> Result<()> {
+        let removed_events = state.file_events - state.pending.len();
+        if removed_events == 0 {
+            Ok(())
+        } else if state.pending.is_empty() {
+            // the common case of a healthy reporter
+            state
+                .file
+                .set_len(0)
+                .with_context(|| format!("can't truncate event queue {}", self.path.display()))?;
+            state.file_events = 0;
+            Ok(())
+        } else if all || removed_events >= state.pending.len() {
+            self.rewrite(state)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Replace the queue file with the events that are still pending
+    fn rewrite(&self, state: &mut QueueState) -> Result<()> {
+        let write = || -> Result<File> {
+            let mut tmp_path = self.path.clone().into_os_string();
+            tmp_path.push(".tmp");
+            let mut file = File::create(&tmp_path)?;
+            for event in &state.pending {
+                writeln!(file, "{}", serde_json::to_string(event)?)?;
+            }
+            if state.sync {
+                file.sync_data()?;
+            }
+            std::fs::rename(&tmp_path, &self.path)?;
+            Ok(OpenOptions::new().append(true).open(&self.path)?)
+        };
+        state.file = write()
+            .with_context(|| format!("can't write to event queue {}", self.path.display()))?;
+        state.file_events = state.pending.len();
+        Ok(())
+    }
+}
+
+impl<R> Drop for DurableQueue<R> {
+    fn drop(&mut self) {
+        // so events that were delivered already aren't replayed by the next
+        // process
+        let mut state = self.shared.state.lock().unwrap();
+        if let Err(err) = self.shared.compact(&mut state, true) {
+            eprintln!("[ll] {:?}", err);
+        }
+    }
+}
+
+/// Delivers queued events whenever new ones are queued. After the reporter
+/// rejects an event it backs off and ignores new events until the next
+/// retry.
+fn deliver_in_background<R: Reporter>(shared: Weak<Shared<R>>, wake: mpsc::Receiver<()>) {
+    let mut retry_delay = MIN_RETRY_DELAY;
+    let mut retry_at: Option<Instant> = None;
+    loop {
+        let woken = match retry_at {
+            Some(retry_at) => wake.recv_timeout(retry_at.saturating_duration_since(Instant::now())),
+            None => wake.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match woken {
+            Err(RecvTimeoutError::Disconnected) => return,
+            Ok(()) if retry_at.is_some_and(|retry_at| Instant::now() < retry_at) => continue,
+            Ok(()) | Err(RecvTimeoutError::Timeout) => {}
+        }
+        // all of the queued events are delivered at once
+        while wake.try_recv().is_ok() {}
+
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+        let empty = shared.drain().unwrap_or_else(|err| {
+            eprintln!("[ll] {:?}", err);
+            false
+        });
+        if empty {
+            retry_delay = MIN_RETRY_DELAY;
+            retry_at = None;
+        } else {
+            retry_at = Some(Instant::now() + retry_delay);
+            retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+}
+
+/// Events of a queue file and the number of lines in it. A line that was
+/// cut off by a crash in the middle of writing it is skipped.
+fn read_events(path: &Path) -> Result<(VecDeque<JsonlEvent>, usize)> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok((VecDeque::new(), 0)),
+        Err(err) => {
+            return Err(err).with_context(|| format!("can't open event queue {}", path.display()))
+        }
+    };
+    let mut events = VecDeque::new();
+    let mut lines = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        lines += 1;
+        if let Ok(event) = JsonlEvent::parse(&line) {
+            events.push_back(event);
+        }
+    }
+    Ok((events, lines))
+}
+
+impl<R: Reporter> Reporter for DurableQueue<R> {
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Start)
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::End)
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Stalled)
+    }
+}
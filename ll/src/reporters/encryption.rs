@@ -0,0 +1,117 @@
+/*!
+Encryption of reporter output. [EncryptedWriter] wraps the writer of a
+file-based reporter and encrypts everything written to it with AES-256-GCM,
+so logs with sensitive data can be stored on shared disks. Read them back
+with [decrypt()].
+
+```no_run
+use ll::reporters::encryption::EncryptedWriter;
+use ll::reporters::JsonlReporter;
+use std::sync::Arc;
+
+# fn main() -> anyhow::Result<()> {
+let key: [u8; 32] = *b"an example very very secret key.";
+let file = std::fs::File::create("log.jsonl.enc")?;
+ll::add_reporter(Arc::new(JsonlReporter::with_writer(Box::new(
+    EncryptedWriter::new(file, &key),
+))));
+
+// later
+let encrypted = std::fs::File::open("log.jsonl.enc")?;
+let jsonl = ll::reporters::encryption::decrypt(encrypted, &key)?;
+# Ok(())
+# }
+```
+
+The output is a sequence of frames, one per flush: the length of the rest
+of the frame (4 bytes, big endian), a random 12 byte nonce and the
+ciphertext with its authentication tag.
+*/
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{bail, Result};
+use std::io::{Read, Write};
+
+const NONCE_LEN: usize = 12;
+
+pub struct EncryptedWriter<W: Write> {
+    writer: W,
+    cipher: Aes256Gcm,
+    /// Plaintext written since the last flush
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncryptedWriter<W> {
+    pub fn new(writer: W, key: &[u8; 32]) -> Self {
+        Self {
+            writer,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+            buffer: vec![],
+        }
+    }
+
+    fn write_frame(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, self.buffer.as_slice())
+            .map_err(|_| std::io::Error::other("encryption failed"))?;
+        let len = (NONCE_LEN + ciphertext.len()) as u32;
+        self.writer.write_all(&len.to_be_bytes())?;
+        self.writer.write_all(&nonce)?;
+        self.writer.write_all(&ciphertext)?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EncryptedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    /// Encrypts everything written since the last flush as one frame
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_frame()?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for EncryptedWriter<W> {
+    fn drop(&mut self) {
+        self.flush().ok();
+    }
+}
+
+/// Decrypts the whole output of an [EncryptedWriter]. Fails if the key is
+/// wrong or any frame was modified or truncated.
+pub fn decrypt<R: Read>(mut reader: R, key: &[u8; 32]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let mut input = vec![];
+    reader.read_to_end(&mut input)?;
+
+    let mut plaintext = vec![];
+    let mut rest = input.as_slice();
+    while !rest.is_empty() {
+        if rest.len() < 4 {
+            bail!("truncated frame header");
+        }
+        let (len, frame) = rest.split_at(4);
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if len < NONCE_LEN || frame.len() < len {
+            bail!("truncated frame");
+        }
+        let (frame, next) = frame.split_at(len);
+        let (nonce, ciphertext) = frame.split_at(NONCE_LEN);
+        let decrypted = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("can't decrypt frame, wrong key or modified data"))?;
+        plaintext.extend(decrypted);
+        rest = next;
+    }
+    Ok(plaintext)
+}
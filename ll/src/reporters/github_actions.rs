@@ -0,0 +1,100 @@
+use super::text::{make_string, DurationFormat, TimestampFormat};
+use super::{Reporter, TaskReportType, DONTPRINT_TAG};
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use crate::uniq_id::UniqID;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Reporter that emits GitHub Actions workflow commands. Tasks at
+/// `group_depth` are wrapped in `::group::`/`::endgroup::` so CI logs
+/// collapse per task, and failures are annotated with `::error::`.
+/// Actions doesn't support nested groups, so only one group is open at a time
+/// and concurrent tasks at the same depth are logged without one.
+pub struct GithubActionsReporter {
+    /// Depth of tasks that open a group, where 0 is root tasks
+    pub group_depth: usize,
+    /// Also print a line for every finished task (same format as
+    /// StdioReporter)
+    pub log_task_end: bool,
+    writer: Mutex<Box<dyn Write + Send>>,
+    open_group: Mutex<Option<UniqID>>,
+}
+
+impl GithubActionsReporter {
+    pub fn new() -> Self {
+        Self::with_writer(Box::new(std::io::stdout()))
+    }
+
+    pub fn with_writer(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            group_depth: 0,
+            log_task_end: true,
+            writer: Mutex::new(writer),
+            open_group: Mutex::new(None),
+        }
+    }
+
+    fn write_lines(&self, lines: &[String]) {
+        let mut writer = self.writer.lock().unwrap();
+        for line in lines {
+            writeln!(writer, "{}", line).ok();
+        }
+        writer.flush().ok();
+    }
+}
+
+impl Reporter for GithubActionsReporter {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        if task.parent_names.len() != self.group_depth || task.tags.contains(DONTPRINT_TAG) {
+            return;
+        }
+
+        let mut open_group = self.open_group.lock().unwrap();
+        if open_group.is_none() {
+            *open_group = Some(task.id);
+            self.write_lines(&[format!("::group::{}", escape_data(&task.full_name()))]);
+        }
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        let mut lines = vec![];
+        if self.log_task_end && !task.tags.contains(DONTPRINT_TAG) {
+            lines.push(make_string(
+                &task,
+                TimestampFormat::None,
+                DurationFormat::Milliseconds,
+                TaskReportType::End,
+            ));
+        }
+
+        let mut open_group = self.open_group.lock().unwrap();
+        if *open_group == Some(task.id) {
+            *open_group = None;
+            lines.push("::endgroup::".to_string());
+        }
+        drop(open_group);
+
+        if let TaskStatus::Finished(TaskResult::Failure(msg), _) = &task.status {
+            if task.hide_errors.is_none() {
+                lines.push(format!(
+                    "::error title={}::{}",
+                    escape_property(&task.full_name()),
+                    escape_data(msg)
+                ));
+            }
+        }
+
+        self.write_lines(&lines);
+    }
+}
+
+// https://github.com/actions/toolkit/blob/main/packages/core/src/command.ts
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
@@ -0,0 +1,89 @@
+use super::Reporter;
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Aggregates finished tasks by [group](crate::TaskBuilder::group), no
+/// matter where they are in the task tree. Tasks without a group are
+/// ignored, tasks with multiple groups are counted in each of them.
+#[derive(Clone, Default)]
+pub struct GroupStatsReporter {
+    groups: Arc<Mutex<BTreeMap<String, GroupStats>>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GroupStats {
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Sum of durations of all finished tasks in the group
+    pub total_duration: Duration,
+}
+
+impl GroupStatsReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> BTreeMap<String, GroupStats> {
+        self.groups.lock().unwrap().clone()
+    }
+
+    /// Stats in the Prometheus text exposition format, e.g. to be served
+    /// from a `/metrics` endpoint.
+    pub fn to_prometheus(&self) -> String {
+        let groups = self.groups.lock().unwrap();
+        let mut result = String::new();
+        result.push_str("# TYPE ll_group_tasks_total counter\n");
+        for (group, stats) in groups.iter() {
+            let group = escape_label(group);
+            for (status, count) in [("success", stats.succeeded), ("failure", stats.failed)] {
+                writeln!(
+                    result,
+                    "ll_group_tasks_total{{group=\"{}\",status=\"{}\"}} {}",
+                    group, status, count
+                )
+                .ok();
+            }
+        }
+        result.push_str("# TYPE ll_group_task_duration_seconds_total counter\n");
+        for (group, stats) in groups.iter() {
+            writeln!(
+                result,
+                "ll_group_task_duration_seconds_total{{group=\"{}\"}} {}",
+                escape_label(group),
+                stats.total_duration.as_secs_f64()
+            )
+            .ok();
+        }
+        result
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Reporter for GroupStatsReporter {
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        let TaskStatus::Finished(result, finished_at) = &task.status else {
+            return;
+        };
+        let duration = finished_at
+            .duration_since(task.started_at)
+            .unwrap_or_default();
+        let mut groups = self.groups.lock().unwrap();
+        for group in task.groups() {
+            let stats = groups.entry(group.to_string()).or_default();
+            match result {
+                TaskResult::Success => stats.succeeded += 1,
+                TaskResult::Failure(_) => stats.failed += 1,
+            }
+            stats.total_duration += duration;
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use super::{Reporter, TaskReportType};
+use crate::data::{Data, DataChange, DataEntry, DataValue};
+use crate::output_schema::{check_schema_version, unversioned, SCHEMA_VERSION};
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use crate::uniq_id::UniqID;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Reporter that writes every report as a single line of JSON
+/// (see [JsonlEvent]). The output can be read back with
+/// [JsonlEvent::parse] or viewed with the `ll-tail` binary.
+pub struct JsonlReporter {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+/// One line of [JsonlReporter] output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonlEvent {
+    /// see [output_schema](crate::output_schema)
+    #[serde(default = "unversioned")]
+    pub schema_version: u32,
+    /// see [TaskInternal::report_seq()]
+    #[serde(default)]
+    pub seq: u64,
+    pub event: JsonlEventType,
+    pub id: u64,
+    pub parent_id: Option<u64>,
+    pub name: String,
+    pub parent_names: Vec<String>,
+    pub tags: Vec<String>,
+    /// Data keys are written with their tags appended (`key#dontprint`), the
+    /// same way they are passed to [Task::data()](crate::Task::data)
+    pub data: BTreeMap<String, DataValue>,
+    pub data_transitive: BTreeMap<String, DataValue>,
+    /// Milliseconds since the unix epoch
+    pub started_at_ms: u64,
+    pub finished_at_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<(i64, i64)>,
+    /// Exact progress, if it was reported with
+    /// [Task::progress_f64()](crate::Task::progress_f64)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress_f64: Option<(f64, f64)>,
+    /// see [Data::timeline]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_timeline: Vec<JsonlDataChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonlDataChange {
+    pub key: String,
+    pub value: DataValue,
+    /// Milliseconds since the unix epoch
+    pub at_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonlEventType {
+    Start,
+    Stalled,
+    End,
+}
+
+impl JsonlReporter {
+    pub fn new() -> Self {
+        Self::with_writer(Box::new(std::io::stdout()))
+    }
+
+    pub fn with_writer(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    fn write_event(&self, task: &TaskInternal, report_type: TaskReportType) -> Result<()> {
+        let line = serde_json::to_string(&JsonlEvent::new(task, report_type))?;
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{}", line)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Reporter for JsonlReporter {
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.write_event(&task, TaskReportType::Start)
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.write_event(&task, TaskReportType::End)
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.write_event(&task, TaskReportType::Stalled)
+    }
+}
+
+impl JsonlEvent {
+    pub fn new(task: &TaskInternal, report_type: TaskReportType) -> Self {
+        let (finished_at_ms, error) = match &task.status {
+            TaskStatus::Running | TaskStatus::Paused(_) | TaskStatus::Scheduled(_) => (None, None),
+            TaskStatus::Finished(result, finished_at) => {
+                let error = match result {
+                    TaskResult::Success => None,
+                    TaskResult::Failure(error) => Some(error.clone()),
+                };
+                (Some(to_ms(*finished_at)), error)
+            }
+        };
+
+        Self {
+            schema_version: SCHEMA_VERSION,
+            seq: task.report_seq,
+            event: match report_type {
+                TaskReportType::Start => JsonlEventType::Start,
+                TaskReportType::Stalled => JsonlEventType::Stalled,
+                TaskReportType::End => JsonlEventType::End,
+            },
+            id: task.id.as_u64(),
+            parent_id: task.parent_id.map(|id| id.as_u64()),
+            name: task.name.clone(),
+            parent_names: task.parent_names.clone(),
+            tags: task.tags.iter().cloned().collect(),
+            data: data_to_map(&task.data),
+            data_transitive: data_to_map(&task.data_transitive),
+            started_at_ms: to_ms(task.started_at),
+            finished_at_ms,
+            error,
+            progress: task.progress,
+            progress_f64: task.progress_f64,
+            data_timeline: task
+                .data
+                .timeline
+                .iter()
+                .chain(&task.data_transitive.timeline)
+                .map(|change| JsonlDataChange {
+                    key: change.key.clone(),
+                    value: change.value.clone(),
+                    at_ms: to_ms(change.at),
+                })
+                .collect(),
+        }
+    }
+
+    /// Parse a single line of [JsonlReporter] output. Fails for output of a
+    /// newer [schema version](crate::output_schema).
+    pub fn parse(line: &str) -> Result<Self> {
+        let event: Self = serde_json::from_str(line)?;
+        check_schema_version(event.schema_version)?;
+        Ok(event)
+    }
+
+    pub fn report_type(&self) -> TaskReportType {
+        match self.event {
+            JsonlEventType::Start => TaskReportType::Start,
+            JsonlEventType::Stalled => TaskReportType::Stalled,
+            JsonlEventType::End => TaskReportType::End,
+        }
+    }
+
+    /// Rebuild the reported task, so it can be passed to any other reporter
+    pub fn to_task_internal(&self) -> TaskInternal {
+        let status = match (self.finished_at_ms, &self.error) {
+            (Some(finished_at), Some(error)) => {
+                TaskStatus::Finished(TaskResult::Failure(error.clone()), from_ms(finished_at))
+            }
+            (Some(finished_at), None) => {
+                TaskStatus::Finished(TaskResult::Success, from_ms(finished_at))
+            }
+            (None, _) => TaskStatus::Running,
+        };
+
+        let mut data = map_to_data(&self.data);
+        data.timeline = self
+            .data_timeline
+            .iter()
+            .map(|change| DataChange {
+                key: change.key.clone(),
+                value: change.value.clone(),
+                at: from_ms(change.at_ms),
+            })
+            .collect();
+
+        TaskInternal {
+            id: UniqID::from_u64(self.id),
+            name: self.name.clone(),
+            parent_id: self.parent_id.map(UniqID::from_u64),
+            parent_names: self.parent_names.clone(),
+            started_at: from_ms(self.started_at_ms),
+            status,
+            data,
+            data_transitive: map_to_data(&self.data_transitive),
+            tags: self.tags.iter().cloned().collect(),
+            progress: self.progress,
+            hide_errors: None,
+            attach_transitive_data_to_errors: false,
+            stalled: self.event == JsonlEventType::Stalled,
+            children_progress: None,
+            cleared_data_transitive: Default::default(),
+            waits_on: vec![],
+            concurrency_limit: None,
+            fail_fast: None,
+            paused_time: Default::default(),
+            paused_at: None,
+            child_count: 0,
+            report_seq: self.seq,
+            full_name_format: Default::default(),
+            progress_f64: self.progress_f64,
+            progress_format: None,
+            infallible: false,
+        }
+    }
+}
+
+fn data_to_map(data: &Data) -> BTreeMap<String, DataValue> {
+    data.map
+        .iter()
+        .map(|(key, DataEntry(value, tags))| {
+            let mut key = key.clone();
+            for tag in tags {
+                key.push('#');
+                key.push_str(tag);
+            }
+            (key, value.clone())
+        })
+        .collect()
+}
+
+fn map_to_data(map: &BTreeMap<String, DataValue>) -> Data {
+    let mut data = Data::empty();
+    for (key, value) in map {
+        data.add(key.as_str(), value.clone());
+    }
+    data
+}
+
+fn to_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn from_ms(ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms)
+}
@@ -0,0 +1,112 @@
+use super::Reporter;
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Collects finished tasks as JUnit test cases, so ll-driven test runners can
+/// be consumed by CI test report ingestion. Reports are accumulated in memory
+/// and written with [write()](JUnitReporter::write), usually at the end of
+/// the process.
+#[derive(Clone)]
+pub struct JUnitReporter {
+    /// Name of the `<testsuite>` element
+    pub suite_name: String,
+    /// If set, only tasks with this tag (e.g. `test` for `my_test #test`)
+    /// are exported
+    pub tag_filter: Option<String>,
+    test_cases: Arc<Mutex<Vec<TestCase>>>,
+}
+
+struct TestCase {
+    classname: String,
+    name: String,
+    duration: Duration,
+    failure: Option<String>,
+}
+
+impl JUnitReporter {
+    pub fn new<S: Into<String>>(suite_name: S) -> Self {
+        Self {
+            suite_name: suite_name.into(),
+            tag_filter: None,
+            test_cases: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    pub fn to_xml(&self) -> String {
+        let test_cases = self.test_cases.lock().unwrap();
+        let failures = test_cases.iter().filter(|t| t.failure.is_some()).count();
+        let total_time: Duration = test_cases.iter().map(|t| t.duration).sum();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites>\n  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+            escape(&self.suite_name),
+            test_cases.len(),
+            failures,
+            total_time.as_secs_f64(),
+        ));
+        for test_case in test_cases.iter() {
+            xml.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                escape(&test_case.classname),
+                escape(&test_case.name),
+                test_case.duration.as_secs_f64(),
+            ));
+            match &test_case.failure {
+                Some(msg) => {
+                    let first_line = msg.lines().next().unwrap_or_default();
+                    xml.push_str(&format!(
+                        ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+                        escape(first_line),
+                        escape(msg)
+                    ));
+                }
+                None => xml.push_str(" />\n"),
+            }
+        }
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_xml())
+            .with_context(|| format!("Failed to write JUnit report to {}", path.display()))
+    }
+}
+
+impl Reporter for JUnitReporter {
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        if let Some(tag) = &self.tag_filter {
+            if !task.tags.contains(tag) {
+                return;
+            }
+        }
+
+        if let TaskStatus::Finished(result, finished_at) = &task.status {
+            let failure = match result {
+                TaskResult::Success => None,
+                TaskResult::Failure(msg) => Some(msg.clone()),
+            };
+            self.test_cases.lock().unwrap().push(TestCase {
+                classname: task.parent_names.join(":"),
+                name: task.name.clone(),
+                duration: finished_at
+                    .duration_since(task.started_at)
+                    .unwrap_or_default(),
+                failure,
+            });
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -1,18 +1,38 @@
-use std::default::Default;
-
 /// Logging levers, by default all tasks log as L1, but can be changed to
 /// l0, l2, l3 by using #l0 #l2 #l3 tags in the task name.
 /// Reporters can be set to ignore anything up from a certain level.
-#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Default)]
 pub enum Level {
     L0,
+    #[default]
     L1,
     L2,
     L3,
 }
 
-impl Default for Level {
-    fn default() -> Self {
-        Self::L1
+impl Level {
+    /// Tag that sets this level when used in a task name, e.g. `#l2`
+    pub fn as_tag(&self) -> &'static str {
+        match self {
+            Level::L0 => "l0",
+            Level::L1 => "l1",
+            Level::L2 => "l2",
+            Level::L3 => "l3",
+        }
+    }
+}
+
+impl std::str::FromStr for Level {
+    type Err = anyhow::Error;
+
+    /// Parses `l0`..`l3` (case insensitive)
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "l0" => Ok(Level::L0),
+            "l1" => Ok(Level::L1),
+            "l2" => Ok(Level::L2),
+            "l3" => Ok(Level::L3),
+            _ => anyhow::bail!("invalid log level `{}`, expected one of l0, l1, l2, l3", s),
+        }
     }
 }
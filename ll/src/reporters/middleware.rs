@@ -0,0 +1,347 @@
+use super::durable_queue::DurableQueue;
+use super::{Reporter, TaskReportType};
+use crate::task_tree::TaskInternal;
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Combinators that wrap an existing reporter and transform or drop tasks
+/// before they are delivered to it. Since every combinator wraps the reporter
+/// built so far, the last one in the chain sees reports first, e.g. this
+/// reporter only prints failures, at most 10 per second:
+///
+/// ```
+/// use ll::reporters::{ReporterExt, StdioReporter};
+/// use ll::task_tree::{TaskResult, TaskStatus};
+///
+/// let failures_only = StdioReporter::new()
+///     .rate_limit(10)
+///     .filter(|task, _| matches!(task.status(), TaskStatus::Finished(TaskResult::Failure(_), _)));
+/// ```
+pub trait ReporterExt: Reporter + Sized {
+    fn map<F>(self, f: F) -> Map<Self, F>
+    where
+        F: Fn(Arc<TaskInternal>) -> Arc<TaskInternal> + Send + Sync,
+    {
+        Map { reporter: self, f }
+    }
+
+    fn filter<F>(self, f: F) -> Filter<Self, F>
+    where
+        F: Fn(&TaskInternal, TaskReportType) -> bool + Send + Sync,
+    {
+        Filter { reporter: self, f }
+    }
+
+    /// Deliver at most `max_per_second` reports, everything above that
+    /// within the same second is dropped.
+    fn rate_limit(self, max_per_second: u32) -> RateLimit<Self> {
+        RateLimit {
+            reporter: self,
+            max_per_second,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Protects the reporting thread from a reporter that blocks (e.g. a
+    /// dead network sink). Reports are delivered from a separate thread and
+    /// waited on for at most `timeout`. After `max_timeouts` timeouts in a
+    /// row the reporter is skipped for `cooldown`. Reports skipped because
+    /// the reporter is still stuck in an earlier one count as timeouts.
+    /// see [CircuitBreaker]
+    fn circuit_breaker(
+        self,
+        timeout: Duration,
+        max_timeouts: u32,
+        cooldown: Duration,
+    ) -> CircuitBreaker<Self>
+    where
+        Self: 'static,
+    {
+        CircuitBreaker::new(self, timeout, max_timeouts, cooldown)
+    }
+
+    /// Persist every report to a local file until the reporter accepts it,
+    /// so reports to a network sink survive outages and crashes. Reports
+    /// queued by a previous run are replayed in the background right away.
+    /// see [DurableQueue]
+    fn durable_queue<P: Into<PathBuf>>(self, path: P) -> Result<DurableQueue<Self>>
+    where
+        Self: 'static,
+    {
+        DurableQueue::open(self, path.into())
+    }
+}
+
+impl<R: Reporter> ReporterExt for R {}
+
+impl<R: Reporter + ?Sized> Reporter for Arc<R> {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        (**self).task_start(task)
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        (**self).task_end(task)
+    }
+
+    fn task_stalled(&self, task: Arc<TaskInternal>) {
+        (**self).task_stalled(task)
+    }
+
+    fn task_progress(&self, task: Arc<TaskInternal>) {
+        (**self).task_progress(task)
+    }
+
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        (**self).try_task_start(task)
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        (**self).try_task_end(task)
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        (**self).try_task_stalled(task)
+    }
+}
+
+pub(super) fn deliver<R: Reporter>(
+    reporter: &R,
+    task: Arc<TaskInternal>,
+    report_type: TaskReportType,
+) -> Result<()> {
+    match report_type {
+        TaskReportType::Start => reporter.try_task_start(task),
+        TaskReportType::Stalled => reporter.try_task_stalled(task),
+        TaskReportType::End => reporter.try_task_end(task),
+    }
+}
+
+pub struct Map<R, F> {
+    reporter: R,
+    f: F,
+}
+
+impl<R: Reporter, F: Fn(Arc<TaskInternal>) -> Arc<TaskInternal>> Map<R, F> {
+    fn report(&self, task: Arc<TaskInternal>, report_type: TaskReportType) -> Result<()> {
+        deliver(&self.reporter, (self.f)(task), report_type)
+    }
+}
+
+impl<R, F> Reporter for Map<R, F>
+where
+    R: Reporter,
+    F: Fn(Arc<TaskInternal>) -> Arc<TaskInternal> + Send + Sync,
+{
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Start)
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::End)
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Stalled)
+    }
+}
+
+pub struct Filter<R, F> {
+    reporter: R,
+    f: F,
+}
+
+impl<R: Reporter, F: Fn(&TaskInternal, TaskReportType) -> bool> Filter<R, F> {
+    fn report(&self, task: Arc<TaskInternal>, report_type: TaskReportType) -> Result<()> {
+        if (self.f)(&task, report_type) {
+            deliver(&self.reporter, task, report_type)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<R, F> Reporter for Filter<R, F>
+where
+    R: Reporter,
+    F: Fn(&TaskInternal, TaskReportType) -> bool + Send + Sync,
+{
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Start)
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::End)
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Stalled)
+    }
+}
+
+pub struct RateLimit<R> {
+    reporter: R,
+    max_per_second: u32,
+    /// Start of the current one second window and how many reports were
+    /// delivered within it.
+    window: Mutex<(Instant, u32)>,
+}
+
+impl<R: Reporter> RateLimit<R> {
+    fn report(&self, task: Arc<TaskInternal>, report_type: TaskReportType) -> Result<()> {
+        {
+            let mut window = self.window.lock().unwrap();
+            if window.0.elapsed() >= Duration::from_secs(1) {
+                *window = (Instant::now(), 0);
+            }
+            if window.1 >= self.max_per_second {
+                return Ok(());
+            }
+            window.1 += 1;
+        }
+        deliver(&self.reporter, task, report_type)
+    }
+}
+
+impl<R: Reporter> Reporter for RateLimit<R> {
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Start)
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::End)
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Stalled)
+    }
+}
+
+type DeliveryJob = (Arc<TaskInternal>, TaskReportType, mpsc::Sender<Result<()>>);
+
+/// see [ReporterExt::circuit_breaker()]. Keep an `Arc` of it around to
+/// check how many reports were [skipped()](CircuitBreaker::skipped).
+pub struct CircuitBreaker<R> {
+    sender: Mutex<mpsc::Sender<DeliveryJob>>,
+    /// Set while the delivery thread is busy with a report
+    busy: Arc<AtomicBool>,
+    timeout: Duration,
+    max_timeouts: u32,
+    cooldown: Duration,
+    /// Timeouts in a row and, if the circuit is open, until when
+    state: Mutex<(u32, Option<Instant>)>,
+    skipped: AtomicU64,
+    _reporter: std::marker::PhantomData<R>,
+}
+
+impl<R: Reporter + 'static> CircuitBreaker<R> {
+    fn new(reporter: R, timeout: Duration, max_timeouts: u32, cooldown: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel::<DeliveryJob>();
+        let busy = Arc::new(AtomicBool::new(false));
+        let busy_clone = busy.clone();
+        // exits when the circuit breaker (and with it the sender) is dropped
+        std::thread::spawn(move || {
+            for (task, report_type, result_sender) in receiver {
+                let result = deliver(&reporter, task, report_type);
+                // before the result is sent, otherwise the next report could
+                // still find the thread busy and count it as a timeout
+                busy_clone.store(false, Ordering::SeqCst);
+                result_sender.send(result).ok();
+            }
+        });
+        Self {
+            sender: Mutex::new(sender),
+            busy,
+            timeout,
+            max_timeouts,
+            cooldown,
+            state: Mutex::new((0, None)),
+            skipped: AtomicU64::new(0),
+            _reporter: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<R> CircuitBreaker<R> {
+    /// Number of reports that were dropped because the circuit was open or
+    /// the reporter was still busy with a previous report
+    pub fn skipped(&self) -> u64 {
+        self.skipped.load(Ordering::SeqCst)
+    }
+
+    pub fn is_open(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .1
+            .is_some_and(|open_until| Instant::now() < open_until)
+    }
+
+    fn report(&self, task: Arc<TaskInternal>, report_type: TaskReportType) -> Result<()> {
+        if self.is_open() {
+            self.skipped.fetch_add(1, Ordering::SeqCst);
+            return Ok(());
+        }
+        if self.busy.swap(true, Ordering::SeqCst) {
+            // still stuck in a report that already timed out. Counts as
+            // another timeout, otherwise a reporter that never returns
+            // would never open the circuit
+            self.skipped.fetch_add(1, Ordering::SeqCst);
+            self.timed_out(&mut self.state.lock().unwrap());
+            return Ok(());
+        }
+
+        let (result_sender, result_receiver) = mpsc::channel();
+        let sent = self
+            .sender
+            .lock()
+            .unwrap()
+            .send((task, report_type, result_sender));
+        if sent.is_err() {
+            // the delivery thread is gone (the reporter panicked)
+            self.skipped.fetch_add(1, Ordering::SeqCst);
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        match result_receiver.recv_timeout(self.timeout) {
+            Ok(result) => {
+                *state = (0, None);
+                result
+            }
+            Err(_) => {
+                self.timed_out(&mut state);
+                Ok(())
+            }
+        }
+    }
+
+    fn timed_out(&self, state: &mut (u32, Option<Instant>)) {
+        state.0 += 1;
+        if state.0 >= self.max_timeouts {
+            // after the cooldown one report is let through, and a single
+            // timeout opens the circuit again
+            state.1 = Some(Instant::now() + self.cooldown);
+            eprintln!(
+                "[ll] reporter timed out {} times in a row, skipping it for {:?}",
+                state.0, self.cooldown
+            );
+        }
+    }
+}
+
+impl<R: Send + Sync> Reporter for CircuitBreaker<R> {
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Start)
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::End)
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.report(task, TaskReportType::Stalled)
+    }
+}
@@ -1,20 +1,105 @@
+pub mod async_reporter;
+#[cfg(feature = "audit")]
+pub mod audit;
+pub mod buildkite;
+pub mod capture;
+pub mod channel;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod durable_queue;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod github_actions;
+pub mod group_stats;
+pub mod jsonl;
+pub mod junit;
 pub mod level;
+pub mod middleware;
+pub mod null;
+pub mod periodic_summary;
+pub mod ring_buffer;
+pub mod tap;
+pub mod teamcity;
+#[cfg(feature = "term-status")]
 pub mod term_status;
 pub mod text;
 pub mod utils;
 
+pub use async_reporter::AsyncReporter;
+#[cfg(feature = "audit")]
+pub use audit::AuditReporter;
+pub use buildkite::BuildkiteReporter;
+pub use capture::CaptureReporter;
+pub use channel::ChannelReporter;
+pub use durable_queue::DurableQueue;
+pub use github_actions::GithubActionsReporter;
+pub use group_stats::GroupStatsReporter;
+pub use jsonl::JsonlReporter;
+pub use junit::JUnitReporter;
 pub use level::Level;
+pub use middleware::ReporterExt;
+pub use null::NullReporter;
+pub use periodic_summary::PeriodicSummaryReporter;
+pub use ring_buffer::RingBufferReporter;
+pub use tap::TapReporter;
+pub use teamcity::TeamCityReporter;
+#[cfg(feature = "term-status")]
 pub use term_status::TermStatus;
 pub use text::StdioReporter;
 pub use text::StringReporter;
 
 pub const DONTPRINT_TAG: &str = "dontprint";
+/// Tasks with this tag are not shown in the terminal status
+pub(crate) const NOSTATUS_TAG: &str = "nostatus";
 
 use crate::task_tree::TaskInternal;
+use crate::uniq_id::UniqID;
+use anyhow::Result;
 use std::sync::Arc;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskReportType {
+    Start,
+    Stalled,
+    End,
+}
+
+/// Identifies a reporter added to a task tree.
+/// see [remove_reporter()](crate::task_tree::TaskTree::remove_reporter)
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReporterHandle(pub(crate) UniqID);
+
 pub trait Reporter: Send + Sync {
     fn task_start(&self, _task: Arc<TaskInternal>) {}
     fn task_end(&self, _task: Arc<TaskInternal>) {}
+    /// Called once when a task has been running for longer than the stall
+    /// threshold configured on the task tree.
+    fn task_stalled(&self, _task: Arc<TaskInternal>) {}
     fn task_progress(&self, _task: Arc<TaskInternal>) {}
+
+    /// Fallible versions of the methods above. These are the ones `TaskTree`
+    /// actually calls. Reporters that write to files, network, etc. can
+    /// implement them instead to return errors. Failed deliveries are retried
+    /// and eventually sent to the dead letter handler.
+    /// see [set_retry_policy()](crate::task_tree::TaskTree::set_retry_policy)
+    fn try_task_start(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.task_start(task);
+        Ok(())
+    }
+
+    fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.task_end(task);
+        Ok(())
+    }
+
+    fn try_task_stalled(&self, task: Arc<TaskInternal>) -> Result<()> {
+        self.task_stalled(task);
+        Ok(())
+    }
+}
+
+/// Receives task reports that could not be delivered to a reporter after
+/// all retries were exhausted.
+pub trait DeadLetterHandler: Send + Sync {
+    fn dead_letter(&self, task: Arc<TaskInternal>, report_type: TaskReportType, err: anyhow::Error);
 }
@@ -0,0 +1,77 @@
+use super::Reporter;
+use crate::task_tree::{TaskInternal, TaskStatus};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Reporter that discards all events but counts how many of them were
+/// delivered and how long it took for them to get delivered since they
+/// happened. Useful for benchmarking ll's own overhead and for asserting how
+/// many tasks were reported in tests.
+#[derive(Default)]
+pub struct NullReporter {
+    starts: AtomicU64,
+    ends: AtomicU64,
+    stalled: AtomicU64,
+    total_latency_us: AtomicU64,
+    max_latency_us: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NullReporterStats {
+    pub starts: u64,
+    pub ends: u64,
+    pub stalled: u64,
+    /// Average time between a task starting/finishing and the corresponding
+    /// report being delivered
+    pub mean_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl NullReporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> NullReporterStats {
+        let starts = self.starts.load(Ordering::SeqCst);
+        let ends = self.ends.load(Ordering::SeqCst);
+        let total_latency_us = self.total_latency_us.load(Ordering::SeqCst);
+        let mean_latency_us = match starts + ends {
+            0 => 0,
+            n => total_latency_us / n,
+        };
+        NullReporterStats {
+            starts,
+            ends,
+            stalled: self.stalled.load(Ordering::SeqCst),
+            mean_latency: Duration::from_micros(mean_latency_us),
+            max_latency: Duration::from_micros(self.max_latency_us.load(Ordering::SeqCst)),
+        }
+    }
+
+    fn record_latency(&self, since: SystemTime) {
+        let latency_us = since.elapsed().unwrap_or_default().as_micros() as u64;
+        self.total_latency_us
+            .fetch_add(latency_us, Ordering::SeqCst);
+        self.max_latency_us.fetch_max(latency_us, Ordering::SeqCst);
+    }
+}
+
+impl Reporter for NullReporter {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        self.starts.fetch_add(1, Ordering::SeqCst);
+        self.record_latency(task.started_at);
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        self.ends.fetch_add(1, Ordering::SeqCst);
+        if let TaskStatus::Finished(_, finished_at) = task.status {
+            self.record_latency(finished_at);
+        }
+    }
+
+    fn task_stalled(&self, _task: Arc<TaskInternal>) {
+        self.stalled.fetch_add(1, Ordering::SeqCst);
+    }
+}
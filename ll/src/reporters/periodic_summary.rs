@@ -0,0 +1,193 @@
+use super::Reporter;
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use crate::uniq_id::UniqID;
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How many of the slowest tasks are listed in a summary by default
+pub const DEFAULT_SLOWEST_COUNT: usize = 5;
+
+/// Instead of a line per task, writes a summary of everything that happened
+/// since the previous one every `interval`, which is the right verbosity for
+/// long running services:
+///
+/// ```text
+/// [ll] last 300s: 1204 started, 1198 finished (3 failed), 42 running
+/// [ll]   slowest: root:request 2.3s, root:request:db_query 1.9s
+/// ```
+///
+/// Summaries are written from a background thread that stops when the
+/// reporter is dropped.
+pub struct PeriodicSummaryReporter {
+    state: Arc<Mutex<SummaryState>>,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    interval: Duration,
+    stopped: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// Length of the period the summary covers
+    pub period: Duration,
+    pub started: u64,
+    pub finished: u64,
+    pub failed: u64,
+    /// Tasks that are still running at the end of the period, including
+    /// ones started in earlier periods
+    pub running: u64,
+    /// Full names and durations of the slowest tasks that finished in the
+    /// period, slowest first
+    pub slowest: Vec<(String, Duration)>,
+}
+
+#[derive(Default)]
+struct SummaryState {
+    started: u64,
+    finished: u64,
+    failed: u64,
+    running: HashSet<UniqID>,
+    slowest: Vec<(String, Duration)>,
+    slowest_count: usize,
+}
+
+impl PeriodicSummaryReporter {
+    /// Writes summaries to STDERR
+    pub fn new(interval: Duration) -> Self {
+        Self::with_writer(interval, Box::new(std::io::stderr()))
+    }
+
+    pub fn with_writer(interval: Duration, writer: Box<dyn Write + Send>) -> Self {
+        let reporter = Self {
+            state: Arc::new(Mutex::new(SummaryState {
+                slowest_count: DEFAULT_SLOWEST_COUNT,
+                ..SummaryState::default()
+            })),
+            writer: Arc::new(Mutex::new(writer)),
+            interval,
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+
+        let state = reporter.state.clone();
+        let writer = reporter.writer.clone();
+        let stopped = reporter.stopped.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let summary = take_summary(&state, interval);
+            write_summary(&writer, &summary);
+        });
+        reporter
+    }
+
+    /// How many of the slowest tasks to list, [DEFAULT_SLOWEST_COUNT] by
+    /// default
+    pub fn slowest_count(self, count: usize) -> Self {
+        self.state.lock().unwrap().slowest_count = count;
+        self
+    }
+
+    /// Stats of the current period so far
+    pub fn summary(&self) -> Summary {
+        self.state.lock().unwrap().summary(self.interval)
+    }
+
+    /// Write the summary of the current period right away and start a new
+    /// one, e.g. before the process exits
+    pub fn write_now(&self) {
+        let summary = take_summary(&self.state, self.interval);
+        write_summary(&self.writer, &summary);
+    }
+}
+
+impl Drop for PeriodicSummaryReporter {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl SummaryState {
+    fn summary(&self, period: Duration) -> Summary {
+        Summary {
+            period,
+            started: self.started,
+            finished: self.finished,
+            failed: self.failed,
+            running: self.running.len() as u64,
+            slowest: self.slowest.clone(),
+        }
+    }
+
+    fn record_slow(&mut self, name: String, duration: Duration) {
+        let i = self.slowest.partition_point(|(_, d)| *d >= duration);
+        if i < self.slowest_count {
+            self.slowest.insert(i, (name, duration));
+            self.slowest.truncate(self.slowest_count);
+        }
+    }
+}
+
+fn take_summary(state: &Mutex<SummaryState>, period: Duration) -> Summary {
+    let mut state = state.lock().unwrap();
+    let summary = state.summary(period);
+    state.started = 0;
+    state.finished = 0;
+    state.failed = 0;
+    state.slowest.clear();
+    summary
+}
+
+fn write_summary(writer: &Mutex<Box<dyn Write + Send>>, summary: &Summary) {
+    let mut writer = writer.lock().unwrap();
+    // there's nowhere to report a failure to write the report
+    write!(writer, "{}", summary).ok();
+    writer.flush().ok();
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "[ll] last {:?}: {} started, {} finished ({} failed), {} running",
+            self.period, self.started, self.finished, self.failed, self.running
+        )?;
+        if !self.slowest.is_empty() {
+            let slowest = self
+                .slowest
+                .iter()
+                .map(|(name, duration)| format!("{} {:.1}s", name, duration.as_secs_f64()))
+                .collect::<Vec<_>>();
+            writeln!(f, "[ll]   slowest: {}", slowest.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Reporter for PeriodicSummaryReporter {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        let mut state = self.state.lock().unwrap();
+        state.started += 1;
+        state.running.insert(task.id);
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        let TaskStatus::Finished(result, finished_at) = &task.status else {
+            return;
+        };
+        let duration = finished_at
+            .duration_since(task.started_at)
+            .unwrap_or_default();
+        let mut state = self.state.lock().unwrap();
+        state.finished += 1;
+        if let TaskResult::Failure(_) = result {
+            state.failed += 1;
+        }
+        state.running.remove(&task.id);
+        state.record_slow(task.full_name(), duration);
+    }
+}
@@ -0,0 +1,83 @@
+use super::text::{make_string, strip_ansi, DurationFormat, TimestampFormat};
+use super::{Reporter, TaskReportType, DONTPRINT_TAG};
+use crate::task_tree::TaskInternal;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Keeps the last `capacity` lines of text output in memory, evicting the
+/// oldest ones. Useful for long running processes that want to dump recent
+/// logs on crash without keeping the whole history.
+#[derive(Clone)]
+pub struct RingBufferReporter {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+    pub timestamp_format: TimestampFormat,
+    pub log_task_start: bool,
+}
+
+impl RingBufferReporter {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            timestamp_format: TimestampFormat::UTC,
+            log_task_start: false,
+        }
+    }
+
+    /// Last `n` lines, oldest first
+    pub fn tail(&self, n: usize) -> Vec<String> {
+        let lines = self.lines.lock().unwrap();
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+
+    fn report(&self, task_internal: Arc<TaskInternal>, report_type: TaskReportType) {
+        if task_internal.tags.contains(DONTPRINT_TAG)
+            || super::utils::is_collapsed_occurrence(&task_internal, report_type)
+        {
+            return;
+        }
+        let result = strip_ansi(&make_string(
+            &task_internal,
+            self.timestamp_format,
+            DurationFormat::Milliseconds,
+            report_type,
+        ));
+        let mut lines = self.lines.lock().unwrap();
+        for line in result.lines() {
+            if lines.len() >= self.capacity {
+                lines.pop_front();
+            }
+            lines.push_back(line.to_string());
+        }
+    }
+}
+
+impl Reporter for RingBufferReporter {
+    fn task_start(&self, task_internal: Arc<TaskInternal>) {
+        if self.log_task_start {
+            self.report(task_internal, TaskReportType::Start);
+        }
+    }
+
+    fn task_end(&self, task_internal: Arc<TaskInternal>) {
+        self.report(task_internal, TaskReportType::End);
+    }
+
+    fn task_stalled(&self, task_internal: Arc<TaskInternal>) {
+        self.report(task_internal, TaskReportType::Stalled);
+    }
+}
+
+impl std::fmt::Display for RingBufferReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in self.lines.lock().unwrap().iter() {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
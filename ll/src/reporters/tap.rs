@@ -0,0 +1,77 @@
+use super::{Reporter, DONTPRINT_TAG};
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Reporter that prints finished tasks as Test Anything Protocol (TAP 13)
+/// test points. Error messages of failed tasks are added as YAML
+/// diagnostics. Since the number of tasks isn't known upfront, the plan line
+/// is printed at the end by [finish()](TapReporter::finish).
+pub struct TapReporter {
+    /// If set, only tasks with this tag (e.g. `test` for `my_check #test`)
+    /// are reported
+    pub tag_filter: Option<String>,
+    writer: Mutex<Box<dyn Write + Send>>,
+    /// Number of the last reported test point
+    count: Mutex<usize>,
+}
+
+impl TapReporter {
+    pub fn new() -> Self {
+        Self::with_writer(Box::new(std::io::stdout()))
+    }
+
+    pub fn with_writer(mut writer: Box<dyn Write + Send>) -> Self {
+        writeln!(writer, "TAP version 13").ok();
+        Self {
+            tag_filter: None,
+            writer: Mutex::new(writer),
+            count: Mutex::new(0),
+        }
+    }
+
+    /// Print the plan line. Should be called once all tasks are reported.
+    pub fn finish(&self) {
+        let count = self.count.lock().unwrap();
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "1..{}", count).ok();
+        writer.flush().ok();
+    }
+}
+
+impl Reporter for TapReporter {
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        if task.tags.contains(DONTPRINT_TAG) {
+            return;
+        }
+        if let Some(tag) = &self.tag_filter {
+            if !task.tags.contains(tag) {
+                return;
+            }
+        }
+
+        let mut count = self.count.lock().unwrap();
+        *count += 1;
+
+        // `#` starts a directive in TAP, so it can't be in the description
+        let description = task.full_name().replace('#', "\\#");
+        let result = match &task.status {
+            TaskStatus::Finished(TaskResult::Failure(msg), _) => {
+                let mut result = format!("not ok {} - {}\n", count, description);
+                if task.hide_errors.is_none() {
+                    result.push_str("  ---\n  message: |\n");
+                    for line in msg.lines() {
+                        result.push_str(&format!("    {}\n", line));
+                    }
+                    result.push_str("  ...\n");
+                }
+                result
+            }
+            _ => format!("ok {} - {}\n", count, description),
+        };
+
+        let mut writer = self.writer.lock().unwrap();
+        write!(writer, "{}", result).ok();
+        writer.flush().ok();
+    }
+}
@@ -0,0 +1,86 @@
+use super::{Reporter, DONTPRINT_TAG};
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Reporter that emits TeamCity service messages. Every task becomes a block
+/// in the build log, `flowId` is the task id so blocks of concurrent tasks
+/// are not mixed up. Failures are reported as error messages.
+pub struct TeamCityReporter {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl TeamCityReporter {
+    pub fn new() -> Self {
+        Self::with_writer(Box::new(std::io::stdout()))
+    }
+
+    pub fn with_writer(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    fn service_message(&self, name: &str, attrs: &[(&str, String)]) {
+        let mut msg = format!("##teamcity[{}", name);
+        for (k, v) in attrs {
+            msg.push_str(&format!(" {}='{}'", k, escape(v)));
+        }
+        msg.push(']');
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{}", msg).ok();
+        writer.flush().ok();
+    }
+}
+
+impl Reporter for TeamCityReporter {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        if task.tags.contains(DONTPRINT_TAG) {
+            return;
+        }
+        self.service_message(
+            "blockOpened",
+            &[("name", task.full_name()), ("flowId", task.id.to_string())],
+        );
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        if task.tags.contains(DONTPRINT_TAG) {
+            return;
+        }
+        if let TaskStatus::Finished(TaskResult::Failure(msg), _) = &task.status {
+            if task.hide_errors.is_none() {
+                self.service_message(
+                    "message",
+                    &[
+                        ("text", format!("{} failed", task.full_name())),
+                        ("errorDetails", msg.clone()),
+                        ("status", "ERROR".to_string()),
+                        ("flowId", task.id.to_string()),
+                    ],
+                );
+            }
+        }
+        self.service_message(
+            "blockClosed",
+            &[("name", task.full_name()), ("flowId", task.id.to_string())],
+        );
+    }
+}
+
+// https://www.jetbrains.com/help/teamcity/service-messages.html#Escaped+Values
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '|' => result.push_str("||"),
+            '\'' => result.push_str("|'"),
+            '\n' => result.push_str("|n"),
+            '\r' => result.push_str("|r"),
+            '[' => result.push_str("|["),
+            ']' => result.push_str("|]"),
+            c => result.push(c),
+        }
+    }
+    result
+}
@@ -1,14 +1,32 @@
-use super::Level;
+use super::text::{make_string, DurationFormat, TimestampFormat};
+use super::{Level, Reporter, ReporterHandle, TaskReportType, DONTPRINT_TAG, NOSTATUS_TAG};
+use crate::process::{COMMAND, OUTPUT_KEY};
+use crate::recurring::{AVG_DURATION_KEY, OCCURRENCES_KEY, RECURRING_TAG};
+use crate::task::Task;
 use crate::task_tree::{TaskInternal, TaskResult, TaskStatus, TaskTree, TASK_TREE};
 use crate::uniq_id::UniqID;
 use anyhow::{Context, Result};
+pub use colored::Color;
 use colored::Colorize;
 use crossterm::{cursor, style, terminal};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::io::Write;
-use std::sync::Arc;
-use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
-const NOSTATUS_TAG: &str = "nostatus";
+pub const DEFAULT_SPINNER_FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+pub const SPINNER_FRAME_DURATION: Duration = Duration::from_millis(100);
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+/// Longest time a frame that doesn't change stays on the screen without
+/// being redrawn. STDIO is locked while a frame is shown, so this is how
+/// long other output may have to wait.
+const MAX_UNCHANGED_FRAME: Duration = Duration::from_secs(1);
+/// Throughput in the footer is averaged over this window
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
+/// Lines of command output longer than this are cut off in the status
+const MAX_OUTPUT_NOTE_LEN: usize = 60;
 
 lazy_static::lazy_static! {
     pub static ref TERM_STATUS: TermStatus = TermStatus::new(TASK_TREE.clone());
@@ -26,22 +44,148 @@ pub fn hide() {
     TERM_STATUS.hide();
 }
 
+/// Writer for output that should be printed above the status tree, see
+/// [BufferedStdout]
+pub fn stdout() -> BufferedStdout {
+    TERM_STATUS.stdout()
+}
+
+/// Same as `std::println!`, but the line is routed through [stdout()] so it
+/// is printed above the status tree instead of garbling it.
+#[macro_export]
+macro_rules! status_println {
+    ($($arg:tt)*) => {{
+        use std::io::Write;
+        writeln!($crate::reporters::term_status::stdout(), $($arg)*).ok();
+    }};
+}
+
+#[derive(Clone)]
+pub struct TermStatus {
+    internal: Arc<RwLock<TermStatusInternal>>,
+    /// Output written through [BufferedStdout] while the status is shown
+    /// (None when it's hidden). It is printed to STDOUT right before the next
+    /// frame is drawn. Kept outside of `internal` so writers don't wait for
+    /// the frame that is currently displayed.
+    buffered_stdout: Arc<Mutex<Option<Vec<u8>>>>,
+    /// Reporter that prints finished tasks into scrollback, see
+    /// [set_scrollback()](TermStatus::set_scrollback)
+    scrollback_reporter: Arc<Mutex<Option<ReporterHandle>>>,
+    /// Reporter that shows the status again when a task is started, see
+    /// [set_auto_hide()](TermStatus::set_auto_hide)
+    auto_hide_reporter: Arc<Mutex<Option<ReporterHandle>>>,
+    /// Set when the status was hidden because nothing was running
+    suspended: Arc<AtomicBool>,
+}
+
+/// STDOUT writer that cooperates with [TermStatus]. While the status tree
+/// is shown, everything written to it is buffered and printed between frames
+/// right above the tree, otherwise it's written to STDOUT directly.
 #[derive(Clone)]
-pub struct TermStatus(Arc<RwLock<TermStatusInternal>>);
+pub struct BufferedStdout(Arc<Mutex<Option<Vec<u8>>>>);
+
+impl Write for BufferedStdout {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut *self.0.lock().unwrap() {
+            Some(buffered) => {
+                buffered.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            None => std::io::stdout().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &*self.0.lock().unwrap() {
+            Some(_) => Ok(()),
+            None => std::io::stdout().flush(),
+        }
+    }
+}
 
 impl TermStatus {
-    fn new(task_tree: Arc<TaskTree>) -> Self {
-        Self(Arc::new(RwLock::new(TermStatusInternal::new(task_tree))))
+    pub fn new(task_tree: Arc<TaskTree>) -> Self {
+        Self {
+            internal: Arc::new(RwLock::new(TermStatusInternal::new(task_tree))),
+            buffered_stdout: Arc::new(Mutex::new(None)),
+            scrollback_reporter: Arc::new(Mutex::new(None)),
+            auto_hide_reporter: Arc::new(Mutex::new(None)),
+            suspended: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Status view that only shows the given task and its subtasks, so a
+    /// library can display its own tasks while the application owns the rest
+    /// of the screen.
+    pub fn for_task(task: &Task) -> Self {
+        let term_status = Self::new(task.0.task_tree.clone());
+        term_status.internal.write().unwrap().root = Some(task.0.id);
+        term_status
+    }
+
+    pub fn stdout(&self) -> BufferedStdout {
+        BufferedStdout(self.buffered_stdout.clone())
+    }
+
+    /// Print every finished task as a log line above the status tree (the
+    /// same format as [StdioReporter](super::StdioReporter)) and only keep
+    /// running tasks in the tree. This way the full log stays in the
+    /// terminal scrollback after the status is hidden.
+    pub fn set_scrollback(&self, enabled: bool) {
+        let mut internal = self.internal.write().unwrap();
+        internal.scrollback = enabled;
+        let task_tree = internal.task_tree.clone();
+        let max_log_level = internal.max_log_level;
+        let root = internal.root;
+        drop(internal);
+
+        let mut handle = self.scrollback_reporter.lock().unwrap();
+        if let Some(handle) = handle.take() {
+            task_tree.remove_reporter(handle);
+        }
+        if enabled {
+            *handle = Some(task_tree.add_reporter(Arc::new(ScrollbackReporter {
+                stdout: self.stdout(),
+                max_log_level,
+                subtree: root.map(|root| SubtreeFilter::new(&task_tree, root)),
+            })));
+        }
+    }
+
+    fn has_buffered_stdout(&self) -> bool {
+        self.buffered_stdout
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|buffered| !buffered.is_empty())
+    }
+
+    /// Write out everything that was buffered by [BufferedStdout]. Must only
+    /// be called when the status tree is cleared from the screen.
+    fn flush_buffered_stdout(&self, stdout: &mut impl Write) {
+        let buffered = match &mut *self.buffered_stdout.lock().unwrap() {
+            Some(buffered) => std::mem::take(buffered),
+            None => return,
+        };
+        if !buffered.is_empty() {
+            stdout.write_all(&buffered).ok();
+            stdout.flush().ok();
+        }
     }
 
     pub fn show(&self) {
-        let mut lock = self.0.write().unwrap();
+        let mut lock = self.internal.write().unwrap();
         if lock.enabled {
             return;
         } else {
             lock.enabled = true;
+            lock.idle_since = None;
         }
         drop(lock);
+        self.buffered_stdout
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Vec::new);
 
         let t = self.clone();
         std::thread::spawn(move || {
@@ -58,19 +202,35 @@ impl TermStatus {
                 // it while the status tree is displayed. If something prints
                 // while the tree si there everything will get messed up, output
                 // will be lost and parts of tree will end up as random noise.
-                let stdout_lock = stdout.lock();
+                let mut stdout_lock = stdout.lock();
                 let mut stderr_lock = stderr.lock();
 
-                let mut internal = t.0.write().unwrap();
+                let mut internal = t.internal.write().unwrap();
                 if internal.enabled {
+                    t.flush_buffered_stdout(&mut stdout_lock);
                     internal.print(&mut stderr_lock).ok();
                 } else {
                     break;
                 }
+                let drawn_at = Instant::now();
                 // STDIO is locked the whole time.
                 // WARN: If there a heavy IO
                 // happening this will obviously slow things down quite a bit.
-                std::thread::sleep(std::time::Duration::from_millis(50));
+                // A frame that doesn't change is left on the screen instead
+                // of being cleared and printed again every tick.
+                loop {
+                    let refresh_interval = internal.refresh_interval;
+                    drop(internal);
+                    std::thread::sleep(refresh_interval);
+                    internal = t.internal.write().unwrap();
+                    if !internal.enabled
+                        || drawn_at.elapsed() >= MAX_UNCHANGED_FRAME
+                        || t.has_buffered_stdout()
+                        || internal.frame_changed()
+                    {
+                        break;
+                    }
+                }
 
                 if internal.enabled {
                     internal.clear(&mut stderr_lock).ok();
@@ -78,6 +238,14 @@ impl TermStatus {
                     break;
                 }
 
+                if internal.should_auto_hide() {
+                    internal.enabled = false;
+                    t.suspended.store(true, Ordering::SeqCst);
+                    drop(internal);
+                    t.write_out_buffered_stdout();
+                    break;
+                }
+
                 drop(stdout_lock);
                 drop(stderr_lock);
             }
@@ -85,7 +253,243 @@ impl TermStatus {
     }
 
     pub fn hide(&self) {
-        self.0.write().unwrap().enabled = false;
+        self.suspended.store(false, Ordering::SeqCst);
+        self.internal.write().unwrap().enabled = false;
+        self.write_out_buffered_stdout();
+    }
+
+    /// Clear the status automatically after no tasks were running for
+    /// `after`, and show it again as soon as a new task is started. This way
+    /// an interactive shell isn't held by an empty status region.
+    pub fn set_auto_hide(&self, after: Option<Duration>) {
+        let mut internal = self.internal.write().unwrap();
+        internal.auto_hide = after;
+        let task_tree = internal.task_tree.clone();
+        drop(internal);
+
+        let mut handle = self.auto_hide_reporter.lock().unwrap();
+        if let Some(handle) = handle.take() {
+            task_tree.remove_reporter(handle);
+        }
+        if after.is_some() {
+            // the tree must not keep the status alive, it holds the tree
+            let reporter = AutoHideReporter {
+                internal: Arc::downgrade(&self.internal),
+                buffered_stdout: Arc::downgrade(&self.buffered_stdout),
+                scrollback_reporter: Arc::downgrade(&self.scrollback_reporter),
+                auto_hide_reporter: Arc::downgrade(&self.auto_hide_reporter),
+                suspended: Arc::downgrade(&self.suspended),
+            };
+            *handle = Some(task_tree.add_reporter(Arc::new(reporter)));
+        }
+    }
+
+    /// Stop buffering [BufferedStdout] output and print whatever was buffered
+    fn write_out_buffered_stdout(&self) {
+        // output buffered after the last frame would be lost otherwise
+        let buffered = self.buffered_stdout.lock().unwrap().take();
+        if let Some(buffered) = buffered {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(&buffered).ok();
+            stdout.flush().ok();
+        }
+    }
+
+    /// Frames of the spinner shown for running tasks that don't report
+    /// progress. Pass an empty vec to show a static `▶` instead.
+    pub fn set_spinner_frames<S: Into<String>>(&self, frames: Vec<S>) {
+        self.internal.write().unwrap().spinner_frames =
+            frames.into_iter().map(Into::into).collect();
+    }
+
+    /// Show a line below the tree with the number of running, succeeded and
+    /// failed tasks, elapsed time and how many tasks finish per second.
+    pub fn set_footer(&self, enabled: bool) {
+        let mut internal = self.internal.write().unwrap();
+        let task_tree = internal.task_tree.clone();
+        let old_footer = internal.footer.take();
+        if enabled {
+            let counts = Arc::new(FinishedCounts {
+                succeeded: AtomicU64::new(0),
+                failed: AtomicU64::new(0),
+                subtree: internal
+                    .root
+                    .map(|root| SubtreeFilter::new(&task_tree, root)),
+            });
+            let reporter_handle = task_tree.add_reporter(counts.clone());
+            internal.footer = Some(Footer {
+                counts,
+                reporter_handle,
+                started_at: task_tree.now(),
+                samples: VecDeque::new(),
+            });
+        }
+        drop(internal);
+
+        if let Some(old_footer) = old_footer {
+            task_tree.remove_reporter(old_footer.reporter_handle);
+        }
+    }
+
+    pub fn set_elapsed_format(&self, format: ElapsedFormat) {
+        self.internal.write().unwrap().elapsed_format = format;
+    }
+
+    pub fn set_elapsed_column(&self, column: ElapsedColumn) {
+        self.internal.write().unwrap().elapsed_column = column;
+    }
+
+    /// Don't count the time tasks spent [paused](crate::Task::pause) in
+    /// their elapsed time.
+    pub fn set_exclude_paused_time(&self, exclude: bool) {
+        self.internal.write().unwrap().exclude_paused_time = exclude;
+    }
+
+    /// How often the status tree is checked for changes. A frame is only
+    /// redrawn once it looks different, or after a second at the latest.
+    /// STDIO is locked while the frame is displayed, so longer intervals
+    /// delay other output more.
+    pub fn set_refresh_interval(&self, interval: Duration) {
+        self.internal.write().unwrap().refresh_interval = interval;
+    }
+
+    /// Don't show tasks deeper than `max_depth` levels (where 1 is only root
+    /// tasks). Instead, their ancestor at the last shown level gets a
+    /// `(+N subtasks)` suffix. Root tasks are always shown, so `Some(0)` is
+    /// the same as `Some(1)`.
+    pub fn set_max_depth(&self, max_depth: Option<usize>) {
+        self.internal.write().unwrap().max_depth = max_depth;
+    }
+
+    /// Style names of tasks that have the given tag, e.g. `#db` tasks in cyan.
+    /// Takes precedence over [set_level_style()](TermStatus::set_level_style).
+    /// If a task has multiple styled tags, the first one that was set wins.
+    pub fn set_tag_style<S: Into<String>>(&self, tag: S, style: RowStyle) {
+        let tag = tag.into();
+        let mut internal = self.internal.write().unwrap();
+        internal.tag_styles.retain(|(t, _)| *t != tag);
+        internal.tag_styles.push((tag, style));
+    }
+
+    pub fn set_level_style(&self, level: Level, style: RowStyle) {
+        self.internal
+            .write()
+            .unwrap()
+            .level_styles
+            .insert(level, style);
+    }
+
+    /// see [TermStatusInternal::render_to_string]
+    pub fn render_to_string(&self, width: usize) -> Result<String> {
+        self.internal.write().unwrap().render_to_string(width)
+    }
+
+    /// see [TermStatusInternal::deterministic]
+    pub fn set_deterministic(&self, enabled: bool) {
+        self.internal.write().unwrap().deterministic = enabled;
+    }
+
+    pub fn set_max_log_level(&self, level: Level) {
+        let mut internal = self.internal.write().unwrap();
+        internal.max_log_level = level;
+        let scrollback = internal.scrollback;
+        drop(internal);
+
+        if scrollback {
+            // re-create the reporter with the new level
+            self.set_scrollback(true);
+        }
+    }
+}
+
+/// Draws the status tree into a ratatui buffer, so it can be a pane of an
+/// application's own TUI. Rows that don't fit are cut off.
+#[cfg(feature = "ratatui")]
+impl ratatui::widgets::Widget for &TermStatus {
+    fn render(self, area: ratatui::layout::Rect, buf: &mut ratatui::buffer::Buffer) {
+        let rendered = match self.render_to_string(area.width as usize) {
+            Ok(rendered) => rendered,
+            Err(_) => return,
+        };
+        let mut lines = rendered.lines().map(String::from).collect::<Vec<_>>();
+        let height = area.height as usize;
+        if lines.len() > height {
+            // keep a line for the "more tasks" message
+            lines = trim_rows(lines, height.saturating_sub(1));
+        }
+        for (i, line) in lines.iter().take(height).enumerate() {
+            buf.set_stringn(
+                area.x,
+                area.y + i as u16,
+                line,
+                area.width as usize,
+                ratatui::style::Style::default(),
+            );
+        }
+    }
+}
+
+/// Weak version of the [TermStatus] it shows again
+struct AutoHideReporter {
+    internal: Weak<RwLock<TermStatusInternal>>,
+    buffered_stdout: Weak<Mutex<Option<Vec<u8>>>>,
+    scrollback_reporter: Weak<Mutex<Option<ReporterHandle>>>,
+    auto_hide_reporter: Weak<Mutex<Option<ReporterHandle>>>,
+    suspended: Weak<AtomicBool>,
+}
+
+impl AutoHideReporter {
+    fn term_status(&self) -> Option<TermStatus> {
+        Some(TermStatus {
+            internal: self.internal.upgrade()?,
+            buffered_stdout: self.buffered_stdout.upgrade()?,
+            scrollback_reporter: self.scrollback_reporter.upgrade()?,
+            auto_hide_reporter: self.auto_hide_reporter.upgrade()?,
+            suspended: self.suspended.upgrade()?,
+        })
+    }
+}
+
+impl Reporter for AutoHideReporter {
+    fn task_start(&self, _task: Arc<TaskInternal>) {
+        if let Some(term_status) = self.term_status() {
+            if term_status.suspended.swap(false, Ordering::SeqCst) {
+                term_status.show();
+            }
+        }
+    }
+}
+
+struct ScrollbackReporter {
+    stdout: BufferedStdout,
+    max_log_level: Level,
+    subtree: Option<SubtreeFilter>,
+}
+
+impl Reporter for ScrollbackReporter {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        if let Some(subtree) = &self.subtree {
+            subtree.started(&task);
+        }
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        if self.subtree.as_ref().is_some_and(|s| !s.finished(&task)) {
+            return;
+        }
+
+        let level = super::utils::parse_level(&task);
+        if level > self.max_log_level || task.tags.contains(DONTPRINT_TAG) {
+            return;
+        }
+
+        let line = make_string(
+            &task,
+            TimestampFormat::UTC,
+            DurationFormat::Milliseconds,
+            TaskReportType::End,
+        );
+        writeln!(self.stdout.clone(), "{}", line).ok();
     }
 }
 
@@ -104,76 +508,301 @@ type Depth = Vec<bool>;
 
 #[derive(Clone)]
 pub struct TermStatusInternal {
-    current_height: usize,
+    /// Rows that are currently on the screen
+    frame: Vec<String>,
     task_tree: Arc<TaskTree>,
     pub max_log_level: Level,
+    /// Snapshot mode for tests. Elapsed times are redacted and sibling tasks
+    /// are sorted by name instead of the order they were created in.
+    pub deterministic: bool,
+    /// Finished tasks are left out of the tree, because they're printed
+    /// into scrollback instead
+    pub scrollback: bool,
+    /// Animation frames for running tasks without progress, one frame is
+    /// shown for [SPINNER_FRAME_DURATION]
+    pub spinner_frames: Vec<String>,
+    footer: Option<Footer>,
+    pub elapsed_format: ElapsedFormat,
+    pub elapsed_column: ElapsedColumn,
+    /// see [TermStatus::set_exclude_paused_time()]
+    pub exclude_paused_time: bool,
+    pub refresh_interval: Duration,
+    pub max_depth: Option<usize>,
+    /// Only show this task and its subtasks, see
+    /// [TermStatus::for_task()]
+    pub root: Option<UniqID>,
+    /// see [TermStatus::set_auto_hide()]
+    pub auto_hide: Option<Duration>,
+    idle_since: Option<SystemTime>,
+    pub tag_styles: Vec<(String, RowStyle)>,
+    pub level_styles: BTreeMap<Level, RowStyle>,
     enabled: bool,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElapsedFormat {
+    /// `12s`
+    Seconds,
+    /// `12.3s`
+    Deciseconds,
+    /// `00:00:12`
+    Clock,
+}
+
+/// Where the elapsed time is shown in a row
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElapsedColumn {
+    /// ` ▶  [1.2s] task`
+    BeforeName,
+    /// ` ▶  task [1.2s]`
+    AfterName,
+}
+
+/// How the name of a task is displayed, see
+/// [set_tag_style()](TermStatus::set_tag_style)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowStyle {
+    Color(Color),
+    Dimmed,
+}
+
+#[derive(Clone)]
+struct Footer {
+    counts: Arc<FinishedCounts>,
+    reporter_handle: ReporterHandle,
+    started_at: SystemTime,
+    /// (time, finished task count) samples used to compute throughput
+    samples: VecDeque<(SystemTime, u64)>,
+}
+
+struct FinishedCounts {
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+    subtree: Option<SubtreeFilter>,
+}
+
+impl Reporter for FinishedCounts {
+    fn task_start(&self, task: Arc<TaskInternal>) {
+        if let Some(subtree) = &self.subtree {
+            subtree.started(&task);
+        }
+    }
+
+    fn task_end(&self, task: Arc<TaskInternal>) {
+        if self.subtree.as_ref().is_some_and(|s| !s.finished(&task)) {
+            return;
+        }
+
+        match task.status {
+            TaskStatus::Finished(TaskResult::Failure(_), _) => &self.failed,
+            _ => &self.succeeded,
+        }
+        .fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Tracks which reported tasks belong to the subtree of a scoped
+/// [TermStatus]. Reports only carry the direct parent of a task, so the ids
+/// of all running tasks in the subtree are kept around. Tasks that are started
+/// after their parent has finished are not tracked.
+struct SubtreeFilter {
+    ids: Mutex<BTreeSet<UniqID>>,
+}
+
+impl SubtreeFilter {
+    fn new(task_tree: &TaskTree, root: UniqID) -> Self {
+        let tree = task_tree.tree_internal.read().unwrap();
+        let mut ids = BTreeSet::new();
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            ids.insert(id);
+            stack.extend(tree.parent_to_children().get(&id).into_iter().flatten());
+        }
+        Self {
+            ids: Mutex::new(ids),
+        }
+    }
+
+    fn started(&self, task: &TaskInternal) {
+        let mut ids = self.ids.lock().unwrap();
+        if task
+            .parent_id
+            .is_some_and(|parent_id| ids.contains(&parent_id))
+        {
+            ids.insert(task.id);
+        }
+    }
+
+    /// Whether the finished task was in the subtree
+    fn finished(&self, task: &TaskInternal) -> bool {
+        self.ids.lock().unwrap().remove(&task.id)
+    }
+}
+
 impl TermStatusInternal {
     fn new(task_tree: Arc<TaskTree>) -> Self {
         Self {
-            current_height: 0,
+            frame: vec![],
             task_tree,
             max_log_level: Level::default(),
+            deterministic: false,
+            scrollback: false,
+            spinner_frames: DEFAULT_SPINNER_FRAMES
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+            footer: None,
+            elapsed_format: ElapsedFormat::Deciseconds,
+            elapsed_column: ElapsedColumn::BeforeName,
+            exclude_paused_time: false,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            max_depth: None,
+            root: None,
+            auto_hide: None,
+            idle_since: None,
+            tag_styles: vec![],
+            level_styles: BTreeMap::new(),
             enabled: false,
         }
     }
 
-    fn print(&mut self, stdio: &mut impl Write) -> Result<()> {
-        let rows = self.make_status_rows()?;
-
-        let height = rows.len();
+    fn make_frame(&mut self) -> Result<Vec<String>> {
+        let mut rows = self.make_status_rows()?;
+        rows.extend(self.make_footer());
+        Ok(rows)
+    }
 
-        if let (0, 0) = (height, self.current_height) {
+    /// Must only be called when the previous frame is cleared from the
+    /// screen
+    fn print(&mut self, stdio: &mut impl Write) -> Result<()> {
+        self.frame = self.make_frame()?;
+        if self.frame.is_empty() {
             return Ok(());
         }
 
-        self.current_height = height;
-
         crossterm::execute!(stdio, style::Print("\n")).ok();
-        crossterm::execute!(stdio, style::Print(rows.join("\n"))).ok();
+        crossterm::execute!(stdio, style::Print(self.frame.join("\n"))).ok();
         crossterm::execute!(stdio, style::Print("\n")).ok();
 
         Ok(())
     }
 
+    /// Whether the next frame would look different from the one on the
+    /// screen
+    fn frame_changed(&mut self) -> bool {
+        self.make_frame().map_or(true, |frame| frame != self.frame)
+    }
+
+    /// Render the status tree (and the footer if it's enabled) as plain text
+    /// without colors, with every line cut to `width` characters. Useful for
+    /// embedding the tree into another UI.
+    pub fn render_to_string(&mut self, width: usize) -> Result<String> {
+        let mut rows = self.make_rows()?;
+        rows.extend(self.make_footer());
+        Ok(rows
+            .iter()
+            .map(|row| {
+                super::text::strip_ansi(row)
+                    .chars()
+                    .take(width)
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    /// Rows that fit into the current terminal
     fn make_status_rows(&self) -> Result<Vec<String>> {
+        let (_, term_height) = crossterm::terminal::size().unwrap_or((50, 50));
+        let footer_height = if self.footer.is_some() { 1 } else { 0 };
+        let max_height = (term_height as usize).saturating_sub(2 + footer_height);
+        Ok(trim_rows(self.make_rows()?, max_height))
+    }
+
+    fn make_rows(&self) -> Result<Vec<String>> {
+        let now = self.task_tree.now();
         let tree = self.task_tree.tree_internal.read().unwrap();
         let child_to_parents = tree.child_to_parents();
         let parent_to_children = tree.parent_to_children();
 
-        let mut stack: Vec<(UniqID, Depth)> = tree
-            .root_tasks()
-            .iter()
-            .filter(|id| !child_to_parents.contains_key(id))
-            .map(|id| (*id, vec![]))
+        let sort_by_name = |ids: &mut Vec<UniqID>| {
+            // stable sort, siblings with the same priority (and name in
+            // deterministic mode) stay in creation order
+            ids.sort_by_key(|id| {
+                tree.get_task(*id).ok().map(|t| {
+                    let name = if self.deterministic {
+                        Some(t.name.clone())
+                    } else {
+                        None
+                    };
+                    (t.priority(), name)
+                })
+            });
+        };
+
+        let mut root_ids = match self.root {
+            Some(root) if tree.get_task(root).is_ok() => vec![root],
+            Some(_) => vec![],
+            None => tree
+                .root_tasks()
+                .iter()
+                .filter(|id| !child_to_parents.contains_key(id))
+                .copied()
+                .collect::<Vec<_>>(),
+        };
+        sort_by_name(&mut root_ids);
+        // (task, indentation, row of the closest printed ancestor, whether the
+        // task is below max_depth and gets collapsed into that row)
+        let mut stack: Vec<(UniqID, Depth, Option<usize>, bool)> = root_ids
+            .into_iter()
+            .map(|id| (id, vec![], None, false))
             .collect();
 
         let mut rows = vec![];
-        while let Some((id, depth)) = stack.pop() {
+        let mut collapsed_count: BTreeMap<usize, usize> = BTreeMap::new();
+        while let Some((id, depth, parent_row, mut collapsed)) = stack.pop() {
             let task = tree.get_task(id).context("must be present")?;
 
-            let dontprint = !self.should_print(task);
+            let mut dontprint = !self.should_print(task);
+            if !dontprint && self.max_depth.is_some_and(|max| depth.len() >= max.max(1)) {
+                collapsed = true;
+            }
+            if collapsed {
+                if let (false, Some(row)) = (dontprint, parent_row) {
+                    *collapsed_count.entry(row).or_default() += 1;
+                }
+                dontprint = true;
+            }
+            let row = if dontprint {
+                parent_row
+            } else {
+                Some(rows.len())
+            };
 
-            let children_iter = parent_to_children.get(&id).into_iter().flatten().peekable();
+            let mut children = parent_to_children
+                .get(&id)
+                .into_iter()
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>();
+            sort_by_name(&mut children);
             let mut append_to_stack = vec![];
 
-            let last_visible_child = children_iter
-                .clone()
-                .filter(|id| tree.get_task(**id).map_or(false, |t| self.should_print(t)))
-                .last();
+            let last_visible_child = children
+                .iter()
+                .rfind(|id| tree.get_task(**id).is_ok_and(|t| self.should_print(t)))
+                .copied();
 
             // we still need to DFS the ones that we don't print to make sure
             // we're not skipping their children
-            for subtask_id in children_iter {
+            for subtask_id in children {
                 let mut new_depth = depth.clone();
                 // If we're not printing it, we're not adding the indent either
                 // so this tasks children will become children of the parent task
                 if !dontprint {
                     new_depth.push(Some(subtask_id) != last_visible_child);
                 }
-                append_to_stack.push((*subtask_id, new_depth));
+                append_to_stack.push((subtask_id, new_depth, row, collapsed));
             }
 
             // Since we're popping, we'll be going through children in reverse order,
@@ -182,28 +811,144 @@ impl TermStatusInternal {
             stack.append(&mut append_to_stack);
 
             if !dontprint {
-                rows.push(self.task_row(task, depth)?);
+                let waiting_on = task
+                    .waits_on
+                    .iter()
+                    .filter(|(dep, _)| {
+                        tree.get_task(*dep)
+                            .is_ok_and(|dep| !matches!(dep.status, TaskStatus::Finished(..)))
+                    })
+                    .map(|(_, name)| name.clone())
+                    .collect();
+                rows.push(self.task_row(task, depth, waiting_on, now)?);
             }
         }
 
-        let (_, term_height) = crossterm::terminal::size().unwrap_or((50, 50));
-        let max_height = term_height as usize - 2;
-
-        if rows.len() > max_height {
-            let trimmed = rows.len() - max_height;
-            rows = rows.into_iter().take(max_height).collect();
-            rows.push(format!(".......{} more tasks.......", trimmed))
+        for (row, count) in collapsed_count {
+            let suffix = format!(" (+{} subtasks)", count).dimmed();
+            rows[row] = format!("{}{}", rows[row], suffix);
         }
 
         Ok(rows)
     }
 
+    /// Number of running tasks that this status shows (or would show,
+    /// ignoring levels and tags)
+    fn running_count(&self) -> usize {
+        let tree = self.task_tree.tree_internal.read().unwrap();
+        let is_running = |task: &TaskInternal| !matches!(task.status, TaskStatus::Finished(..));
+        match self.root {
+            Some(root) => {
+                let mut running = 0;
+                let mut stack = vec![root];
+                while let Some(id) = stack.pop() {
+                    if tree.get_task(id).is_ok_and(is_running) {
+                        running += 1;
+                    }
+                    stack.extend(tree.parent_to_children().get(&id).into_iter().flatten());
+                }
+                running
+            }
+            None => tree.tasks().filter(|task| is_running(task)).count(),
+        }
+    }
+
+    /// Whether nothing was running for longer than
+    /// [auto_hide](TermStatusInternal::auto_hide)
+    fn should_auto_hide(&mut self) -> bool {
+        let auto_hide = match self.auto_hide {
+            Some(auto_hide) => auto_hide,
+            None => return false,
+        };
+        if self.running_count() > 0 {
+            self.idle_since = None;
+            return false;
+        }
+        let now = self.task_tree.now();
+        let idle_since = *self.idle_since.get_or_insert(now);
+        now.duration_since(idle_since).unwrap_or_default() >= auto_hide
+    }
+
+    fn make_footer(&mut self) -> Option<String> {
+        let now = self.task_tree.now();
+        let running = self.running_count();
+        let footer = self.footer.as_mut()?;
+
+        let succeeded = footer.counts.succeeded.load(Ordering::Relaxed);
+        let failed = footer.counts.failed.load(Ordering::Relaxed);
+        let finished = succeeded + failed;
+
+        footer.samples.push_back((now, finished));
+        while let Some((time, _)) = footer.samples.front() {
+            match now.duration_since(*time) {
+                Ok(age) if age > THROUGHPUT_WINDOW => footer.samples.pop_front(),
+                _ => break,
+            };
+        }
+
+        let (elapsed, throughput) = if self.deterministic {
+            ("[ ]".to_string(), "[ ]".to_string())
+        } else {
+            let elapsed = now.duration_since(footer.started_at).unwrap_or_default();
+            let throughput = match footer.samples.front() {
+                Some((time, count)) => {
+                    let window = now.duration_since(*time).unwrap_or_default();
+                    if window.as_millis() == 0 {
+                        0.0
+                    } else {
+                        (finished - count) as f64 / window.as_secs_f64()
+                    }
+                }
+                None => 0.0,
+            };
+            (
+                format!("{}.{}s", elapsed.as_secs(), elapsed.subsec_millis() / 100),
+                format!("{:.1}", throughput),
+            )
+        };
+
+        let failed = if failed > 0 {
+            failed.to_string().red().to_string()
+        } else {
+            failed.to_string()
+        };
+
+        Some(
+            format!(
+                "running: {} | succeeded: {} | failed: {} | elapsed: {} | {} tasks/s",
+                running, succeeded, failed, elapsed, throughput
+            )
+            .dimmed()
+            .to_string(),
+        )
+    }
+
+    fn row_style(&self, task: &TaskInternal) -> Option<RowStyle> {
+        self.tag_styles
+            .iter()
+            .find(|(tag, _)| task.tags.contains(tag))
+            .map(|(_, style)| *style)
+            .or_else(|| {
+                let level = super::utils::parse_level(task);
+                self.level_styles.get(&level).copied()
+            })
+    }
+
     fn should_print(&self, task: &TaskInternal) -> bool {
         let level = super::utils::parse_level(task);
-        !task.tags.contains(NOSTATUS_TAG) && (level <= self.max_log_level)
+        let finished = matches!(task.status, TaskStatus::Finished(..));
+        !task.tags.contains(NOSTATUS_TAG)
+            && (level <= self.max_log_level)
+            && !(self.scrollback && finished)
     }
 
-    fn task_row(&self, task_internal: &TaskInternal, mut depth: Depth) -> Result<String> {
+    fn task_row(
+        &self,
+        task_internal: &TaskInternal,
+        mut depth: Depth,
+        waiting_on: Vec<String>,
+        now: SystemTime,
+    ) -> Result<String> {
         /*
 
         [▶] Root Task
@@ -236,58 +981,621 @@ impl TermStatusInternal {
             String::new()
         };
 
+        let duration = if self.exclude_paused_time {
+            task_internal.active_duration(now)
+        } else {
+            match task_internal.status {
+                TaskStatus::Finished(_, finished_at) => {
+                    finished_at.duration_since(task_internal.started_at)
+                }
+                _ => now.duration_since(task_internal.started_at),
+            }?
+        };
+
+        let status_symbol = match task_internal.status {
+            TaskStatus::Running
+                if task_internal.progress.is_none()
+                    && !task_internal.stalled
+                    && !self.spinner_frames.is_empty()
+                    && !self.deterministic =>
+            {
+                // frames are derived from the elapsed time, so every task
+                // spins independently without keeping any state around
+                let frame = (duration.as_millis() / SPINNER_FRAME_DURATION.as_millis()) as usize
+                    % self.spinner_frames.len();
+                self.spinner_frames[frame].as_str()
+            }
+            TaskStatus::Running => "▶",
+            TaskStatus::Paused(_) => "‖",
+            TaskStatus::Scheduled(_) => "◷",
+            TaskStatus::Finished(TaskResult::Success, _) => "✓",
+            TaskStatus::Finished(TaskResult::Failure(_), _) => "x",
+        };
+
+        let elapsed = if self.deterministic {
+            " ".to_string()
+        } else {
+            format_elapsed(duration, self.elapsed_format)
+        };
+
+        // shown dimmed in parentheses after the name, e.g. why the task is
+        // paused
+        let note = match &task_internal.status {
+            TaskStatus::Paused(reason) => Some(reason.clone()),
+            TaskStatus::Scheduled(_) if self.deterministic => Some("scheduled".to_string()),
+            TaskStatus::Scheduled(starts_at) => {
+                let remaining = starts_at.duration_since(now).unwrap_or_default();
+                let remaining = format_elapsed(remaining, self.elapsed_format);
+                Some(format!("starts in {}", remaining))
+            }
+            TaskStatus::Running if task_internal.has_tag(COMMAND) => command_output(task_internal),
+            _ => None,
+        };
+
+        let status = format!(" {} ", status_symbol);
         let status = match task_internal.status {
-            TaskStatus::Running => " ▶ ".black().on_yellow(),
-            TaskStatus::Finished(TaskResult::Success, _) => " ✓ ".black().on_green(),
-            TaskStatus::Finished(TaskResult::Failure(_), _) => " x ".white().on_red(),
+            TaskStatus::Running if task_internal.stalled => status.black().on_magenta(),
+            TaskStatus::Running => status.black().on_yellow(),
+            TaskStatus::Paused(_) => status.black().on_blue(),
+            TaskStatus::Scheduled(_) => status.black().on_cyan(),
+            TaskStatus::Finished(TaskResult::Success, _) => status.black().on_green(),
+            TaskStatus::Finished(TaskResult::Failure(_), _) => status.white().on_red(),
         };
 
         let progress = make_progress(task_internal);
 
-        let duration = match task_internal.status {
-            TaskStatus::Finished(_, finished_at) => {
-                finished_at.duration_since(task_internal.started_at)
-            }
-            _ => task_internal.started_at.elapsed(),
-        }?;
+        let ts = format!(" [{}] ", elapsed).dimmed();
 
-        let secs = duration.as_secs();
-        let millis = (duration.as_millis() % 1000) / 100;
-        let ts = format!(" [{}.{}s] ", secs, millis).dimmed();
+        let name = recurring_name(task_internal);
+        let name = if task_internal.stalled && matches!(task_internal.status, TaskStatus::Running) {
+            name.magenta().bold().to_string()
+        } else {
+            match self.row_style(task_internal) {
+                Some(RowStyle::Color(color)) => name.color(color).to_string(),
+                Some(RowStyle::Dimmed) => name.dimmed().to_string(),
+                None => name,
+            }
+        };
+        let name = match &note {
+            Some(note) => format!("{} {}", name, format!("({})", note).dimmed()),
+            None => name,
+        };
+        let name = if waiting_on.is_empty() {
+            name
+        } else {
+            let waiting_on = format!("⧗ waiting on {}", waiting_on.join(", "));
+            format!("{} {}", name, waiting_on.dimmed())
+        };
 
-        Ok(format!(
-            "{}{}{}{}{}",
-            indent, status, ts, progress, task_internal.name
-        ))
+        let row = match self.elapsed_column {
+            ElapsedColumn::BeforeName => {
+                format!("{}{}{}{}{}", indent, status, ts, progress, name)
+            }
+            ElapsedColumn::AfterName => {
+                format!("{}{} {}{}{}", indent, status, progress, name, ts)
+            }
+        };
+        Ok(row)
     }
 
-    fn clear(&self, stdio: &mut impl Write) -> Result<()> {
-        if self.current_height != 0 {
-            for _ in 0..(self.current_height + 1) {
+    fn clear(&mut self, stdio: &mut impl Write) -> Result<()> {
+        if !self.frame.is_empty() {
+            for _ in 0..(self.frame.len() + 1) {
                 crossterm::execute!(stdio, terminal::Clear(terminal::ClearType::CurrentLine)).ok();
                 crossterm::execute!(stdio, cursor::MoveUp(1)).ok();
             }
         }
+        self.frame.clear();
 
         Ok(())
     }
 }
 
-fn make_progress(task: &TaskInternal) -> String {
-    const PROGRESS_BAR_LEN: i64 = 30;
-
-    if let Some((done, total)) = &task.progress {
-        if *total == 0 {
-            // otherwise we'll divide by 0 and it'll panic
-            return String::new();
-        }
-        let pct_done = (done * 100) / total;
-        let done_blocks_len = std::cmp::min((PROGRESS_BAR_LEN * pct_done) / 100, PROGRESS_BAR_LEN);
-        let todo_blocks_len = PROGRESS_BAR_LEN - done_blocks_len;
-        let done_blocks = " ".repeat(done_blocks_len as usize).on_bright_green();
-        let todo_blocks = ".".repeat(todo_blocks_len as usize).on_black();
-        format!(" [{}{}] {}/{} ", done_blocks, todo_blocks, done, total)
+/// Name of the task, with occurrences of [recurring](crate::recurring) tasks
+/// collapsed into it, e.g. `poll_queue ×1523, avg 4ms`
+fn recurring_name(task: &TaskInternal) -> String {
+    let name = task.interpolated_name();
+    if !task.tags.contains(RECURRING_TAG) {
+        return name;
+    }
+    match (
+        task.data.map.get(OCCURRENCES_KEY),
+        task.data.map.get(AVG_DURATION_KEY),
+    ) {
+        (Some(count), Some(avg)) => format!("{} ×{}, avg {}", name, count.0, avg.0),
+        _ => name,
+    }
+}
+
+/// Last line of output of a running [run_command()](Task::run_command) task
+fn command_output(task: &TaskInternal) -> Option<String> {
+    let output = task.data.map.get(OUTPUT_KEY)?.0.to_string();
+    let line = output.lines().last()?.trim();
+    if line.chars().count() > MAX_OUTPUT_NOTE_LEN {
+        let line = line
+            .chars()
+            .take(MAX_OUTPUT_NOTE_LEN - 1)
+            .collect::<String>();
+        Some(format!("{}…", line))
     } else {
-        String::new()
+        Some(line.to_string())
+    }
+}
+
+fn trim_rows(mut rows: Vec<String>, max_height: usize) -> Vec<String> {
+    if rows.len() > max_height {
+        let trimmed = rows.len() - max_height;
+        rows.truncate(max_height);
+        rows.push(format!(".......{} more tasks.......", trimmed))
+    }
+    rows
+}
+
+fn format_elapsed(duration: Duration, format: ElapsedFormat) -> String {
+    let secs = duration.as_secs();
+    match format {
+        ElapsedFormat::Seconds => format!("{}s", secs),
+        ElapsedFormat::Deciseconds => format!("{}.{}s", secs, duration.subsec_millis() / 100),
+        ElapsedFormat::Clock => {
+            format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+        }
+    }
+}
+
+fn make_progress(task: &TaskInternal) -> String {
+    const PROGRESS_BAR_LEN: usize = 30;
+
+    match (task.progress_f64(), task.format_progress()) {
+        (Some((done, total)), Some(formatted)) if total > 0.0 => {
+            let ratio = (done / total).clamp(0.0, 1.0);
+            let done_blocks_len = (PROGRESS_BAR_LEN as f64 * ratio) as usize;
+            let todo_blocks_len = PROGRESS_BAR_LEN - done_blocks_len;
+            let done_blocks = " ".repeat(done_blocks_len).on_bright_green();
+            let todo_blocks = ".".repeat(todo_blocks_len).on_black();
+            format!(" [{}{}] {} ", done_blocks, todo_blocks, formatted)
+        }
+        // nothing to draw a bar for, e.g. a total of 0
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn buffered_stdout_test() {
+        let term_status = TermStatus::new(TaskTree::new());
+        *term_status.buffered_stdout.lock().unwrap() = Some(vec![]);
+
+        let mut stdout = term_status.stdout();
+        writeln!(stdout, "hello").unwrap();
+        write!(stdout, "world").unwrap();
+
+        let mut output = vec![];
+        term_status.flush_buffered_stdout(&mut output);
+        k9::assert_equal!(String::from_utf8(output).unwrap(), "hello\nworld");
+        k9::assert_equal!(
+            term_status.buffered_stdout.lock().unwrap().clone(),
+            Some(vec![])
+        );
+    }
+
+    #[tokio::test]
+    async fn scrollback_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+        term_status.set_scrollback(true);
+        *term_status.buffered_stdout.lock().unwrap() = Some(vec![]);
+
+        let root = tt.create_task("root");
+        root.spawn_sync("done", |_| Ok(())).unwrap();
+        let _running = root.create("running");
+        tt.flush_async().await;
+
+        let rows = term_status
+            .internal
+            .read()
+            .unwrap()
+            .make_status_rows()
+            .unwrap();
+        let rows = rows
+            .iter()
+            .map(|row| crate::reporters::text::strip_ansi(row))
+            .collect::<Vec<_>>();
+        k9::assert_equal!(rows, vec![" ▶  [ ] root", "╰  ▶  [ ] running"]);
+
+        let mut output = vec![];
+        term_status.flush_buffered_stdout(&mut output);
+        let output = crate::reporters::text::strip_ansi(&String::from_utf8(output).unwrap());
+        // `[timestamp] | duration | name`
+        let names = output
+            .lines()
+            .filter_map(|line| line.rsplit("| ").next())
+            .collect::<Vec<_>>();
+        k9::assert_equal!(names, vec!["root:done"]);
+    }
+
+    #[tokio::test]
+    async fn spinner_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_spinner_frames(vec!["a", "b"]);
+
+        let task = tt.create_task("task");
+        tt.flush_async().await;
+        let internal = term_status.internal.read().unwrap();
+        let task_internal = tt
+            .tree_internal
+            .read()
+            .unwrap()
+            .get_task(task.0.id)
+            .unwrap()
+            .clone();
+        let row_at = |ms| {
+            let now = task_internal.started_at + std::time::Duration::from_millis(ms);
+            let row = internal
+                .task_row(&task_internal, vec![], vec![], now)
+                .unwrap();
+            crate::reporters::text::strip_ansi(&row)[..3].to_string()
+        };
+        k9::assert_equal!(row_at(0), " a ");
+        k9::assert_equal!(row_at(150), " b ");
+        k9::assert_equal!(row_at(250), " a ");
+    }
+
+    #[tokio::test]
+    async fn footer_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+        term_status.set_footer(true);
+
+        let root = tt.create_task("root");
+        root.spawn_sync("ok", |_| Ok(())).unwrap();
+        root.spawn_sync("fails", |_| -> anyhow::Result<()> { anyhow::bail!("err") })
+            .ok();
+        tt.flush_async().await;
+
+        let footer = term_status.internal.write().unwrap().make_footer().unwrap();
+        k9::assert_equal!(
+            crate::reporters::text::strip_ansi(&footer),
+            "running: 1 | succeeded: 1 | failed: 1 | elapsed: [ ] | [ ] tasks/s"
+        );
+
+        term_status.set_footer(false);
+        k9::assert_equal!(term_status.internal.write().unwrap().make_footer(), None);
+    }
+
+    #[test]
+    fn format_elapsed_test() {
+        let duration = Duration::from_millis(3_723_456);
+        k9::assert_equal!(format_elapsed(duration, ElapsedFormat::Seconds), "3723s");
+        k9::assert_equal!(
+            format_elapsed(duration, ElapsedFormat::Deciseconds),
+            "3723.4s"
+        );
+        k9::assert_equal!(format_elapsed(duration, ElapsedFormat::Clock), "01:02:03");
+    }
+
+    #[tokio::test]
+    async fn elapsed_column_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+        term_status.set_elapsed_column(ElapsedColumn::AfterName);
+
+        let _task = tt.create_task("task");
+        tt.flush_async().await;
+        let rows = term_status
+            .internal
+            .read()
+            .unwrap()
+            .make_status_rows()
+            .unwrap();
+        k9::assert_equal!(
+            crate::reporters::text::strip_ansi(&rows[0]),
+            " ▶  task [ ] "
+        );
+    }
+
+    #[tokio::test]
+    async fn max_depth_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+        term_status.set_max_depth(Some(2));
+
+        let root = tt.create_task("root");
+        let a = root.create("a");
+        let b = a.create("b");
+        let _c = b.create("c");
+        let _d = a.create("d #nostatus");
+        let _e = root.create("e");
+        tt.flush_async().await;
+
+        let rows = term_status
+            .internal
+            .read()
+            .unwrap()
+            .make_status_rows()
+            .unwrap();
+        let rows = rows
+            .iter()
+            .map(|row| crate::reporters::text::strip_ansi(row))
+            .collect::<Vec<_>>();
+        k9::assert_equal!(
+            rows,
+            vec![" ▶  [ ] root", "├  ▶  [ ] a (+2 subtasks)", "╰  ▶  [ ] e",]
+        );
+
+        // root tasks are shown even with a max depth of 0
+        term_status.set_max_depth(Some(0));
+        let rows = term_status
+            .internal
+            .read()
+            .unwrap()
+            .make_status_rows()
+            .unwrap();
+        k9::assert_equal!(
+            crate::reporters::text::strip_ansi(&rows[0]),
+            " ▶  [ ] root (+4 subtasks)"
+        );
+    }
+
+    #[tokio::test]
+    async fn row_style_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_level_style(Level::L3, RowStyle::Dimmed);
+        term_status.set_tag_style("db", RowStyle::Color(Color::Cyan));
+        term_status.set_max_log_level(Level::L3);
+
+        let root = tt.create_task("root");
+        let query = root.create("query #db #l3");
+        let debug = root.create("debug #l3");
+        tt.flush_async().await;
+
+        let internal = term_status.internal.read().unwrap();
+        let tree = tt.tree_internal.read().unwrap();
+        let style = |task: &crate::Task| internal.row_style(tree.get_task(task.0.id).unwrap());
+        k9::assert_equal!(style(&root), None);
+        k9::assert_equal!(style(&query), Some(RowStyle::Color(Color::Cyan)));
+        k9::assert_equal!(style(&debug), Some(RowStyle::Dimmed));
+    }
+
+    #[tokio::test]
+    async fn render_to_string_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+
+        let root = tt.create_task("root");
+        let _child = root.create("child_with_a_long_name");
+        tt.flush_async().await;
+
+        k9::assert_equal!(
+            term_status.render_to_string(20).unwrap(),
+            " ▶  [ ] root\n╰  ▶  [ ] child_with"
+        );
+    }
+
+    #[tokio::test]
+    async fn unchanged_frame_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+
+        let root = tt.create_task("root");
+        tt.flush_async().await;
+        let mut internal = term_status.internal.write().unwrap();
+        let mut output = vec![];
+        internal.print(&mut output).unwrap();
+        k9::assert_equal!(output.is_empty(), false);
+        // the frame is kept on the screen
+        k9::assert_equal!(internal.frame_changed(), false);
+
+        let _child = root.create("child");
+        k9::assert_equal!(internal.frame_changed(), true);
+    }
+
+    #[tokio::test]
+    async fn paused_task_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+
+        let root = tt.create_task("root");
+        root.pause("waiting for lock");
+        tt.flush_async().await;
+        k9::assert_equal!(
+            term_status.render_to_string(100).unwrap(),
+            " ‖  [ ] root (waiting for lock)"
+        );
+
+        root.resume();
+        k9::assert_equal!(term_status.render_to_string(100).unwrap(), " ▶  [ ] root");
+    }
+
+    #[tokio::test]
+    async fn priority_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+
+        let root = tt.create_task("root");
+        let _a = root.create("a_background #p3");
+        let _b = root.create("b_default");
+        let _c = crate::Task::builder("c_important")
+            .parent(&root)
+            .priority(0)
+            .create();
+        tt.flush_async().await;
+        k9::snapshot!(
+            term_status.render_to_string(100).unwrap(),
+            "
+ ▶  [ ] root
+├  ▶  [ ] c_important
+├  ▶  [ ] b_default
+╰  ▶  [ ] a_background
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn recurring_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+
+        let root = tt.create_task("root");
+        let poll = root.recurring("poll_queue");
+        for _ in 0..3 {
+            poll.run_sync(|_| Ok(())).unwrap();
+        }
+        tt.flush_async().await;
+
+        let rendered = term_status.render_to_string(100).unwrap();
+        let rows = rendered.lines().collect::<Vec<_>>();
+        k9::assert_equal!(rows.len(), 2);
+        k9::assert_equal!(rows[1].starts_with("╰  ▶  [ ] poll_queue ×3, avg "), true);
+    }
+
+    #[tokio::test]
+    async fn scheduled_task_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+
+        let root = tt.create_task("root");
+        let handle = root.spawn_after(Duration::from_millis(300), "retry", |_| async { Ok(()) });
+        tt.flush_async().await;
+        k9::snapshot!(
+            term_status.render_to_string(100).unwrap(),
+            "
+ ▶  [ ] root
+╰  ◷  [ ] retry (scheduled)
+"
+        );
+
+        term_status.set_deterministic(false);
+        let rendered = term_status.render_to_string(100).unwrap();
+        k9::assert_equal!(rendered.contains("retry (starts in 0."), true);
+
+        handle.await.unwrap();
+        let rendered = term_status.render_to_string(100).unwrap();
+        k9::assert_equal!(rendered.contains("✓"), true);
+        k9::assert_equal!(rendered.contains("starts in"), false);
+    }
+
+    #[tokio::test]
+    async fn waits_on_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+
+        let build = tt.create_task("build");
+        let compile = build.create("compile");
+        let test = tt.create_task("test");
+        test.waits_on(&compile);
+        tt.flush_async().await;
+        k9::snapshot!(
+            term_status.render_to_string(100).unwrap(),
+            "
+ ▶  [ ] test ⧗ waiting on build:compile
+ ▶  [ ] build
+╰  ▶  [ ] compile
+"
+        );
+
+        drop(compile);
+        k9::snapshot!(
+            term_status.render_to_string(100).unwrap(),
+            "
+ ▶  [ ] test
+ ▶  [ ] build
+╰  ✓  [ ] compile
+"
+        );
+    }
+
+    #[cfg(feature = "ratatui")]
+    #[tokio::test]
+    async fn ratatui_widget_test() {
+        use ratatui::buffer::Buffer;
+        use ratatui::layout::Rect;
+        use ratatui::widgets::Widget;
+
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_deterministic(true);
+
+        let root = tt.create_task("root");
+        let _a = root.create("a");
+        let _b = root.create("b");
+        tt.flush_async().await;
+
+        let mut buf = Buffer::empty(Rect::new(0, 0, 30, 2));
+        (&term_status).render(buf.area, &mut buf);
+        k9::assert_equal!(
+            buf,
+            Buffer::with_lines(vec![
+                " ▶  [ ] root                  ",
+                ".......2 more tasks.......    ",
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn scoped_view_test() {
+        let tt = TaskTree::new();
+        let root = tt.create_task("root");
+        let library = root.create("library");
+        let _other = root.create("other");
+
+        let term_status = TermStatus::for_task(&library);
+        term_status.set_deterministic(true);
+        term_status.set_footer(true);
+
+        let _running = library.create("running");
+        library.spawn_sync("done", |_| Ok(())).unwrap();
+        root.spawn_sync("outside", |_| Ok(())).unwrap();
+        tt.flush_async().await;
+
+        k9::snapshot!(
+            term_status.render_to_string(100).unwrap(),
+            "
+ ▶  [ ] library
+├  ✓  [ ] done
+╰  ▶  [ ] running
+running: 2 | succeeded: 1 | failed: 0 | elapsed: [ ] | [ ] tasks/s
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn auto_hide_test() {
+        let tt = TaskTree::new();
+        let term_status = TermStatus::new(tt.clone());
+        term_status.set_auto_hide(Some(Duration::from_secs(2)));
+        {
+            let mut internal = term_status.internal.write().unwrap();
+            let task = tt.create_task("task");
+            k9::assert_equal!(internal.should_auto_hide(), false);
+            drop(task);
+            k9::assert_equal!(internal.should_auto_hide(), false);
+            let idle_since = internal.idle_since.unwrap();
+            internal.idle_since = Some(idle_since - Duration::from_secs(3));
+            k9::assert_equal!(internal.should_auto_hide(), true);
+            let _task = tt.create_task("another");
+            k9::assert_equal!(internal.should_auto_hide(), false);
+            k9::assert_equal!(internal.idle_since, None);
+        }
+
+        // the auto hide reporter doesn't keep the status alive
+        let internal = Arc::downgrade(&term_status.internal);
+        drop(term_status);
+        assert!(internal.upgrade().is_none());
     }
 }
@@ -1,12 +1,18 @@
+#[cfg(not(feature = "color"))]
+use super::utils::plain::*;
 use super::Level;
 use super::DONTPRINT_TAG;
 use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use crate::uniq_id::UniqID;
 use chrono::prelude::*;
 use chrono::{DateTime, Local, Utc};
+#[cfg(feature = "color")]
 use colored::*;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex, RwLock};
 
 use super::Reporter;
+pub use super::TaskReportType;
 
 /// Simple drain that logs everything into STDOUT
 pub struct StdioReporter {
@@ -18,6 +24,13 @@ pub struct StdioReporter {
     /// finished
     pub log_task_start: bool,
     pub max_log_level: Level,
+    /// Separate max level for start reports, e.g. to only log starts of
+    /// L0/L1 tasks while logging ends of everything. Falls back to
+    /// `max_log_level` if not set.
+    pub max_start_log_level: Option<Level>,
+    /// Show when data was added relative to the start of the task, e.g.
+    /// `key: value (+2.3s)`
+    pub show_data_offsets: bool,
 }
 
 // Similar to STDOUT drain, but instead logs everything into a string
@@ -28,12 +41,71 @@ pub struct StringReporter {
     timestamp_format: Arc<RwLock<TimestampFormat>>,
     duration_format: Arc<RwLock<DurationFormat>>,
     strip_ansi: bool,
+    /// If set, reports are recorded instead of being written to `output`
+    /// right away, see [set_deterministic()](StringReporter::set_deterministic)
+    deterministic: Arc<Mutex<Option<DeterministicOutput>>>,
+    /// Oldest lines are evicted from `output` when there's more than this
+    max_lines: Arc<RwLock<Option<usize>>>,
+    show_data_offsets: Arc<RwLock<bool>>,
 }
 
-#[derive(Clone, Copy)]
-pub enum TaskReportType {
-    Start,
-    End,
+/// (priority, name, seq, phase) of every task from the root to the reported
+/// one
+type TreePosition = Vec<(u32, String, usize, u8)>;
+
+/// Reports recorded in deterministic mode, keyed by their position in the
+/// task tree so they can be rendered in the same order regardless of timing.
+#[derive(Default)]
+struct DeterministicOutput {
+    /// Stable sequence numbers that are used instead of UniqIDs (which depend
+    /// on everything else that happened in the process) to tell apart sibling
+    /// tasks with the same name.
+    seq: HashMap<UniqID, usize>,
+    /// (priority, name, seq, parent id) of every task reported so far.
+    tasks: HashMap<UniqID, (u32, String, usize, Option<UniqID>)>,
+    reports: Vec<(TreePosition, String)>,
+}
+
+impl DeterministicOutput {
+    fn record(&mut self, task_internal: &TaskInternal, report_type: TaskReportType, line: String) {
+        let next_seq = self.seq.len();
+        let seq = *self.seq.entry(task_internal.id).or_insert(next_seq);
+        let priority = task_internal.priority();
+        self.tasks.entry(task_internal.id).or_insert_with(|| {
+            let name = task_internal.name.clone();
+            (priority, name, seq, task_internal.parent_id)
+        });
+
+        // Position of the report in the tree. Every ancestor is
+        // (priority, name, seq, 2) and the task itself has a phase that puts
+        // its start before its children and the end after them.
+        let phase = match report_type {
+            TaskReportType::Start => 0,
+            TaskReportType::Stalled => 1,
+            TaskReportType::End => 3,
+        };
+        let mut key = vec![(priority, task_internal.name.clone(), seq, phase)];
+        let mut parent_id = task_internal.parent_id;
+        while let Some((priority, name, seq, next_parent_id)) =
+            parent_id.and_then(|id| self.tasks.get(&id))
+        {
+            key.push((*priority, name.clone(), *seq, 2));
+            parent_id = *next_parent_id;
+        }
+        key.reverse();
+        self.reports.push((key, line));
+    }
+
+    fn render(&self) -> String {
+        let mut reports = self.reports.iter().collect::<Vec<_>>();
+        reports.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut result = String::new();
+        for (_, line) in reports {
+            result.push_str(line);
+            result.push('\n');
+        }
+        result
+    }
 }
 
 impl StdioReporter {
@@ -43,23 +115,32 @@ impl StdioReporter {
             use_stdout: false,
             log_task_start: false,
             max_log_level: Level::default(),
+            max_start_log_level: None,
+            show_data_offsets: false,
         }
     }
 
     fn report(&self, task_internal: Arc<TaskInternal>, report_type: TaskReportType) {
         let level = super::utils::parse_level(&task_internal);
+        let max_log_level = match report_type {
+            TaskReportType::Start => self.max_start_log_level.unwrap_or(self.max_log_level),
+            TaskReportType::Stalled | TaskReportType::End => self.max_log_level,
+        };
 
-        if level <= self.max_log_level {
-            if task_internal.tags.contains(DONTPRINT_TAG) {
+        if level <= max_log_level {
+            if task_internal.tags.contains(DONTPRINT_TAG)
+                || super::utils::is_collapsed_occurrence(&task_internal, report_type)
+            {
                 return;
             }
 
             let timestamp_format = self.timestamp_format.unwrap_or(TimestampFormat::UTC);
-            let result = make_string(
+            let result = make_string_with_data_offsets(
                 &task_internal,
                 timestamp_format,
                 DurationFormat::Milliseconds,
                 report_type,
+                self.show_data_offsets,
             );
 
             if self.use_stdout {
@@ -96,6 +177,10 @@ impl Reporter for StdioReporter {
     fn task_end(&self, task_internal: Arc<TaskInternal>) {
         self.report(task_internal, TaskReportType::End)
     }
+
+    fn task_stalled(&self, task_internal: Arc<TaskInternal>) {
+        self.report(task_internal, TaskReportType::Stalled)
+    }
 }
 
 pub fn strip_ansi(s: &str) -> String {
@@ -112,33 +197,87 @@ impl StringReporter {
             timestamp_format: Arc::new(RwLock::new(TimestampFormat::Redacted)),
             duration_format: Arc::new(RwLock::new(DurationFormat::None)),
             strip_ansi: true,
+            deterministic: Arc::new(Mutex::new(None)),
+            max_lines: Arc::new(RwLock::new(None)),
+            show_data_offsets: Arc::new(RwLock::new(false)),
         }
     }
 
     fn report(&self, task_internal: Arc<TaskInternal>, report_type: TaskReportType) {
-        if task_internal.tags.contains(DONTPRINT_TAG) {
+        if task_internal.tags.contains(DONTPRINT_TAG)
+            || super::utils::is_collapsed_occurrence(&task_internal, report_type)
+        {
             return;
         }
         let timestamp_format = *self.timestamp_format.read().unwrap();
         let duration_format = *self.duration_format.read().unwrap();
-        let mut result = make_string(
+        let mut result = make_string_with_data_offsets(
             &task_internal,
             timestamp_format,
             duration_format,
             report_type,
+            *self.show_data_offsets.read().unwrap(),
         );
         if self.strip_ansi {
             result = strip_ansi(&result);
         }
+        if let Some(deterministic) = &mut *self.deterministic.lock().expect("poisoned lock") {
+            deterministic.record(&task_internal, report_type, result);
+            return;
+        }
         let mut output = self.output.lock().expect("poisoned lock");
         output.push_str(&result);
         output.push('\n');
+
+        if let Some(max_lines) = *self.max_lines.read().unwrap() {
+            evict_oldest_lines(&mut output, max_lines);
+        }
+    }
+
+    /// Only keep the last `max_lines` lines of output, e.g. to keep the last
+    /// 10k log lines of a long running process in memory for crash dumps.
+    pub fn set_max_lines(&self, max_lines: Option<usize>) {
+        *self.max_lines.write().unwrap() = max_lines;
+        if let Some(max_lines) = max_lines {
+            evict_oldest_lines(&mut self.output.lock().expect("poisoned lock"), max_lines);
+        }
+    }
+
+    /// Last `n` lines of output
+    pub fn tail(&self, n: usize) -> Vec<String> {
+        let output = self.output.lock().expect("poisoned lock");
+        let mut lines = output
+            .lines()
+            .rev()
+            .take(n)
+            .map(String::from)
+            .collect::<Vec<_>>();
+        lines.reverse();
+        lines
     }
 
     pub fn set_timestamp_format(&self, format: TimestampFormat) {
         *self.timestamp_format.write().unwrap() = format;
     }
 
+    /// Snapshot mode for tests. Timestamps and durations are redacted and
+    /// reports are ordered by their position in the task tree (parents before
+    /// children, siblings by name) rather than by the time they were
+    /// reported, so snapshots don't change when timing of concurrent tasks
+    /// does.
+    pub fn set_deterministic(&self, enabled: bool) {
+        if enabled {
+            self.set_timestamp_format(TimestampFormat::Redacted);
+            self.log_duration(false);
+        }
+        *self.deterministic.lock().unwrap() = enabled.then(DeterministicOutput::default);
+    }
+
+    /// see [StdioReporter::show_data_offsets]
+    pub fn show_data_offsets(&self, enabled: bool) {
+        *self.show_data_offsets.write().unwrap() = enabled;
+    }
+
     pub fn log_duration(&self, enabled: bool) {
         *self.duration_format.write().unwrap() = if enabled {
             DurationFormat::Milliseconds
@@ -156,12 +295,31 @@ impl Reporter for StringReporter {
     fn task_end(&self, task_internal: Arc<TaskInternal>) {
         self.report(task_internal, TaskReportType::End);
     }
+
+    fn task_stalled(&self, task_internal: Arc<TaskInternal>) {
+        self.report(task_internal, TaskReportType::Stalled);
+    }
 }
 
 impl std::fmt::Display for StringReporter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = self.output.lock().expect("poisoned lock");
-        write!(f, "{}", &s)
+        write!(f, "{}", &s)?;
+        if let Some(deterministic) = &*self.deterministic.lock().expect("poisoned lock") {
+            write!(f, "{}", deterministic.render())?;
+        }
+        Ok(())
+    }
+}
+
+fn evict_oldest_lines(output: &mut String, max_lines: usize) {
+    let lines = output.matches('\n').count();
+    if lines > max_lines {
+        let evict_until = output
+            .match_indices('\n')
+            .nth(lines - max_lines - 1)
+            .map_or(0, |(i, _)| i + 1);
+        output.drain(..evict_until);
     }
 }
 
@@ -170,12 +328,33 @@ pub fn make_string(
     timestamp_format: TimestampFormat,
     duration_format: DurationFormat,
     report_type: TaskReportType,
+) -> String {
+    make_string_with_data_offsets(
+        task_internal,
+        timestamp_format,
+        duration_format,
+        report_type,
+        false,
+    )
+}
+
+/// Same as [make_string], but data entries that are in the data timeline
+/// are followed by when they were added, relative to the start of the task.
+pub fn make_string_with_data_offsets(
+    task_internal: &TaskInternal,
+    timestamp_format: TimestampFormat,
+    duration_format: DurationFormat,
+    report_type: TaskReportType,
+    data_offsets: bool,
 ) -> String {
     let timestamp = format_timestamp(timestamp_format, task_internal, report_type);
     let status = format_status(task_internal, duration_format, report_type);
     let name = format_name(task_internal, report_type);
     let (mut data, error) = if let TaskReportType::End = report_type {
-        (format_data(task_internal), format_error(task_internal))
+        (
+            format_data(task_internal, data_offsets),
+            format_error(task_internal),
+        )
     } else {
         (String::new(), String::new())
     };
@@ -196,6 +375,7 @@ fn format_timestamp(
 ) -> String {
     let datetime: Option<DateTime<Utc>> = match report_type {
         TaskReportType::Start => Some(task_internal.started_at.into()),
+        TaskReportType::Stalled => Some(Utc::now()),
         TaskReportType::End => {
             if let TaskStatus::Finished(_, at) = task_internal.status {
                 Some(at.into())
@@ -235,6 +415,7 @@ fn format_name(task_internal: &TaskInternal, report_type: TaskReportType) -> Col
             format!("[ERR] {}", task_internal.full_name()).red()
         }
         (_, TaskReportType::Start) => task_internal.full_name().yellow(),
+        (_, TaskReportType::Stalled) => task_internal.full_name().magenta(),
         (_, TaskReportType::End) => task_internal.full_name().green(),
     }
 }
@@ -246,6 +427,7 @@ fn format_status(
 ) -> String {
     match report_type {
         TaskReportType::Start => format!("| {} | ", "STARTING".yellow()),
+        TaskReportType::Stalled => format!("| {} | ", "STALLED ".magenta()),
         // If it's the end of the task, we'll print a timestamp
         TaskReportType::End => {
             if let TaskStatus::Finished(_, finished_at) = task_internal.status {
@@ -267,7 +449,7 @@ fn format_status(
     }
 }
 
-fn format_data(task_internal: &TaskInternal) -> String {
+fn format_data(task_internal: &TaskInternal, data_offsets: bool) -> String {
     let mut result = String::new();
     let mut data = vec![];
     for (k, entry) in task_internal.all_data() {
@@ -275,7 +457,22 @@ fn format_data(task_internal: &TaskInternal) -> String {
             continue;
         }
 
-        data.push(format!("  |      {}: {}", k, entry.0).dimmed().to_string());
+        let added_at = task_internal
+            .data
+            .added_at(k)
+            .or_else(|| task_internal.data_transitive.added_at(k));
+        let offset = match added_at.map(|at| at.duration_since(task_internal.started_at)) {
+            Some(Ok(offset)) if data_offsets => {
+                format!(" (+{}.{}s)", offset.as_secs(), offset.subsec_millis() / 100)
+            }
+            _ => String::new(),
+        };
+
+        data.push(
+            format!("  |      {}: {}{}", k, entry.0, offset)
+                .dimmed()
+                .to_string(),
+        );
     }
 
     if !data.is_empty() {
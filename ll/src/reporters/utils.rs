@@ -1,4 +1,6 @@
-use super::Level;
+use super::{Level, TaskReportType};
+use crate::recurring::OCCURRENCE_TAG;
+use crate::task_tree::{TaskResult, TaskStatus};
 use crate::TaskInternal;
 
 pub fn parse_level(task_internal: &TaskInternal) -> Level {
@@ -15,3 +17,55 @@ pub fn parse_level(task_internal: &TaskInternal) -> Level {
 
     all_level_tags.into_iter().min().unwrap_or(Level::L1)
 }
+
+/// Occurrences of [recurring](crate::recurring) tasks are collapsed into
+/// their parent, unless they failed.
+pub fn is_collapsed_occurrence(task_internal: &TaskInternal, report_type: TaskReportType) -> bool {
+    if !task_internal.tags.contains(OCCURRENCE_TAG) {
+        return false;
+    }
+    let failed = matches!(
+        task_internal.status,
+        TaskStatus::Finished(TaskResult::Failure(_), _)
+    );
+    !(failed && report_type == TaskReportType::End)
+}
+
+/// Stand-in for `colored` when the `color` feature is disabled, so reporters
+/// make the same calls and print plain text
+#[cfg(not(feature = "color"))]
+pub(crate) mod plain {
+    use std::fmt::Display;
+
+    pub type ColoredString = String;
+
+    pub trait Colorize {
+        fn red(self) -> String;
+        fn green(self) -> String;
+        fn yellow(self) -> String;
+        fn magenta(self) -> String;
+        fn bold(self) -> String;
+        fn dimmed(self) -> String;
+    }
+
+    impl<T: Display> Colorize for T {
+        fn red(self) -> String {
+            self.to_string()
+        }
+        fn green(self) -> String {
+            self.to_string()
+        }
+        fn yellow(self) -> String {
+            self.to_string()
+        }
+        fn magenta(self) -> String {
+            self.to_string()
+        }
+        fn bold(self) -> String {
+            self.to_string()
+        }
+        fn dimmed(self) -> String {
+            self.to_string()
+        }
+    }
+}
@@ -0,0 +1,189 @@
+/*!
+Retention of files written by reporters (JSONL logs, audit logs, JUnit
+reports, attachments, ...) so long running daemons don't fill up the disk.
+A [RetentionPolicy] deletes the oldest files in a directory once they are
+older than a maximum age or take more than a maximum total size.
+
+```no_run
+use ll::retention::RetentionPolicy;
+use std::time::Duration;
+
+let _cleanup = RetentionPolicy::new()
+    .matching("*.jsonl*")
+    .max_total_bytes(500 * 1024 * 1024)
+    .max_age(Duration::from_secs(7 * 24 * 3600))
+    .spawn_cleanup("/var/log/my-daemon", Duration::from_secs(60));
+```
+*/
+use crate::test::name_matches;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Debug)]
+pub struct RetentionPolicy {
+    pattern: Option<String>,
+    max_total_bytes: Option<u64>,
+    max_age: Option<Duration>,
+}
+
+/// Stops the background cleanup when dropped, see
+/// [RetentionPolicy::spawn_cleanup()]
+pub struct CleanupHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl Drop for CleanupHandle {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl RetentionPolicy {
+    /// Policy that keeps everything
+    pub fn new() -> Self {
+        Self {
+            pattern: None,
+            max_total_bytes: None,
+            max_age: None,
+        }
+    }
+
+    /// Only manage files with names matching the pattern, where `*` matches
+    /// any sequence of characters. By default all files in the directory
+    /// are managed.
+    pub fn matching<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.pattern = Some(pattern.into());
+        self
+    }
+
+    pub fn max_total_bytes(mut self, max_total_bytes: u64) -> Self {
+        self.max_total_bytes = Some(max_total_bytes);
+        self
+    }
+
+    /// Age is based on the last modification time of the file
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Deletes files in `dir` that are over the limits, oldest first, and
+    /// returns their paths. The most recently modified file is always kept,
+    /// since it's most likely still being written to.
+    pub fn cleanup<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        let mut files = vec![];
+        let entries =
+            std::fs::read_dir(dir).with_context(|| format!("can't read {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(pattern) = &self.pattern {
+                if !name_matches(pattern, &name) {
+                    continue;
+                }
+            }
+            files.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+        // newest first
+        files.sort_by(|a, b| b.cmp(a));
+
+        let now = SystemTime::now();
+        let mut total_bytes = 0;
+        let mut deleted = vec![];
+        for (i, (modified, len, path)) in files.into_iter().enumerate() {
+            total_bytes += len;
+            let too_old = self
+                .max_age
+                .is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age);
+            let too_big = self
+                .max_total_bytes
+                .is_some_and(|max_total_bytes| total_bytes > max_total_bytes);
+            if i > 0 && (too_old || too_big) {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("can't delete {}", path.display()))?;
+                deleted.push(path);
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Runs [cleanup()](RetentionPolicy::cleanup) on `dir` every `interval`
+    /// in a background thread until the returned handle is dropped. Errors
+    /// are printed to stderr.
+    pub fn spawn_cleanup<P: Into<PathBuf>>(self, dir: P, interval: Duration) -> CleanupHandle {
+        let dir = dir.into();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = stopped.clone();
+        thread::spawn(move || {
+            while !stopped_clone.load(Ordering::SeqCst) {
+                if let Err(err) = self.cleanup(&dir) {
+                    eprintln!("[ll] retention cleanup failed: {:?}", err);
+                }
+                thread::sleep(interval);
+            }
+        });
+        CleanupHandle { stopped }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file(dir: &Path, name: &str, len: usize, age: Duration) {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![b'x'; len]).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    fn file_names(paths: Vec<PathBuf>) -> Vec<String> {
+        let mut names = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn retention_test() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("ll-retention-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let hour = Duration::from_secs(3600);
+        write_file(&dir, "log.1.jsonl", 100, hour * 30);
+        write_file(&dir, "log.2.jsonl", 100, hour * 3);
+        write_file(&dir, "log.3.jsonl", 100, hour * 2);
+        write_file(&dir, "log.4.jsonl", 100, hour);
+        write_file(&dir, "keep.txt", 100, hour * 50);
+
+        let policy = RetentionPolicy::new()
+            .matching("*.jsonl")
+            .max_age(hour * 24)
+            .max_total_bytes(250);
+        k9::assert_equal!(
+            file_names(policy.cleanup(&dir)?),
+            vec!["log.1.jsonl", "log.2.jsonl"]
+        );
+        k9::assert_equal!(policy.cleanup(&dir)?.len(), 0);
+
+        // the newest file is kept even if it's over the limits on its own
+        let policy = RetentionPolicy::new().max_total_bytes(10);
+        k9::assert_equal!(
+            file_names(policy.cleanup(&dir)?),
+            vec!["keep.txt", "log.3.jsonl"]
+        );
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
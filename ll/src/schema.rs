@@ -0,0 +1,104 @@
+/*!
+Validation of task data. A [DataSchema] lists data keys (and their types)
+that every task matching a name pattern must have by the time it finishes.
+see [TaskTree::add_data_schema()](crate::task_tree::TaskTree::add_data_schema)
+
+```
+# #[tokio::main]
+# async fn main() {
+use ll::schema::{DataSchema, DataType};
+
+let tree = ll::TaskTree::new();
+tree.add_data_schema(
+    "*http_request",
+    DataSchema::new()
+        .required("url", DataType::String)
+        .required("status", DataType::Int)
+        .strict(true),
+);
+# }
+```
+*/
+use crate::data::DataValue;
+use crate::task_tree::TaskInternal;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataType {
+    String,
+    Int,
+    Float,
+    Quantity,
+    Json,
+    /// Any value, the key only needs to be present
+    Any,
+}
+
+impl DataType {
+    fn matches(&self, value: &DataValue) -> bool {
+        matches!(
+            (self, value.reveal()),
+            (DataType::Any, _)
+                | (DataType::String, DataValue::String(_))
+                | (DataType::Int, DataValue::Int(_))
+                | (DataType::Float, DataValue::Float(_))
+                | (DataType::Quantity, DataValue::Quantity(..))
+                | (DataType::Json, DataValue::Json(_))
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DataSchema {
+    required: Vec<(String, DataType)>,
+    strict: bool,
+}
+
+impl DataSchema {
+    pub fn new() -> Self {
+        Self {
+            required: vec![],
+            strict: false,
+        }
+    }
+
+    /// Require `key` to be present (in data or transitive data) with a
+    /// value of type `data_type`.
+    pub fn required<S: Into<String>>(mut self, key: S, data_type: DataType) -> Self {
+        self.required.push((key.into(), data_type));
+        self
+    }
+
+    /// By default violations are printed to stderr as warnings. In strict
+    /// mode (useful in tests) a task violating the schema is reported as
+    /// failed instead.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Human readable descriptions of everything that's wrong with the data
+    /// of the task. Empty if the task is valid.
+    pub fn violations(&self, task: &TaskInternal) -> Vec<String> {
+        let mut violations = vec![];
+        for (key, data_type) in &self.required {
+            let value = task
+                .data
+                .map
+                .get(key)
+                .or_else(|| task.data_transitive.map.get(key));
+            match value {
+                None => violations.push(format!("missing required data `{}`", key)),
+                Some(entry) if !data_type.matches(&entry.0) => violations.push(format!(
+                    "data `{}` is expected to be {:?}, got `{}`",
+                    key, data_type, entry.0
+                )),
+                Some(_) => {}
+            }
+        }
+        violations
+    }
+}
@@ -0,0 +1,150 @@
+/*!
+Hooks that make sure the last events before a crash or Ctrl-C reach the
+reporters. On SIGINT/SIGTERM (and, if enabled, a panic) every task that
+is still running is marked as failed with `interrupted: <reason>`, all
+reporters are flushed and, optionally, a [trace](crate::trace::Trace) of
+the task tree is written to a file.
+
+```
+# #[tokio::main]
+# async fn main() {
+ll::shutdown::hooks().dump_trace("crash_trace.json").install();
+# }
+```
+
+The panic hook is off by default. It treats every panic as fatal, so only
+enable it with [panic(true)](ShutdownHooks::panic) in applications that
+don't recover from panics (unlike e.g. servers relying on tokio catching
+panics of spawned tasks).
+*/
+use crate::task_tree::{TaskTree, TASK_TREE};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long hooks wait for tasks to be marked and reports to be flushed.
+/// The panicking thread might be holding a lock that's needed for it, in
+/// which case the hook gives up instead of hanging.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Hooks for the global task tree
+pub fn hooks() -> ShutdownHooks {
+    ShutdownHooks::for_tree(TASK_TREE.clone())
+}
+
+pub struct ShutdownHooks {
+    task_tree: Arc<TaskTree>,
+    panic: bool,
+    signals: bool,
+    trace_path: Option<PathBuf>,
+}
+
+impl ShutdownHooks {
+    pub fn for_tree(task_tree: Arc<TaskTree>) -> Self {
+        Self {
+            task_tree,
+            panic: false,
+            signals: true,
+            trace_path: None,
+        }
+    }
+
+    /// Install the panic hook (off by default). The previously installed
+    /// hook still runs after it. Every panic, even one that is caught
+    /// later, fails all running tasks, so only enable it if panics abort
+    /// the process.
+    pub fn panic(mut self, enabled: bool) -> Self {
+        self.panic = enabled;
+        self
+    }
+
+    /// Handle SIGINT (Ctrl-C) and SIGTERM (on by default). After flushing,
+    /// the process exits with `128 + <signal number>`. Requires a tokio
+    /// runtime.
+    pub fn signals(mut self, enabled: bool) -> Self {
+        self.signals = enabled;
+        self
+    }
+
+    /// Also write a JSON trace of the task tree to `path`
+    pub fn dump_trace<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.trace_path = Some(path.into());
+        self
+    }
+
+    pub fn install(self) {
+        let shutdown = Arc::new(Shutdown {
+            task_tree: self.task_tree,
+            trace_path: self.trace_path,
+        });
+
+        if self.panic {
+            let clone = shutdown.clone();
+            let previous_hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                clone.run(format!("panic: {}", panic_message(info)));
+                previous_hook(info);
+            }));
+        }
+
+        if self.signals {
+            tokio::spawn(async move {
+                let (reason, exit_code) = wait_for_signal().await;
+                shutdown.run(reason.to_string());
+                std::process::exit(exit_code);
+            });
+        }
+    }
+}
+
+struct Shutdown {
+    task_tree: Arc<TaskTree>,
+    trace_path: Option<PathBuf>,
+}
+
+impl Shutdown {
+    fn run(self: &Arc<Self>, reason: String) {
+        let clone = self.clone();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            clone.task_tree.interrupt_running(&reason);
+            clone.task_tree.flush_blocking();
+            if let Some(path) = &clone.trace_path {
+                let trace = clone.task_tree.trace();
+                if let Err(err) = std::fs::write(path, trace.to_json_pretty()) {
+                    eprintln!("[ll] can't write trace to {}: {}", path.display(), err);
+                }
+            }
+            sender.send(()).ok();
+        });
+        if receiver.recv_timeout(FLUSH_TIMEOUT).is_err() {
+            eprintln!("[ll] timed out flushing reports on shutdown");
+        }
+    }
+}
+
+fn panic_message(info: &std::panic::PanicHookInfo) -> String {
+    let payload = info.payload();
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string())
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> (&'static str, i32) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("can't listen to SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => ("SIGINT", 130),
+        _ = terminate.recv() => ("SIGTERM", 143),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> (&'static str, i32) {
+    tokio::signal::ctrl_c().await.ok();
+    ("SIGINT", 130)
+}
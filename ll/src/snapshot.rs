@@ -0,0 +1,154 @@
+/*!
+"Black box recorder" for long running processes. A [SnapshotWriter]
+periodically writes a [trace](crate::trace::Trace) of everything that is
+currently running in the task tree to a file, so there's something to
+inspect after the process hangs or gets OOM killed.
+
+```no_run
+use ll::snapshot::SnapshotWriter;
+use std::time::Duration;
+
+let _snapshots = SnapshotWriter::new(ll::task_tree::TASK_TREE.clone(), "/var/log/my-daemon/tasks.json")
+    .interval(Duration::from_secs(30))
+    .spawn();
+```
+
+Snapshots replace each other by default. With
+[timestamped()](SnapshotWriter::timestamped) every snapshot goes to its own
+file, which can be pruned with a
+[RetentionPolicy](crate::retention::RetentionPolicy).
+*/
+use crate::task_tree::TaskTree;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+pub const DEFAULT_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct SnapshotWriter {
+    task_tree: Arc<TaskTree>,
+    path: PathBuf,
+    interval: Duration,
+    timestamped: bool,
+}
+
+/// Stops writing snapshots when dropped, see [SnapshotWriter::spawn()]
+pub struct SnapshotHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl Drop for SnapshotHandle {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl SnapshotWriter {
+    pub fn new<P: Into<PathBuf>>(task_tree: Arc<TaskTree>, path: P) -> Self {
+        Self {
+            task_tree,
+            path: path.into(),
+            interval: DEFAULT_SNAPSHOT_INTERVAL,
+            timestamped: false,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Write every snapshot to a new file with the time in milliseconds
+    /// since the unix epoch added to its name, e.g. `tasks-1700000000000.json`
+    /// for `tasks.json`.
+    pub fn timestamped(mut self, enabled: bool) -> Self {
+        self.timestamped = enabled;
+        self
+    }
+
+    /// Write a snapshot right away and return the path it was written to.
+    /// The file is replaced atomically, so a crash in the middle of writing
+    /// doesn't destroy the previous snapshot.
+    pub fn write_now(&self) -> Result<PathBuf> {
+        let path = if self.timestamped {
+            let millis = self
+                .task_tree
+                .now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            timestamped_path(&self.path, millis)
+        } else {
+            self.path.clone()
+        };
+        let json = self.task_tree.trace().to_json_pretty();
+
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, json)
+            .with_context(|| format!("can't write snapshot to {}", path.display()))?;
+        std::fs::rename(&tmp_path, &path)
+            .with_context(|| format!("can't write snapshot to {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Writes a snapshot every interval in a background thread until the
+    /// returned handle is dropped. Errors are printed to stderr.
+    pub fn spawn(self) -> SnapshotHandle {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = stopped.clone();
+        thread::spawn(move || {
+            while !stopped_clone.load(Ordering::SeqCst) {
+                if let Err(err) = self.write_now() {
+                    eprintln!("[ll] {:?}", err);
+                }
+                thread::sleep(self.interval);
+            }
+        });
+        SnapshotHandle { stopped }
+    }
+}
+
+fn timestamped_path(path: &Path, millis: u128) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, millis, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, millis),
+    };
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::trace::Trace;
+
+    #[tokio::test]
+    async fn snapshot_writer_test() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("ll-snapshot-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let tt = TaskTree::new();
+        tt.set_clock(Arc::new(ManualClock::new(
+            UNIX_EPOCH + Duration::from_millis(1500),
+        )));
+        let _root = tt.create_task("root");
+
+        let writer = SnapshotWriter::new(tt.clone(), dir.join("tasks.json"));
+        let path = writer.write_now()?;
+        k9::assert_equal!(path, dir.join("tasks.json"));
+        let trace = Trace::from_json(&std::fs::read_to_string(&path)?)?;
+        k9::assert_equal!(trace.tasks.len(), 1);
+        k9::assert_equal!(trace.tasks[0].name, "root");
+
+        let path = writer.timestamped(true).write_now()?;
+        k9::assert_equal!(path, dir.join("tasks-1500.json"));
+        k9::assert_equal!(std::fs::read_dir(&dir)?.count(), 2);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+/*!
+Typed tags. Tags can be added to tasks with hashtags in their names
+(`"query #db"`), but defining them as constants lets the compiler catch typos.
+
+```
+use ll::{Tag, Task};
+
+const DB: Tag = Tag::new("db");
+
+# #[tokio::main]
+# async fn main() -> anyhow::Result<()> {
+let root = Task::create_new("root");
+Task::builder("query")
+    .parent(&root)
+    .tag(DB)
+    .spawn_sync(|_| Ok(()))?;
+# Ok(())
+# }
+```
+*/
+use crate::attachment::ATTACHMENT_TAG;
+use crate::reporters::DONTPRINT_TAG;
+use crate::reporters::NOSTATUS_TAG;
+
+/// Tags starting with this put the task into a group (`#group:io`), see
+/// [TaskBuilder::group()](crate::TaskBuilder::group)
+pub const GROUP_TAG_PREFIX: &str = "group:";
+
+/// Don't report the task (same as `#dontprint`)
+pub const DONTPRINT: Tag = Tag::new(DONTPRINT_TAG);
+/// Don't show the task in the terminal status (same as `#nostatus`)
+pub const NOSTATUS: Tag = Tag::new(NOSTATUS_TAG);
+/// Tag of data entries that reference a [task attachment](crate::Task::attach)
+pub const ATTACHMENT: Tag = Tag::new(ATTACHMENT_TAG);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tag(&'static str);
+
+impl Tag {
+    pub const fn new(name: &'static str) -> Self {
+        Self(name)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl AsRef<str> for Tag {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<Tag> for String {
+    fn from(tag: Tag) -> String {
+        tag.0.to_string()
+    }
+}
@@ -1,9 +1,15 @@
-use crate::data::DataValue;
+use crate::attachment::AttachmentContent;
+use crate::data::{DataEntry, DataValue};
+use crate::recurring::{Recurring, RECURRING_TAG};
+use crate::task_builder::TaskBuilder;
 use crate::task_tree::{TaskTree, TASK_TREE};
 use crate::uniq_id::UniqID;
 use anyhow::Result;
 use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 
 pub type MarkDoneOnDrop = bool;
 
@@ -14,6 +20,10 @@ pub(crate) struct TaskData {
     pub(crate) id: UniqID,
     pub(crate) task_tree: Arc<TaskTree>,
     pub(crate) mark_done_on_drop: MarkDoneOnDrop,
+    /// Prefix added to the names of subtasks. see [Task::nest()]
+    pub(crate) name_prefix: Option<String>,
+    /// Task this one is a nested view of, kept alive while the view exists.
+    pub(crate) _nested_in: Option<Task>,
 }
 
 impl Task {
@@ -23,18 +33,75 @@ impl Task {
             id,
             task_tree: TASK_TREE.clone(),
             mark_done_on_drop: true,
+            name_prefix: None,
+            _nested_in: None,
         }))
     }
 
+    /// Configure a new task with typed options instead of hashtags in its
+    /// name. see [TaskBuilder]
+    pub fn builder<S: Into<String>>(name: S) -> TaskBuilder {
+        TaskBuilder::new(name.into())
+    }
+
+    /// Handle to the same task that prefixes names of all subtasks spawned
+    /// through it with `prefix.`, e.g. `task.nest("db").spawn("query", ..)`
+    /// creates a subtask called `db.query`. Nesting multiple times joins the
+    /// prefixes (`db.pool.connect`).
+    pub fn nest(&self, prefix: &str) -> Self {
+        Self(Arc::new(TaskData {
+            id: self.0.id,
+            task_tree: self.0.task_tree.clone(),
+            mark_done_on_drop: false,
+            name_prefix: Some(self.child_name(prefix)),
+            _nested_in: Some(self.clone()),
+        }))
+    }
+
+    pub(crate) fn child_name<S: Into<String>>(&self, name: S) -> String {
+        match &self.0.name_prefix {
+            Some(prefix) => format!("{}.{}", prefix, name.into()),
+            None => name.into(),
+        }
+    }
+
     pub fn create(&self, name: &str) -> Self {
-        let id = self.0.task_tree.create_task_internal(name, Some(self.0.id));
+        let id = self
+            .0
+            .task_tree
+            .create_task_internal(self.child_name(name), Some(self.0.id));
         Self(Arc::new(TaskData {
             id,
             task_tree: self.0.task_tree.clone(),
             mark_done_on_drop: true,
+            name_prefix: None,
+            _nested_in: None,
         }))
     }
 
+    /// Start a subtask that finishes when the returned guard is finished
+    /// explicitly or dropped. Unlike [spawn()](Task::spawn) it doesn't need a
+    /// closure, which is useful with early returns or when the task lifetime
+    /// is tied to a struct rather than a lexical scope.
+    ///
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let root = ll::Task::create_new("root");
+    /// let t = root.start("load_config");
+    /// t.data("path", "/etc/config");
+    /// t.success();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn start<S: Into<String>>(&self, name: S) -> TaskGuard {
+        let id = self
+            .0
+            .task_tree
+            .create_task_internal(self.child_name(name), Some(self.0.id));
+        TaskGuard::new(self.0.task_tree.spawned_task(id))
+    }
+
     /// Spawn a new top level task, with no parent.
     /// This should usually be done in the very beginning of
     /// the process/application.
@@ -55,7 +122,7 @@ impl Task {
     {
         self.0
             .task_tree
-            .spawn(name.into(), f, Some(self.0.id))
+            .spawn(self.child_name(name), f, Some(self.0.id))
             .await
     }
 
@@ -64,13 +131,264 @@ impl Task {
         F: FnOnce(Task) -> Result<T>,
         T: Send,
     {
-        self.0.task_tree.spawn_sync(name.into(), f, Some(self.0.id))
+        self.0
+            .task_tree
+            .spawn_sync(self.child_name(name), f, Some(self.0.id))
+    }
+
+    /// Spawn a subtask on the tokio runtime right away and return a handle
+    /// that can be awaited later to get its result. The subtask is created
+    /// immediately, so it is parented correctly even if this task finishes
+    /// before the subtask gets to run.
+    pub fn spawn_detached<F, FT, T, S: Into<String>>(&self, name: S, f: F) -> TaskJoinHandle<T>
+    where
+        F: FnOnce(Task) -> FT + Send + 'static,
+        FT: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.0
+            .task_tree
+            .spawn_detached(self.child_name(name), f, Some(self.0.id))
+    }
+
+    /// Create a subtask right away but only start running it after `delay`,
+    /// so planned work (e.g. a retry with backoff) is visible before it
+    /// runs. `TermStatus` shows a countdown for it.
+    pub fn spawn_after<F, FT, T, S: Into<String>>(
+        &self,
+        delay: std::time::Duration,
+        name: S,
+        f: F,
+    ) -> TaskJoinHandle<T>
+    where
+        F: FnOnce(Task) -> FT + Send + 'static,
+        FT: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.0
+            .task_tree
+            .spawn_after(delay, self.child_name(name), f, Some(self.0.id))
+    }
+
+    /// Spawn a group of subtasks that run concurrently and wait for all of
+    /// them to finish. Results are returned in the order tasks were spawned.
+    /// If any of the subtasks fail, the error of the first failed one is
+    /// returned with the errors of the rest attached to it as context.
+    /// Subtasks never outlive the scope: if the returned future is dropped
+    /// (e.g. cancelled by a timeout) before they finish, they are aborted.
+    ///
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let root = ll::Task::create_new("root");
+    /// let sizes = root
+    ///     .scope(|s| {
+    ///         s.spawn("a", |_| async { Ok(1) });
+    ///         s.spawn("b", |_| async { Ok(2) });
+    ///     })
+    ///     .await?;
+    /// assert_eq!(sizes, vec![1, 2]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn scope<F, T>(&self, f: F) -> Result<Vec<T>>
+    where
+        F: FnOnce(&mut Scope<T>),
+        T: Send + 'static,
+    {
+        let mut scope = Scope {
+            parent: self.clone(),
+            handles: vec![],
+        };
+        f(&mut scope);
+        let _abort_guard = AbortOnDrop(
+            scope
+                .handles
+                .iter()
+                .map(|handle| handle.0.abort_handle())
+                .collect(),
+        );
+
+        let total = scope.handles.len();
+        let mut results = vec![];
+        let mut errors = vec![];
+        for handle in scope.handles {
+            match handle.await {
+                Ok(value) => results.push(value),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        let mut errors = errors.into_iter();
+        match errors.next() {
+            None => Ok(results),
+            Some(first) => {
+                let failed = errors.len() + 1;
+                let rest = errors.map(|err| format!("{:#}", err)).collect::<Vec<_>>();
+                let msg = if rest.is_empty() {
+                    format!("1 of {} tasks in scope failed", total)
+                } else {
+                    format!(
+                        "{} of {} tasks in scope failed, other errors:\n{}",
+                        failed,
+                        total,
+                        rest.join("\n")
+                    )
+                };
+                Err(first.context(msg))
+            }
+        }
+    }
+
+    /// Process `items` in a `name` subtask, running `f` for every item in
+    /// its own `item_<index>` subtask with at most `concurrency` of them at
+    /// the same time (see [concurrency_limit()](Task::concurrency_limit)).
+    /// Progress of the `name` task is driven by its finished items. All items
+    /// are processed even if some of them fail, and failures are aggregated
+    /// the same way as in [scope()](Task::scope).
+    ///
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let root = ll::Task::create_new("root");
+    /// let lengths = root
+    ///     .spawn_map("measure", vec!["a", "bb"], 4, |item, _task| async move {
+    ///         Ok(item.len())
+    ///     })
+    ///     .await?;
+    /// assert_eq!(lengths, vec![1, 2]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn spawn_map<I, F, FT, T, S>(
+        &self,
+        name: S,
+        items: I,
+        concurrency: usize,
+        f: F,
+    ) -> Result<Vec<T>>
+    where
+        S: Into<String>,
+        I: IntoIterator + Send,
+        I::Item: Send + 'static,
+        F: Fn(I::Item, Task) -> FT + Send + Sync + 'static,
+        FT: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn(name, |task| async move {
+            task.concurrency_limit(concurrency.max(1));
+            task.aggregate_progress(true);
+            let f = Arc::new(f);
+            task.scope(|s| {
+                for (i, item) in items.into_iter().enumerate() {
+                    let f = f.clone();
+                    s.spawn(format!("item_{}", i), move |task| f(item, task));
+                }
+            })
+            .await
+        })
+        .await
+    }
+
+    /// Same as [spawn()](Task::spawn) but for tasks that can't fail.
+    /// [Injected faults](crate::task_tree::TaskTree::inject_fault) only
+    /// delay the task, and a [fail_fast()](Task::fail_fast) parent lets it
+    /// run to completion.
+    pub async fn spawn_ok<F, FT, T, S: Into<String>>(&self, name: S, f: F) -> T
+    where
+        F: FnOnce(Task) -> FT,
+        FT: Future<Output = T> + Send,
+        T: Send,
+    {
+        let tree = &self.0.task_tree;
+        let task = tree.pre_spawn_infallible(self.child_name(name), Some(self.0.id));
+        tree.run_spawned(task, |task| {
+            let future = f(task);
+            async move { Ok(future.await) }
+        })
+        .await
+        // neither faults nor fail-fast parents can make it fail
+        .expect("infallible task failed")
+    }
+
+    /// Same as [spawn_sync()](Task::spawn_sync) but for tasks that can't fail.
+    /// [Injected faults](crate::task_tree::TaskTree::inject_fault) only
+    /// delay the task.
+    pub fn spawn_sync_ok<F, T, S: Into<String>>(&self, name: S, f: F) -> T
+    where
+        F: FnOnce(Task) -> T,
+        T: Send,
+    {
+        let tree = &self.0.task_tree;
+        let task = tree.pre_spawn_infallible(self.child_name(name), Some(self.0.id));
+        tree.run_spawned_sync(task, |task| Ok(f(task)))
+            .expect("infallible task failed")
     }
 
     pub fn data<D: Into<DataValue>>(&self, name: &str, data: D) {
         self.0.task_tree.add_data(self.0.id, name, data);
     }
 
+    /// Add any serializable value to the task data, either as a single
+    /// structured entry or flattened into one entry per field depending on
+    /// [TaskTree::set_serde_data_mode()](crate::TaskTree::set_serde_data_mode).
+    pub fn data_serde<T: serde::Serialize + ?Sized>(&self, name: &str, value: &T) -> Result<()> {
+        self.0
+            .task_tree
+            .add_data_serde_for_task(self.0.id, name, value)
+    }
+
+    /// Run a child process and capture its output into this task. Every
+    /// line written to stdout or stderr is recorded as `stdout`/`stderr`
+    /// data and the exit code as `exit_code`. Each line replaces the
+    /// previous one, and only the latest changes are kept in the
+    /// [data timeline](crate::data::Data::timeline), so use
+    /// [attach()](Task::attach) for output that has to be kept in full.
+    /// Invalid UTF-8 is replaced with `U+FFFD`. Fails if the process exits
+    /// unsuccessfully, so returning the result from a spawned task marks
+    /// it as failed.
+    pub async fn capture_child(
+        &self,
+        command: &mut tokio::process::Command,
+    ) -> Result<std::process::ExitStatus> {
+        crate::process::capture_child(self, command).await
+    }
+
+    /// Run `command` in a subtask called `name` with the default
+    /// [CommandOptions](crate::process::CommandOptions).
+    /// see [run_command_with()](Task::run_command_with)
+    pub async fn run_command<S: Into<String>>(
+        &self,
+        name: S,
+        command: tokio::process::Command,
+    ) -> Result<std::process::ExitStatus> {
+        self.run_command_with(name, command, Default::default())
+            .await
+    }
+
+    /// Run `command` in a `#command` subtask called `name`. The subtask
+    /// has the command line, exit code, duration and the last
+    /// [max_output_len](crate::process::CommandOptions::max_output_len)
+    /// bytes of output as data and fails if the command does.
+    pub async fn run_command_with<S: Into<String>>(
+        &self,
+        name: S,
+        command: tokio::process::Command,
+        options: crate::process::CommandOptions,
+    ) -> Result<std::process::ExitStatus> {
+        crate::process::run_command(self, name.into(), command, options).await
+    }
+
+    /// Store a large blob (e.g. full output of a command) out of band and add
+    /// a reference to it to the task data under `name`. Fails if no
+    /// [attachment store](crate::task_tree::TaskTree::set_attachment_store)
+    /// is set. see [attachment](crate::attachment)
+    pub fn attach<C: Into<AttachmentContent>>(&self, name: &str, content: C) -> Result<()> {
+        self.0
+            .task_tree
+            .attach_for_task(self.0.id, name, content.into())
+    }
+
     /// Get a piece of previously set data or transitive data. This can be
     /// useful if session/request tracking IDs need to be past to other loggers,
     /// e.g. when shelling out to another process that needs to set the same
@@ -85,10 +403,140 @@ impl Task {
             .add_data_transitive_for_task(self.0.id, name, data);
     }
 
+    /// Remove a transitive key from this task. Children created afterwards
+    /// won't inherit it, even if it was set on one of the ancestors or on
+    /// the whole tree.
+    pub fn clear_data_transitive(&self, name: &str) {
+        self.0
+            .task_tree
+            .clear_data_transitive_for_task(self.0.id, name);
+    }
+
+    /// Shadow a transitive key with a different value while `f` runs.
+    /// Children created inside `f` inherit the new value, and the previous
+    /// value (or its absence) is restored afterwards, even if `f` panics.
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let task = ll::Task::create_new("request");
+    /// task.data_transitive("request_id", 1);
+    /// task.data_transitive_scoped("request_id", 2, || {
+    ///     let retry = task.create("retry");
+    ///     assert_eq!(retry.get_data("request_id"), Some(2.into()));
+    /// });
+    /// assert_eq!(task.get_data("request_id"), Some(1.into()));
+    /// # }
+    /// ```
+    pub fn data_transitive_scoped<D, F, T>(&self, name: &str, data: D, f: F) -> T
+    where
+        D: Into<DataValue>,
+        F: FnOnce() -> T,
+    {
+        let tree = &self.0.task_tree;
+        let previous = tree.clear_data_transitive_for_task(self.0.id, name);
+        tree.add_data_transitive_for_task(self.0.id, name, data);
+        let _restore = RestoreOnDrop {
+            task: self,
+            name,
+            previous,
+        };
+        f()
+    }
+
+    /// Run at most `limit` async subtasks spawned directly under this task
+    /// (with [spawn()](Task::spawn), [spawn_detached()](Task::spawn_detached),
+    /// [scope()](Task::scope), ...) at the same time. Subtasks over the limit
+    /// are created right away but wait, shown as paused with a `pending`
+    /// reason, until a running one finishes. Subtasks of subtasks aren't
+    /// limited, since they'd be waiting on their own parents.
+    pub fn concurrency_limit(&self, limit: usize) {
+        self.0
+            .task_tree
+            .set_concurrency_limit_for_task(self.0.id, limit);
+    }
+
+    /// When any async subtask spawned directly under this task fails, cancel
+    /// the rest of the running ones (and everything under them). Cancelled
+    /// subtasks are reported as failed with the name of the subtask that
+    /// caused it, and the error of this task (if it fails) says which subtask
+    /// caused the abort.
+    pub fn fail_fast(&self, enabled: bool) {
+        self.0.task_tree.set_fail_fast_for_task(self.0.id, enabled);
+    }
+
+    /// Resolves once none of the subtasks of this task (at any depth) are
+    /// running, e.g. detached background work.
+    /// see [TaskTree::wait_idle()](crate::TaskTree::wait_idle)
+    pub async fn wait_idle(&self) {
+        self.0.task_tree.wait_idle_for(Some(self.0.id)).await
+    }
+
+    /// Create a subtask for work that repeats many times, where every
+    /// iteration is reported as a collapsed occurrence.
+    /// see [recurring](crate::recurring)
+    pub fn recurring(&self, name: &str) -> Recurring {
+        Recurring::new(self.create(&format!("{} #{}", name, RECURRING_TAG)))
+    }
+
+    /// Record that this task can't make progress until `other` finishes.
+    /// `TermStatus` shows what a running task is still
+    /// waiting on and [traces](crate::trace::Trace) include the edges.
+    pub fn waits_on(&self, other: &Task) {
+        self.0
+            .task_tree
+            .add_dependency_for_task(self.0.id, other.0.id);
+    }
+
+    /// Mark the task as blocked on something external (a lock, a rate
+    /// limit, user input) until [resume()](Task::resume) is called. Paused
+    /// tasks are shown differently by `TermStatus` and
+    /// don't count as stalled.
+    pub fn pause<S: Into<String>>(&self, reason: S) {
+        self.0.task_tree.pause_for_task(self.0.id, reason.into());
+    }
+
+    pub fn resume(&self) {
+        self.0.task_tree.resume_for_task(self.0.id);
+    }
+
     pub fn progress(&self, done: i64, total: i64) {
         self.0.task_tree.task_progress(self.0.id, done, total);
     }
 
+    /// Same as [progress()](Task::progress), for work that isn't counted in
+    /// whole units, e.g. megabytes or fractions of batches. Reporters that
+    /// only read integer progress get `done` rounded down and `total` rounded
+    /// up (to at least 1), so work is shown as complete once `done` reaches
+    /// `total` and not before.
+    pub fn progress_f64(&self, done: f64, total: f64) {
+        self.0.task_tree.task_progress_f64(self.0.id, done, total);
+    }
+
+    /// Custom rendering of `(done, total)` progress in the terminal status,
+    /// e.g. `task.progress_format(|done, total| format!("{:.0}%", done / total * 100.0))`
+    ///
+    /// `format` is called while the terminal status, STDIO and the task tree
+    /// are locked, so it must not call into ll (create tasks, add data,
+    /// print above the status, ...) or it
+    /// deadlocks.
+    pub fn progress_format<F>(&self, format: F)
+    where
+        F: Fn(f64, f64) -> String + Send + Sync + 'static,
+    {
+        self.0
+            .task_tree
+            .progress_format_for_task(self.0.id, Some(Arc::new(format)));
+    }
+
+    /// Compute progress of this task from its children instead of reporting
+    /// it manually.
+    /// see [aggregate_progress_for_task()](crate::task_tree::TaskTree::aggregate_progress_for_task)
+    pub fn aggregate_progress(&self, enabled: bool) {
+        self.0
+            .task_tree
+            .aggregate_progress_for_task(self.0.id, enabled);
+    }
+
     /// Reporters can use this flag to choose to not report errors.
     /// This is useful for cases where there's a large task chain and every
     /// single task reports a partial errors (that gets built up with each task)
@@ -122,3 +570,133 @@ impl Drop for TaskData {
         }
     }
 }
+
+/// Task started with [Task::start()]. Dereferences to the underlying [Task]
+/// so data, progress and subtasks can be added to it directly.
+pub struct TaskGuard {
+    task: Task,
+    drop_error: Option<String>,
+    finished: bool,
+}
+
+impl TaskGuard {
+    pub(crate) fn new(task: Task) -> Self {
+        Self {
+            task,
+            drop_error: None,
+            finished: false,
+        }
+    }
+
+    /// Mark the task as failed with the given message if the guard is dropped
+    /// without calling [success()](TaskGuard::success) or
+    /// [fail()](TaskGuard::fail). By default dropping marks it as successful.
+    pub fn fail_on_drop<S: Into<String>>(mut self, msg: S) -> Self {
+        self.drop_error = Some(msg.into());
+        self
+    }
+
+    pub fn success(self) {
+        self.finish(Ok(())).ok();
+    }
+
+    /// Mark the task as failed. Returns the error with the task context
+    /// attached, the same way [spawn()](Task::spawn) would.
+    pub fn fail(self, err: anyhow::Error) -> anyhow::Error {
+        match self.finish::<()>(Err(err)) {
+            Ok(()) => unreachable!(),
+            Err(err) => err,
+        }
+    }
+
+    /// Finish the task with the given result and pass it through.
+    pub fn finish<T>(mut self, result: Result<T>) -> Result<T> {
+        self.finished = true;
+        let task_tree = self.task.0.task_tree.clone();
+        task_tree.post_spawn(self.task.0.id, result)
+    }
+}
+
+impl Deref for TaskGuard {
+    type Target = Task;
+
+    fn deref(&self) -> &Task {
+        &self.task
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            let error = if std::thread::panicking() {
+                Some("task guard dropped during a panic".to_string())
+            } else {
+                self.drop_error.clone()
+            };
+            let task_tree = &self.task.0.task_tree;
+            task_tree.mark_done(self.task.0.id, error);
+            task_tree.maybe_force_flush();
+        }
+    }
+}
+
+/// Group of concurrently running subtasks.
+/// see [Task::scope()]
+pub struct Scope<T> {
+    parent: Task,
+    handles: Vec<TaskJoinHandle<T>>,
+}
+
+impl<T: Send + 'static> Scope<T> {
+    pub fn spawn<F, FT, S: Into<String>>(&mut self, name: S, f: F)
+    where
+        F: FnOnce(Task) -> FT + Send + 'static,
+        FT: Future<Output = Result<T>> + Send + 'static,
+    {
+        self.handles.push(self.parent.spawn_detached(name, f));
+    }
+}
+
+/// Aborts tokio tasks when dropped. Aborting a task that already finished
+/// does nothing.
+struct AbortOnDrop(Vec<tokio::task::AbortHandle>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        for handle in &self.0 {
+            handle.abort();
+        }
+    }
+}
+
+/// Restores a transitive key shadowed by [Task::data_transitive_scoped()]
+/// when dropped
+struct RestoreOnDrop<'a> {
+    task: &'a Task,
+    name: &'a str,
+    previous: Option<DataEntry>,
+}
+
+impl Drop for RestoreOnDrop<'_> {
+    fn drop(&mut self) {
+        self.task.0.task_tree.restore_data_transitive_for_task(
+            self.task.0.id,
+            self.name,
+            self.previous.take(),
+        );
+    }
+}
+
+/// Handle to a task started with [Task::spawn_detached()]. Resolves to the
+/// task result, or to an error if the task panicked or was cancelled.
+pub struct TaskJoinHandle<T>(pub(crate) tokio::task::JoinHandle<Result<T>>);
+
+impl<T> Future for TaskJoinHandle<T> {
+    type Output = Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T>> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|result| result.unwrap_or_else(|err| Err(err.into())))
+    }
+}
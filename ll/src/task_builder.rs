@@ -0,0 +1,162 @@
+use crate::data::{Data, DataValue};
+use crate::reporters::Level;
+use crate::tag::{GROUP_TAG_PREFIX, NOSTATUS};
+use crate::task::{Task, TaskData, TaskGuard, TaskJoinHandle};
+use crate::task_tree::{TaskTree, TASK_TREE};
+use crate::uniq_id::UniqID;
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Typed alternative to configuring tasks with hashtags in their names.
+///
+/// ```
+/// use ll::reporters::Level;
+/// use ll::Task;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let root = Task::create_new("root");
+/// Task::builder("query")
+///     .parent(&root)
+///     .level(Level::L2)
+///     .tag("db")
+///     .data("shard", 3)
+///     .no_status()
+///     .spawn(|_task| async move { Ok(()) })
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TaskBuilder {
+    task_tree: Arc<TaskTree>,
+    parent: Option<UniqID>,
+    name: String,
+    tags: BTreeSet<String>,
+    data: Data,
+}
+
+impl TaskBuilder {
+    pub(crate) fn new(name: String) -> Self {
+        Self {
+            task_tree: TASK_TREE.clone(),
+            parent: None,
+            name,
+            tags: BTreeSet::new(),
+            data: Data::empty(),
+        }
+    }
+
+    /// Create the task as a subtask of `parent` (and in its task tree).
+    /// Without a parent the task is created at the top level of the global
+    /// task tree.
+    pub fn parent(mut self, parent: &Task) -> Self {
+        self.name = parent.child_name(self.name);
+        self.task_tree = parent.0.task_tree.clone();
+        self.parent = Some(parent.0.id);
+        self
+    }
+
+    pub fn level(self, level: Level) -> Self {
+        self.tag(level.as_tag())
+    }
+
+    pub fn tag<S: Into<String>>(mut self, tag: S) -> Self {
+        self.tags.insert(tag.into());
+        self
+    }
+
+    pub fn data<S: Into<String>, D: Into<DataValue>>(mut self, key: S, value: D) -> Self {
+        self.data.add(key, value);
+        self
+    }
+
+    pub fn tags<I, S>(self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        tags.into_iter().fold(self, |builder, tag| builder.tag(tag))
+    }
+
+    /// Put the task into a group (same as a `#group:<name>` tag). Groups are
+    /// independent of the task tree, so e.g. all IO work can be aggregated
+    /// by [GroupStatsReporter](crate::reporters::GroupStatsReporter) no
+    /// matter which tasks it happens under. A task can be in multiple groups.
+    pub fn group(self, group: &str) -> Self {
+        self.tag(format!("{}{}", GROUP_TAG_PREFIX, group))
+    }
+
+    /// Lower is more important, same as a `#p0`, `#p1`, ... tag.
+    /// see [TaskInternal::priority()](crate::TaskInternal::priority)
+    pub fn priority(self, priority: u32) -> Self {
+        self.tag(format!("p{}", priority))
+    }
+
+    /// Don't show the task in the terminal status (same as `#nostatus`).
+    pub fn no_status(self) -> Self {
+        self.tag(NOSTATUS)
+    }
+
+    fn create_internal(self) -> (Arc<TaskTree>, UniqID) {
+        let id =
+            self.task_tree
+                .create_task_internal_with(self.name, self.parent, self.tags, self.data);
+        (self.task_tree, id)
+    }
+
+    /// Create the task. It is finished when the last clone of it is dropped.
+    /// see [Task::create()]
+    pub fn create(self) -> Task {
+        let (task_tree, id) = self.create_internal();
+        Task(Arc::new(TaskData {
+            id,
+            task_tree,
+            mark_done_on_drop: true,
+            name_prefix: None,
+            _nested_in: None,
+        }))
+    }
+
+    /// see [Task::start()]
+    pub fn start(self) -> TaskGuard {
+        let (task_tree, id) = self.create_internal();
+        TaskGuard::new(task_tree.spawned_task(id))
+    }
+
+    /// see [Task::spawn()]
+    pub async fn spawn<F, FT, T>(self, f: F) -> Result<T>
+    where
+        F: FnOnce(Task) -> FT,
+        FT: Future<Output = Result<T>> + Send,
+        T: Send,
+    {
+        let (task_tree, id) = self.create_internal();
+        let task = task_tree.spawned_task(id);
+        task_tree.run_spawned(task, f).await
+    }
+
+    /// see [Task::spawn_sync()]
+    pub fn spawn_sync<F, T>(self, f: F) -> Result<T>
+    where
+        F: FnOnce(Task) -> Result<T>,
+        T: Send,
+    {
+        let (task_tree, id) = self.create_internal();
+        let task = task_tree.spawned_task(id);
+        task_tree.run_spawned_sync(task, f)
+    }
+
+    /// see [Task::spawn_detached()]
+    pub fn spawn_detached<F, FT, T>(self, f: F) -> TaskJoinHandle<T>
+    where
+        F: FnOnce(Task) -> FT + Send + 'static,
+        FT: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (task_tree, id) = self.create_internal();
+        let task = task_tree.spawned_task(id);
+        task_tree.run_detached(task, f)
+    }
+}
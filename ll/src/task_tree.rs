@@ -1,13 +1,27 @@
-use crate::data::{Data, DataEntry, DataValue};
-use crate::reporters::Reporter;
-use crate::task::{Task, TaskData};
+use crate::attachment::{AttachmentContent, AttachmentStore, ATTACHMENT_TAG};
+use crate::clock::{Clock, SystemClock};
+use crate::data::{Data, DataEntry, DataValue, SerdeDataMode};
+use crate::data_scope::{current_data_scope, DataScope};
+use crate::redaction::{RedactionRules, REDACTED};
+use crate::reporters::async_reporter::{AsyncReporterBridge, BufferFull};
+use crate::reporters::capture::TaskRecord;
+use crate::reporters::NOSTATUS_TAG;
+use crate::reporters::{
+    AsyncReporter, DeadLetterHandler, Level, Reporter, ReporterHandle, TaskReportType,
+    DONTPRINT_TAG,
+};
+use crate::schema::DataSchema;
+use crate::task::{Task, TaskData, TaskJoinHandle};
+use crate::test::{name_matches, Fault};
+use crate::trace::Trace;
 use crate::uniq_id::UniqID;
 use anyhow::{Context, Result};
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::future::Future;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 use std::thread;
 use std::time::Duration;
 use std::time::SystemTime;
@@ -16,19 +30,155 @@ lazy_static::lazy_static! {
     pub static ref TASK_TREE: Arc<TaskTree>  = TaskTree::new();
 }
 
-pub fn add_reporter(reporter: Arc<dyn Reporter>) {
-    TASK_TREE.add_reporter(reporter);
+thread_local! {
+    /// Trees this thread is delivering reports of, see
+    /// [TaskTree::report_all()]
+    static REPORTING: RefCell<Vec<usize>> = const { RefCell::new(vec![]) };
+}
+
+pub fn add_reporter(reporter: Arc<dyn Reporter>) -> ReporterHandle {
+    TASK_TREE.add_reporter(reporter)
 }
 
 pub trait ErrorFormatter: Send + Sync {
     fn format_error(&self, err: &anyhow::Error) -> String;
 }
 
+/// How failed reporter deliveries are retried. Backoff starts with
+/// `initial_backoff` and doubles after every attempt, up to `max_backoff`.
+/// Retries block the reporting thread, so they should be kept short.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Renders `(done, total)` progress of a task, see
+/// [Task::progress_format()](crate::Task::progress_format)
+pub type ProgressFormat = Arc<dyn Fn(f64, f64) -> String + Send + Sync>;
+
+/// How [TaskInternal::full_name()] renders a task and its parents, see
+/// [TaskTree::set_full_name_format()]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FullNameFormat {
+    /// Put between the names of parents and the task, `:` by default
+    pub separator: String,
+    /// Prepend the id of the task, e.g. `42-root:request`
+    pub with_id: bool,
+    /// Only show this many of the closest parents
+    pub max_depth: Option<usize>,
+}
+
+impl Default for FullNameFormat {
+    fn default() -> Self {
+        Self {
+            separator: ":".to_string(),
+            with_id: false,
+            max_depth: None,
+        }
+    }
+}
+
+/// Delivery statistics of a single reporter, see
+/// [TaskTree::reporter_stats()]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReporterStats {
+    /// Reports the reporter accepted, possibly after retries
+    pub delivered: u64,
+    /// Failed delivery attempts, including ones that were retried
+    pub errors: u64,
+    /// Reports that failed even after all retries (or couldn't be retried,
+    /// like reports to a full async reporter buffer) and were given to the
+    /// dead letter handler
+    pub dropped: u64,
+    /// Time spent delivering reports, including retries
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl ReporterStats {
+    pub fn avg_latency(&self) -> Duration {
+        match self.delivered + self.dropped {
+            0 => Duration::ZERO,
+            reports => {
+                let nanos = self.total_latency.as_nanos() / reports as u128;
+                Duration::from_nanos(nanos as u64)
+            }
+        }
+    }
+
+    fn record(&mut self, delivered: bool, errors: u32, latency: Duration) {
+        if delivered {
+            self.delivered += 1;
+        } else {
+            self.dropped += 1;
+        }
+        self.errors += errors as u64;
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+    }
+
+    fn merge(&mut self, other: &ReporterStats) {
+        self.delivered += other.delivered;
+        self.errors += other.errors;
+        self.dropped += other.dropped;
+        self.total_latency += other.total_latency;
+        self.max_latency = self.max_latency.max(other.max_latency);
+    }
+}
+
+/// Stops logging reporter stats when dropped, see
+/// [TaskTree::log_reporter_stats_every()]
+pub struct ReporterStatsHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl Drop for ReporterStatsHandle {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl std::fmt::Display for ReporterStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "delivered: {}, errors: {}, dropped: {}, avg latency: {:?}, max latency: {:?}",
+            self.delivered,
+            self.errors,
+            self.dropped,
+            self.avg_latency(),
+            self.max_latency
+        )
+    }
+}
+
 pub struct TaskTree {
     pub(crate) tree_internal: RwLock<TaskTreeInternal>,
     /// If true, it will block the current thread until all task events are
     /// reported (e.g. written to STDOUT)
     force_flush: AtomicBool,
+    /// Held while reports are being delivered, so when `report_all()` returns
+    /// everything reported before it was called has reached reporters, even
+    /// if the batch was picked up by another thread. Reporters calling back
+    /// into `report_all()` skip it instead of waiting for themselves.
+    report_lock: Mutex<()>,
+    /// Woken up every time a task finishes, see [wait_idle()](TaskTree::wait_idle)
+    task_finished: tokio::sync::Notify,
+    /// Kept outside of `tree_internal`, so it's not locked while reports
+    /// are being delivered
+    reporter_stats: Mutex<BTreeMap<ReporterHandle, ReporterStats>>,
 }
 
 pub(crate) struct TaskTreeInternal {
@@ -36,39 +186,176 @@ pub(crate) struct TaskTreeInternal {
     parent_to_children: BTreeMap<UniqID, BTreeSet<UniqID>>,
     child_to_parents: BTreeMap<UniqID, BTreeSet<UniqID>>,
     root_tasks: BTreeSet<UniqID>,
-    reporters: Vec<Arc<dyn Reporter>>,
+    reporters: BTreeMap<ReporterHandle, Arc<dyn Reporter>>,
+    /// Async reporters are also in `reporters`, this is to be able to flush
+    /// their buffers.
+    async_reporters: BTreeMap<ReporterHandle, Arc<AsyncReporterBridge>>,
     tasks_marked_for_deletion: HashMap<UniqID, SystemTime>,
     report_start: Vec<UniqID>,
     report_end: Vec<UniqID>,
+    report_stalled: Vec<UniqID>,
     data_transitive: Data,
     remove_task_after_done_ms: u64,
+    stall_threshold: Option<Duration>,
+    stall_threshold_by_tag: BTreeMap<String, Duration>,
     hide_errors_default_msg: Option<Arc<String>>,
     attach_transitive_data_to_errors_default: bool,
     error_formatter: Option<Arc<dyn ErrorFormatter>>,
+    retry_policy: RetryPolicy,
+    dead_letter_handler: Option<Arc<dyn DeadLetterHandler>>,
+    clock: Arc<dyn Clock>,
+    faults: Vec<(String, Fault)>,
+    name_prefix: Option<String>,
+    dontprint_patterns: Vec<String>,
+    redaction_rules: RedactionRules,
+    data_schemas: Vec<(String, DataSchema)>,
+    serde_data_mode: SerdeDataMode,
+    attachment_store: Option<Arc<dyn AttachmentStore>>,
+    name_rate_limit: Option<u32>,
+    name_rate_windows: HashMap<String, NameRateWindow>,
+    /// Tasks whose reports were dropped by the name rate limit
+    rate_limited: HashSet<UniqID>,
+    /// Summaries of windows that were closed early, waiting to be reported
+    rate_limit_summaries: Vec<TaskInternal>,
+    /// Transitive data of open [data scopes](crate::data_scope)
+    data_scopes: HashMap<UniqID, Data>,
+    /// Sequence number of the last report, see [TaskInternal::report_seq()]
+    report_seq: u64,
+    full_name_format: Arc<FullNameFormat>,
+}
+
+/// One second window of the per name rate limit, see
+/// [TaskTree::set_name_rate_limit()]
+struct NameRateWindow {
+    started_at: SystemTime,
+    reported: u32,
+    suppressed: u32,
+    /// Suppressed tasks that failed and the last of their errors
+    failed: u32,
+    last_error: Option<String>,
+    /// First task that was suppressed, used as a template for the summary
+    /// report
+    first_suppressed: Option<TaskInternal>,
+}
+
+impl NameRateWindow {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        now.duration_since(self.started_at).unwrap_or_default() >= Duration::from_secs(1)
+    }
+
+    /// Finished `#rate_limited` task with the number of suppressed tasks,
+    /// if anything was suppressed. It fails if any of the suppressed tasks
+    /// failed.
+    fn into_summary(self, now: SystemTime) -> Option<TaskInternal> {
+        let mut summary = self.first_suppressed?;
+        summary.id = UniqID::new();
+        summary.started_at = self.started_at;
+        let result = match self.last_error {
+            Some(error) => TaskResult::Failure(format!(
+                "{} of the suppressed tasks failed, last error:\n{}",
+                self.failed, error
+            )),
+            None => TaskResult::Success,
+        };
+        summary.status = TaskStatus::Finished(result, now);
+        summary.data = Data::empty();
+        summary.data.add(SUPPRESSED_KEY, self.suppressed);
+        if self.failed > 0 {
+            summary.data.add(SUPPRESSED_FAILED_KEY, self.failed);
+        }
+        summary.tags.insert(RATE_LIMITED_TAG.to_string());
+        Some(summary)
+    }
 }
 
+/// Pause reason of tasks waiting for a
+/// [concurrency limit](crate::Task::concurrency_limit) of their parent
+pub const PENDING_REASON: &str = "pending";
+
+/// Priority of tasks that don't have a `#p<N>` tag, see
+/// [TaskInternal::priority()]
+pub const DEFAULT_PRIORITY: u32 = 2;
+
+/// Tag of the summary tasks reported when a
+/// [name rate limit](TaskTree::set_name_rate_limit) window closes
+pub const RATE_LIMITED_TAG: &str = "rate_limited";
+/// Data key holding the number of suppressed tasks in rate limit summaries
+pub const SUPPRESSED_KEY: &str = "suppressed";
+/// Data key holding the number of suppressed tasks that failed
+pub const SUPPRESSED_FAILED_KEY: &str = "suppressed_failed";
+
 #[derive(Clone)]
+#[non_exhaustive]
 pub struct TaskInternal {
-    pub id: UniqID,
-    pub name: String,
-    pub parent_names: Vec<String>,
-    pub started_at: SystemTime,
-    pub status: TaskStatus,
-    pub data: Data,
-    pub data_transitive: Data,
-    pub tags: BTreeSet<String>,
+    pub(crate) id: UniqID,
+    pub(crate) name: String,
+    pub(crate) parent_id: Option<UniqID>,
+    pub(crate) parent_names: Vec<String>,
+    pub(crate) started_at: SystemTime,
+    pub(crate) status: TaskStatus,
+    pub(crate) data: Data,
+    pub(crate) data_transitive: Data,
+    pub(crate) tags: BTreeSet<String>,
     /// optional tuple containing values indicating task progress, where
     /// first value is how many items finished and the second value is how many
     /// items there are total. E.g. if it's a task processing 10 pieces of work,
     /// (1, 10) would mean that 1 out of ten pieces is done.
-    pub progress: Option<(i64, i64)>,
-    pub hide_errors: Option<Arc<String>>,
-    pub attach_transitive_data_to_errors: bool,
+    pub(crate) progress: Option<(i64, i64)>,
+    pub(crate) hide_errors: Option<Arc<String>>,
+    pub(crate) attach_transitive_data_to_errors: bool,
+    /// Set by the watchdog when the task has been running for longer than
+    /// the configured stall threshold.
+    /// see [set_stall_threshold()](crate::task_tree::TaskTree::set_stall_threshold)
+    pub(crate) stalled: bool,
+    /// If set, `progress` of this task is computed from its children instead
+    /// of being reported manually. Map values are (done, total) contributions
+    /// of every child that was ever created under this task, so they don't
+    /// disappear when finished children get garbage collected.
+    pub(crate) children_progress: Option<BTreeMap<UniqID, (i64, i64)>>,
+    /// Transitive keys removed with
+    /// [clear_data_transitive()](crate::Task::clear_data_transitive). Inherited
+    /// by children so that tree level transitive data doesn't come back
+    /// further down the subtree.
+    pub(crate) cleared_data_transitive: BTreeSet<String>,
+    /// Tasks this task waits on (id and full name), see
+    /// [Task::waits_on()](crate::Task::waits_on)
+    pub(crate) waits_on: Vec<(UniqID, String)>,
+    /// Limits how many async subtasks spawned directly under this task run
+    /// at the same time, see [Task::concurrency_limit()](crate::Task::concurrency_limit)
+    pub(crate) concurrency_limit: Option<Arc<tokio::sync::Semaphore>>,
+    /// Set for tasks with [fail_fast()](crate::Task::fail_fast) enabled.
+    /// Holds the full name of the first subtask that failed.
+    pub(crate) fail_fast: Option<Arc<tokio::sync::watch::Sender<Option<String>>>>,
+    /// Time spent in previous pauses, not counting the current one
+    pub(crate) paused_time: Duration,
+    /// When the current pause started, if the task is paused
+    pub(crate) paused_at: Option<SystemTime>,
+    /// Number of subtasks created directly under this task so far
+    pub(crate) child_count: usize,
+    /// Set on the copies of the task that are delivered to reporters
+    pub(crate) report_seq: u64,
+    pub(crate) full_name_format: Arc<FullNameFormat>,
+    /// Exact values reported with [Task::progress_f64()](crate::Task::progress_f64),
+    /// `progress` holds them rounded
+    pub(crate) progress_f64: Option<(f64, f64)>,
+    pub(crate) progress_format: Option<ProgressFormat>,
+    /// Spawned with [Task::spawn_ok()](crate::Task::spawn_ok), injected
+    /// faults only delay it
+    pub(crate) infallible: bool,
 }
 
+/// More states can be added in the future, so matches on it outside of this
+/// crate need a wildcard arm.
 #[derive(Clone)]
+#[non_exhaustive]
 pub enum TaskStatus {
     Running,
+    /// Blocked on something external, with a human readable reason.
+    /// see [Task::pause()](crate::Task::pause)
+    Paused(String),
+    /// Created ahead of time and will start at the given time.
+    /// see [Task::spawn_after()](crate::Task::spawn_after)
+    Scheduled(SystemTime),
     Finished(TaskResult, SystemTime),
 }
 
@@ -86,17 +373,41 @@ impl TaskTree {
                 parent_to_children: BTreeMap::new(),
                 child_to_parents: BTreeMap::new(),
                 root_tasks: BTreeSet::new(),
-                reporters: vec![],
+                reporters: BTreeMap::new(),
+                async_reporters: BTreeMap::new(),
                 tasks_marked_for_deletion: HashMap::new(),
                 report_start: vec![],
                 report_end: vec![],
+                report_stalled: vec![],
                 data_transitive: Data::empty(),
                 remove_task_after_done_ms: 0,
+                stall_threshold: None,
+                stall_threshold_by_tag: BTreeMap::new(),
                 hide_errors_default_msg: None,
                 attach_transitive_data_to_errors_default: true,
                 error_formatter: None,
+                retry_policy: RetryPolicy::default(),
+                dead_letter_handler: None,
+                clock: Arc::new(SystemClock),
+                faults: vec![],
+                name_prefix: None,
+                dontprint_patterns: vec![],
+                redaction_rules: RedactionRules::new(),
+                data_schemas: vec![],
+                serde_data_mode: SerdeDataMode::Nested,
+                attachment_store: None,
+                name_rate_limit: None,
+                name_rate_windows: HashMap::new(),
+                rate_limited: HashSet::new(),
+                rate_limit_summaries: vec![],
+                data_scopes: HashMap::new(),
+                report_seq: 0,
+                full_name_format: Arc::new(FullNameFormat::default()),
             }),
             force_flush: AtomicBool::new(false),
+            report_lock: Mutex::new(()),
+            task_finished: tokio::sync::Notify::new(),
+            reporter_stats: Mutex::new(BTreeMap::new()),
         });
         let clone = s.clone();
         tokio::spawn(async move {
@@ -104,6 +415,7 @@ impl TaskTree {
                 tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
                 let mut tree = clone.tree_internal.write().unwrap();
                 tree.garbage_collect();
+                tree.detect_stalled();
             }
         });
         let clone = s.clone();
@@ -129,27 +441,118 @@ impl TaskTree {
             id,
             task_tree: self.clone(),
             mark_done_on_drop: true,
+            name_prefix: None,
+            _nested_in: None,
         }))
     }
 
-    pub fn add_reporter(&self, reporter: Arc<dyn Reporter>) {
-        self.tree_internal.write().unwrap().reporters.push(reporter);
+    /// Add a reporter. The returned handle can be used to remove it later.
+    pub fn add_reporter(&self, reporter: Arc<dyn Reporter>) -> ReporterHandle {
+        let handle = ReporterHandle(UniqID::new());
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.reporters.insert(handle, reporter);
+        handle
+    }
+
+    /// Remove a previously added reporter. Returns false if there was no
+    /// reporter with this handle.
+    pub fn remove_reporter(&self, handle: ReporterHandle) -> bool {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.async_reporters.remove(&handle);
+        let removed = tree.reporters.remove(&handle).is_some();
+        // still holding the tree, so report_all() can't record stats for
+        // the removed reporter afterwards
+        self.reporter_stats.lock().unwrap().remove(&handle);
+        removed
+    }
+
+    /// Atomically replace all reporters with new ones, e.g. to re-open log
+    /// files or switch verbosity in a long running process. No report will be
+    /// delivered to both old and new reporters, or missed by both.
+    pub fn replace_reporters(&self, reporters: Vec<Arc<dyn Reporter>>) -> Vec<ReporterHandle> {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.reporters.clear();
+        tree.async_reporters.clear();
+        self.reporter_stats.lock().unwrap().clear();
+        reporters
+            .into_iter()
+            .map(|reporter| {
+                let handle = ReporterHandle(UniqID::new());
+                tree.reporters.insert(handle, reporter);
+                handle
+            })
+            .collect()
+    }
+
+    /// Add an async reporter. Reports are buffered in a bounded queue of
+    /// `buffer_size` items (at least 1), when it's full reporting waits for
+    /// the async reporter to catch up. Reports from a `current_thread`
+    /// runtime can't wait and are dropped instead, see
+    /// [reporter_stats()](TaskTree::reporter_stats). Must be called from
+    /// within a tokio runtime.
+    pub fn add_async_reporter(
+        &self,
+        reporter: Arc<dyn AsyncReporter>,
+        buffer_size: usize,
+    ) -> ReporterHandle {
+        let bridge = Arc::new(AsyncReporterBridge::new(reporter, buffer_size));
+        let handle = self.add_reporter(bridge.clone());
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.async_reporters.insert(handle, bridge);
+        handle
     }
 
     fn pre_spawn(self: &Arc<Self>, name: String, parent: Option<UniqID>) -> Task {
+        let id = self.create_task_internal(name, parent);
+        self.spawned_task(id)
+    }
+
+    /// [pre_spawn()](TaskTree::pre_spawn) for tasks that can't fail
+    pub(crate) fn pre_spawn_infallible(
+        self: &Arc<Self>,
+        name: String,
+        parent: Option<UniqID>,
+    ) -> Task {
+        let id = self.create_task_internal(name, parent);
+        if let Some(task_internal) = self
+            .tree_internal
+            .write()
+            .unwrap()
+            .tasks_internal
+            .get_mut(&id)
+        {
+            task_internal.infallible = true;
+        }
+        self.spawned_task(id)
+    }
+
+    /// Task handle for an already created task that is finished by
+    /// [post_spawn()](TaskTree::post_spawn) rather than on drop.
+    pub(crate) fn spawned_task(self: &Arc<Self>, id: UniqID) -> Task {
         let task = Task(Arc::new(TaskData {
-            id: self.create_task_internal(&name, parent),
+            id,
             task_tree: self.clone(),
             mark_done_on_drop: false,
+            name_prefix: None,
+            _nested_in: None,
         }));
         self.maybe_force_flush();
         task
     }
 
-    fn post_spawn<T>(self: &Arc<Self>, id: UniqID, result: Result<T>) -> Result<T> {
+    pub(crate) fn post_spawn<T>(self: &Arc<Self>, id: UniqID, result: Result<T>) -> Result<T> {
+        let result = match self.fail_fast_cause(id) {
+            Some(cause) => {
+                result.with_context(|| format!("aborted after subtask {} failed", cause))
+            }
+            None => result,
+        };
+        if result.is_err() {
+            self.abort_siblings(id);
+        }
         let result = result.with_context(|| {
             let mut desc = String::from("[Task]");
-            if let Some(task_internal) = self.get_cloned_task(id) {
+            if let Some(task_internal) = self.get_redacted_task(id) {
                 desc.push_str(&format!(" {}", task_internal.name));
                 if task_internal.attach_transitive_data_to_errors {
                     for (k, v) in task_internal.all_data() {
@@ -174,7 +577,7 @@ impl TaskTree {
             if let Some(formatter) = formatter {
                 Some(formatter.format_error(err))
             } else {
-                Some(format!("{:?}", err))
+                Some(default_format_error(err))
             }
         } else {
             None
@@ -195,8 +598,27 @@ impl TaskTree {
         T: Send,
     {
         let task = self.pre_spawn(name, parent);
+        self.run_spawned_sync(task, f)
+    }
+
+    pub(crate) fn run_spawned_sync<F, T>(self: &Arc<Self>, task: Task, f: F) -> Result<T>
+    where
+        F: FnOnce(Task) -> Result<T>,
+        T: Send,
+    {
         let id = task.0.id;
-        let result = f(task);
+        let result = match self.fault_for_task(id) {
+            Some(fault) => {
+                if let Some(duration) = fault.sleep_duration() {
+                    thread::sleep(duration);
+                }
+                match fault.error() {
+                    Some(err) => Err(err),
+                    None => f(task),
+                }
+            }
+            None => f(task),
+        };
         self.post_spawn(id, result)
     }
 
@@ -212,74 +634,533 @@ impl TaskTree {
         T: Send,
     {
         let task = self.pre_spawn(name, parent);
+        self.run_spawned(task, f).await
+    }
+
+    pub(crate) fn spawn_detached<F, FT, T>(
+        self: &Arc<Self>,
+        name: String,
+        f: F,
+        parent: Option<UniqID>,
+    ) -> TaskJoinHandle<T>
+    where
+        F: FnOnce(Task) -> FT + Send + 'static,
+        FT: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        // The task is created right away rather than when the runtime gets to
+        // poll it, so it shows up under its parent even if the parent
+        // finishes first.
+        let task = self.pre_spawn(name, parent);
+        self.run_detached(task, f)
+    }
+
+    /// Creates the task right away in [Scheduled](TaskStatus::Scheduled)
+    /// state and runs it on the tokio runtime after `delay`.
+    pub(crate) fn spawn_after<F, FT, T>(
+        self: &Arc<Self>,
+        delay: Duration,
+        name: String,
+        f: F,
+        parent: Option<UniqID>,
+    ) -> TaskJoinHandle<T>
+    where
+        F: FnOnce(Task) -> FT + Send + 'static,
+        FT: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let task = self.pre_spawn(name, parent);
+        let id = task.0.id;
+        {
+            let mut tree = self.tree_internal.write().unwrap();
+            let starts_at = tree.clock.now() + delay;
+            if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+                task_internal.status = TaskStatus::Scheduled(starts_at);
+            }
+        }
+        let tree = self.clone();
+        TaskJoinHandle(tokio::spawn(async move {
+            let cancel_guard = CancelOnDrop { tree: &tree, id };
+            tokio::time::sleep(delay).await;
+            std::mem::forget(cancel_guard);
+            tree.start_scheduled(id);
+            tree.run_spawned(task, f).await
+        }))
+    }
+
+    /// Durations of scheduled tasks are counted from when they actually
+    /// started rather than from when they were created.
+    fn start_scheduled(&self, id: UniqID) {
+        let mut tree = self.tree_internal.write().unwrap();
+        let now = tree.clock.now();
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            if let TaskStatus::Scheduled(_) = task_internal.status {
+                task_internal.status = TaskStatus::Running;
+                task_internal.started_at = now;
+            }
+        }
+    }
+
+    pub(crate) fn run_detached<F, FT, T>(self: &Arc<Self>, task: Task, f: F) -> TaskJoinHandle<T>
+    where
+        F: FnOnce(Task) -> FT + Send + 'static,
+        FT: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let tree = self.clone();
+        TaskJoinHandle(tokio::spawn(async move { tree.run_spawned(task, f).await }))
+    }
+
+    pub(crate) async fn run_spawned<F, FT, T>(self: &Arc<Self>, task: Task, f: F) -> Result<T>
+    where
+        F: FnOnce(Task) -> FT,
+        FT: Future<Output = Result<T>> + Send,
+        T: Send,
+    {
         let id = task.0.id;
-        let result = f(task).await;
+        let cancel_guard = CancelOnDrop { tree: self, id };
+        let abort = self.fail_fast_receiver(id);
+        let _permit = self.acquire_concurrency_permit(id).await;
+        let run = async {
+            match self.fault_for_task(id) {
+                Some(fault) => {
+                    if let Some(duration) = fault.sleep_duration() {
+                        tokio::time::sleep(duration).await;
+                    }
+                    match fault.error() {
+                        Some(err) => Err(err),
+                        None => f(task).await,
+                    }
+                }
+                None => f(task).await,
+            }
+        };
+        let result = match abort {
+            Some(mut abort) => {
+                // descendants are marked before `run` is dropped, otherwise
+                // they'd be finished as merely cancelled
+                let aborted = async {
+                    let cause = wait_for_abort(&mut abort).await;
+                    let msg = format!("cancelled because {} failed", cause);
+                    self.cancel_descendants(id, &msg);
+                    msg
+                };
+                tokio::select! {
+                    result = run => result,
+                    msg = aborted => Err(anyhow::anyhow!(msg)),
+                }
+            }
+            None => run.await,
+        };
+        std::mem::forget(cancel_guard);
         self.post_spawn(id, result)
     }
 
+    pub(crate) fn set_fail_fast_for_task(&self, id: UniqID, enabled: bool) {
+        let mut tree = self.tree_internal.write().unwrap();
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            task_internal.fail_fast = if enabled {
+                Some(Arc::new(tokio::sync::watch::channel(None).0))
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Abort signal of the parent of the task, if the parent is fail-fast.
+    /// Tasks that can't fail run to completion instead.
+    fn fail_fast_receiver(
+        &self,
+        id: UniqID,
+    ) -> Option<tokio::sync::watch::Receiver<Option<String>>> {
+        let tree = self.tree_internal.read().unwrap();
+        let task_internal = tree.get_task(id).ok()?;
+        if task_internal.infallible {
+            return None;
+        }
+        let parent_id = task_internal.parent_id?;
+        let sender = tree.get_task(parent_id).ok()?.fail_fast.as_ref()?;
+        Some(sender.subscribe())
+    }
+
+    /// Full name of the subtask that caused a fail-fast task to abort
+    fn fail_fast_cause(&self, id: UniqID) -> Option<String> {
+        let tree = self.tree_internal.read().unwrap();
+        let sender = tree.get_task(id).ok()?.fail_fast.as_ref()?;
+        let cause = sender.borrow().clone();
+        cause
+    }
+
+    /// Signal running siblings of a failed task to abort if its parent is
+    /// fail-fast. Only the first failure is recorded as the cause.
+    fn abort_siblings(&self, id: UniqID) {
+        let tree = self.tree_internal.read().unwrap();
+        let Ok(task_internal) = tree.get_task(id) else {
+            return;
+        };
+        let parent = task_internal
+            .parent_id
+            .and_then(|pid| tree.get_task(pid).ok());
+        if let Some(sender) = parent.and_then(|parent| parent.fail_fast.as_ref()) {
+            let name = task_internal.full_name();
+            sender.send_if_modified(|cause| {
+                if cause.is_none() {
+                    *cause = Some(name);
+                    true
+                } else {
+                    false
+                }
+            });
+        }
+    }
+
+    /// Mark all still running subtasks of a cancelled task as failed, since
+    /// their futures get dropped without finishing.
+    fn cancel_descendants(&self, id: UniqID, msg: &str) {
+        let running = {
+            let tree = self.tree_internal.read().unwrap();
+            let mut running = vec![];
+            let mut stack = vec![id];
+            while let Some(id) = stack.pop() {
+                for child_id in tree.parent_to_children.get(&id).into_iter().flatten() {
+                    if tree
+                        .get_task(*child_id)
+                        .is_ok_and(|child| !matches!(child.status, TaskStatus::Finished(..)))
+                    {
+                        running.push(*child_id);
+                    }
+                    stack.push(*child_id);
+                }
+            }
+            running
+        };
+        for child_id in running {
+            self.mark_done(child_id, Some(msg.to_string()));
+        }
+    }
+
+    /// Waits until the task is allowed to run by the concurrency limit of
+    /// its parent (if there's one). The task is shown as paused with a
+    /// `pending` reason while it waits.
+    async fn acquire_concurrency_permit(
+        self: &Arc<Self>,
+        id: UniqID,
+    ) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        let semaphore = {
+            let tree = self.tree_internal.read().unwrap();
+            let parent_id = tree.get_task(id).ok()?.parent_id?;
+            tree.get_task(parent_id).ok()?.concurrency_limit.clone()?
+        };
+        if let Ok(permit) = semaphore.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        self.pause_for_task(id, PENDING_REASON.to_string());
+        let permit = semaphore.acquire_owned().await.ok();
+        self.resume_for_task(id);
+        permit
+    }
+
+    pub(crate) fn set_concurrency_limit_for_task(&self, id: UniqID, limit: usize) {
+        let mut tree = self.tree_internal.write().unwrap();
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            task_internal.concurrency_limit = Some(Arc::new(tokio::sync::Semaphore::new(limit)));
+        }
+    }
+
     pub fn create_task_internal<S: Into<String>>(
         self: &Arc<Self>,
         name: S,
         parent: Option<UniqID>,
+    ) -> UniqID {
+        self.create_task_internal_with(name, parent, BTreeSet::new(), Data::empty())
+    }
+
+    /// Same as [create_task_internal()](TaskTree::create_task_internal) but
+    /// with extra tags (on top of the ones in the name) and initial data.
+    pub(crate) fn create_task_internal_with<S: Into<String>>(
+        self: &Arc<Self>,
+        name: S,
+        parent: Option<UniqID>,
+        extra_tags: BTreeSet<String>,
+        data: Data,
     ) -> UniqID {
         let mut tree = self.tree_internal.write().unwrap();
 
         let mut parent_names = vec![];
+        let mut parent_id = None;
         let mut data_transitive = tree.data_transitive.clone();
-        let (name, tags) = crate::utils::extract_tags(name.into());
+        let mut cleared_data_transitive = BTreeSet::new();
+        let (mut name, mut tags) = crate::utils::extract_tags(name.into());
+        tags.extend(extra_tags);
         let id = UniqID::new();
+        let now = tree.clock.now();
         if let Some(parent_task) = parent.and_then(|pid| tree.tasks_internal.get(&pid)) {
             parent_names = parent_task.parent_names.clone();
             parent_names.push(parent_task.name.clone());
             data_transitive.merge(&parent_task.data_transitive);
-            let parent_id = parent_task.id;
-
-            tree.parent_to_children
-                .entry(parent_id)
-                .or_insert_with(BTreeSet::new)
-                .insert(id);
-            tree.child_to_parents
-                .entry(id)
-                .or_insert_with(BTreeSet::new)
-                .insert(parent_id);
+            for key in &parent_task.cleared_data_transitive {
+                data_transitive.remove(key);
+            }
+            cleared_data_transitive = parent_task.cleared_data_transitive.clone();
+            let pid = parent_task.id;
+            parent_id = Some(pid);
+
+            tree.parent_to_children.entry(pid).or_default().insert(id);
+            tree.child_to_parents.entry(id).or_default().insert(pid);
         } else {
             tree.root_tasks.insert(id);
+            if let Some(scope) =
+                current_data_scope(self).and_then(|scope_id| tree.data_scopes.get(&scope_id))
+            {
+                data_transitive.merge(scope);
+            }
+            if let Some(prefix) = &tree.name_prefix {
+                name = format!("{}.{}", prefix, name);
+            }
         }
+        // keyed by the final name, the same one suppressed tasks are
+        // recorded under
+        let rate_limited = !tree.allow_report_for_name(&name, now);
 
-        let task_internal = TaskInternal {
+        let mut task_internal = TaskInternal {
             status: TaskStatus::Running,
             name,
+            parent_id,
             parent_names,
             id,
-            started_at: SystemTime::now(),
-            data: Data::empty(),
+            started_at: now,
+            data,
             data_transitive,
             tags,
             progress: None,
             hide_errors: tree.hide_errors_default_msg.clone(),
             attach_transitive_data_to_errors: tree.attach_transitive_data_to_errors_default,
+            stalled: false,
+            children_progress: None,
+            cleared_data_transitive,
+            waits_on: vec![],
+            concurrency_limit: None,
+            fail_fast: None,
+            paused_time: Duration::ZERO,
+            paused_at: None,
+            child_count: 0,
+            report_seq: 0,
+            full_name_format: tree.full_name_format.clone(),
+            progress_f64: None,
+            progress_format: None,
+            infallible: false,
         };
+        if let Some(parent_task) = parent_id.and_then(|pid| tree.tasks_internal.get_mut(&pid)) {
+            parent_task.child_count += 1;
+        }
 
+        if rate_limited {
+            tree.record_suppressed(&task_internal);
+            tree.rate_limited.insert(id);
+            // don't let a runaway loop flood the terminal status either
+            task_internal.tags.insert(NOSTATUS_TAG.to_string());
+        } else {
+            tree.report_start.push(id);
+        }
         tree.tasks_internal.insert(id, task_internal);
-        tree.report_start.push(id);
+        tree.update_parent_progress(id);
 
         id
     }
 
     pub fn mark_done(&self, id: UniqID, error_message: Option<String>) {
         let mut tree = self.tree_internal.write().unwrap();
+        // tasks only finish once, e.g. a task that was interrupted on
+        // shutdown isn't reported again when its handle is dropped
+        if tree
+            .get_task(id)
+            .is_ok_and(|task| matches!(task.status, TaskStatus::Finished(..)))
+        {
+            return;
+        }
+        let now = tree.clock.now();
+        // printed after the tree is unlocked, TermStatus holds the stderr
+        // lock while it reads the tree
+        let mut warnings = vec![];
+        let error_message = match tree.schema_violations(id) {
+            Some((violations, strict)) if strict => {
+                let violations = violations.join(", ");
+                Some(match error_message {
+                    Some(msg) => format!("{}\n{}", msg, violations),
+                    None => violations,
+                })
+            }
+            Some((violations, _)) => {
+                if let Ok(task_internal) = tree.get_task(id) {
+                    for violation in violations {
+                        warnings.push(format!("{}: {}", task_internal.full_name(), violation));
+                    }
+                }
+                error_message
+            }
+            None => error_message,
+        };
         if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
-            task_internal.mark_done(error_message);
+            task_internal.mark_done(error_message, now);
+            tree.update_parent_progress(id);
             tree.mark_for_gc(id);
-            tree.report_end.push(id);
+            let reported = if tree.rate_limited.remove(&id) {
+                // failures must not disappear into a green summary
+                match tree
+                    .get_task(id)
+                    .map(|task| (task.name.clone(), task.status.clone()))
+                {
+                    Ok((name, TaskStatus::Finished(TaskResult::Failure(error), _))) => {
+                        !tree.record_suppressed_failure(&name, &error)
+                    }
+                    _ => false,
+                }
+            } else {
+                true
+            };
+            if reported {
+                tree.report_end.push(id);
+            }
+        }
+        drop(tree);
+        for warning in warnings {
+            eprintln!("[ll] {}", warning);
+        }
+        self.task_finished.notify_waiters();
+    }
+
+    /// Marks every task that hasn't finished yet (including paused and
+    /// scheduled ones) as failed with `interrupted: <reason>`, e.g. right
+    /// before the process exits. see [shutdown](crate::shutdown)
+    pub fn interrupt_running(&self, reason: &str) {
+        let ids = self
+            .tree_internal
+            .read()
+            .unwrap()
+            .tasks()
+            .filter(|task| !matches!(task.status, TaskStatus::Finished(..)))
+            .map(|task| task.id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            self.mark_done(id, Some(format!("interrupted: {}", reason)));
+        }
+    }
+
+    /// Trace of the tasks that are currently in the tree. Finished tasks are
+    /// only included until they get garbage collected.
+    pub fn trace(&self) -> Trace {
+        let tree = self.tree_internal.read().unwrap();
+        let records = tree
+            .tasks()
+            .map(|task| TaskRecord {
+                report_type: TaskReportType::End,
+                task: Arc::new(tree.report_clone(task)),
+            })
+            .collect::<Vec<_>>();
+        Trace::from_records(&records)
+    }
+
+    /// Resolves once no task in the tree is running (or paused). Useful for
+    /// shutting down daemons after background work is done. Called from
+    /// inside a task this never resolves, since that task is still running;
+    /// use [Task::wait_idle()](crate::Task::wait_idle) there instead. Call
+    /// [flush_async()](TaskTree::flush_async) afterwards to also wait for
+    /// the reports.
+    pub async fn wait_idle(&self) {
+        self.wait_idle_for(None).await
+    }
+
+    /// Resolves once nothing is running under `root` (or in the whole tree)
+    pub(crate) async fn wait_idle_for(&self, root: Option<UniqID>) {
+        loop {
+            // registered before checking, so a task finishing in between
+            // still wakes us up
+            let notified = self.task_finished.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if !self.tree_internal.read().unwrap().has_running_tasks(root) {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub(crate) fn add_dependency_for_task(&self, id: UniqID, depends_on: UniqID) {
+        let mut tree = self.tree_internal.write().unwrap();
+        let Ok(name) = tree.get_task(depends_on).map(|task| task.full_name()) else {
+            return;
+        };
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            if !task_internal
+                .waits_on
+                .iter()
+                .any(|(dep, _)| *dep == depends_on)
+            {
+                task_internal.waits_on.push((depends_on, name));
+            }
+        }
+    }
+
+    pub(crate) fn pause_for_task(&self, id: UniqID, reason: String) {
+        let mut tree = self.tree_internal.write().unwrap();
+        let now = tree.clock.now();
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            task_internal.pause(reason, now);
+        }
+    }
+
+    pub(crate) fn resume_for_task(&self, id: UniqID) {
+        let mut tree = self.tree_internal.write().unwrap();
+        let now = tree.clock.now();
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            task_internal.resume(now);
         }
     }
 
     pub fn add_data<S: Into<String>, D: Into<DataValue>>(&self, id: UniqID, key: S, value: D) {
         let mut tree = self.tree_internal.write().unwrap();
+        let now = tree.clock.now();
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            task_internal.data.add_at(key, value, now);
+        }
+    }
+
+    pub(crate) fn add_data_serde_for_task<T: serde::Serialize + ?Sized>(
+        &self,
+        id: UniqID,
+        key: &str,
+        value: &T,
+    ) -> Result<()> {
+        let mode = self.tree_internal.read().unwrap().serde_data_mode;
+        // serialize before taking the write lock
+        let entries = crate::data::serde_data_entries(key, value, mode)?;
+        let mut tree = self.tree_internal.write().unwrap();
+        let now = tree.clock.now();
         if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
-            task_internal.data.add(key, value);
+            for (key, value) in entries {
+                task_internal.data.add_at(key, value, now);
+            }
         }
+        Ok(())
+    }
+
+    pub(crate) fn attach_for_task(
+        &self,
+        id: UniqID,
+        name: &str,
+        content: AttachmentContent,
+    ) -> Result<()> {
+        let store = self
+            .tree_internal
+            .read()
+            .unwrap()
+            .attachment_store
+            .clone()
+            .context("no attachment store is set, see TaskTree::set_attachment_store()")?;
+        // storing can be slow, so the tree isn't locked while it happens
+        let reference = store.store(id, name, content)?;
+        self.add_data(id, format!("{}#{}", name, ATTACHMENT_TAG), reference);
+        Ok(())
     }
 
     pub fn get_data<S: Into<String>>(&self, id: UniqID, key: S) -> Option<DataValue> {
@@ -298,25 +1179,74 @@ impl TaskTree {
         value: D,
     ) {
         let mut tree = self.tree_internal.write().unwrap();
+        let now = tree.clock.now();
         if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
-            task_internal.data_transitive.add(key, value);
+            let key = key.into();
+            let (bare_key, _) = crate::utils::extract_tags(key.clone());
+            task_internal.cleared_data_transitive.remove(&bare_key);
+            task_internal.data_transitive.add_at(key, value, now);
         }
     }
-    /// Reporters can use this flag to choose to not report errors.
-    /// This is useful for cases where there's a large task chain and every
-    /// single task reports a partial errors (that gets built up with each task)
-    /// It would make sense to report it only once at the top level (thrift
-    /// request, cli call, etc) and only mark other tasks.
-    /// If set to Some, the message inside is what would be reported by default
-    /// instead of reporting errors to avoid confusion (e.g. "error was hidden,
-    /// see ...")
-    pub fn hide_errors_default_msg<S: Into<String>>(&self, msg: Option<S>) {
+
+    /// Removes a transitive key from the task, returning the previous entry.
+    /// Children created after this won't inherit the key.
+    pub(crate) fn clear_data_transitive_for_task(
+        &self,
+        id: UniqID,
+        key: &str,
+    ) -> Option<DataEntry> {
         let mut tree = self.tree_internal.write().unwrap();
-        let msg = msg.map(|msg| Arc::new(msg.into()));
-        tree.hide_errors_default_msg = msg;
+        let task_internal = tree.tasks_internal.get_mut(&id)?;
+        task_internal
+            .cleared_data_transitive
+            .insert(key.to_string());
+        task_internal.data_transitive.remove(key)
     }
 
-    pub(crate) fn hide_error_msg_for_task(&self, id: UniqID, msg: Option<Arc<String>>) {
+    /// Puts back a transitive entry previously returned by
+    /// [clear_data_transitive_for_task()](TaskTree::clear_data_transitive_for_task),
+    /// or keeps the key cleared if there was none.
+    pub(crate) fn restore_data_transitive_for_task(
+        &self,
+        id: UniqID,
+        key: &str,
+        entry: Option<DataEntry>,
+    ) {
+        let mut tree = self.tree_internal.write().unwrap();
+        let now = tree.clock.now();
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            match entry {
+                Some(DataEntry(value, tags)) => {
+                    task_internal.cleared_data_transitive.remove(key);
+                    task_internal.data_transitive.add_at(key, value, now);
+                    if let Some(entry) = task_internal.data_transitive.map.get_mut(key) {
+                        entry.1 = tags;
+                    }
+                }
+                None => {
+                    task_internal
+                        .cleared_data_transitive
+                        .insert(key.to_string());
+                    task_internal.data_transitive.remove(key);
+                }
+            }
+        }
+    }
+    /// Reporters can use this flag to choose to not report errors.
+    /// This is useful for cases where there's a large task chain and every
+    /// single task reports a partial errors (that gets built up with each task)
+    /// It would make sense to report it only once at the top level (thrift
+    /// request, cli call, etc) and only mark other tasks.
+    /// If set to Some, the message inside is what would be reported by default
+    /// instead of reporting errors to avoid confusion (e.g. "error was hidden,
+    /// see ...")
+    pub fn hide_errors_default_msg<S: Into<String>>(&self, msg: Option<S>) {
+        let mut tree = self.tree_internal.write().unwrap();
+        let msg = msg.map(|msg| Arc::new(msg.into()));
+        tree.hide_errors_default_msg = msg;
+    }
+
+    pub(crate) fn hide_error_msg_for_task(&self, id: UniqID, msg: Option<Arc<String>>) {
         let mut tree = self.tree_internal.write().unwrap();
         if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
             task_internal.hide_errors = msg;
@@ -349,23 +1279,320 @@ impl TaskTree {
         tree.error_formatter = error_formatter;
     }
 
+    /// Set how [full names](TaskInternal::full_name) of tasks are rendered.
+    /// Tasks that were already created keep the previous format.
+    pub fn set_full_name_format(&self, format: FullNameFormat) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.full_name_format = Arc::new(format);
+    }
+
+    /// Set how deliveries to reporters that return errors are retried.
+    pub fn set_retry_policy(&self, retry_policy: RetryPolicy) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.retry_policy = retry_policy;
+    }
+
+    /// Set a handler that receives reports that failed to be delivered to a
+    /// reporter after all retries. If not set, such reports are dropped.
+    pub fn set_dead_letter_handler(&self, handler: Option<Arc<dyn DeadLetterHandler>>) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.dead_letter_handler = handler;
+    }
+
+    /// Make spawned tasks whose full name (e.g. `root:db:query`) matches
+    /// `pattern` fail, get delayed or time out. `*` in the pattern matches
+    /// any sequence of characters. If multiple patterns match, the first
+    /// injected one wins. Meant for testing error handling paths. Tasks
+    /// spawned with [Task::spawn_ok()](crate::Task::spawn_ok) can't fail, so
+    /// only the delay of a fault applies to them.
+    pub fn inject_fault<S: Into<String>>(&self, pattern: S, fault: Fault) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.faults.push((pattern.into(), fault));
+    }
+
+    pub fn clear_faults(&self) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.faults.clear();
+    }
+
+    fn fault_for_task(&self, id: UniqID) -> Option<Fault> {
+        let tree = self.tree_internal.read().unwrap();
+        if tree.faults.is_empty() {
+            return None;
+        }
+        let task_internal = tree.get_task(id).ok()?;
+        let full_name = task_internal.path();
+        let fault = tree
+            .faults
+            .iter()
+            .find(|(pattern, _)| name_matches(pattern, &full_name))
+            .map(|(_, fault)| fault.clone())?;
+        if task_internal.infallible {
+            fault.delay_only()
+        } else {
+            Some(fault)
+        }
+    }
+
+    /// Prefix names of all top level tasks (and through them full names of
+    /// all tasks) in this tree with `prefix.`, e.g. `my_service.root:child`.
+    /// see [Task::nest()](crate::Task::nest) for prefixing subtasks.
+    pub fn set_name_prefix<S: Into<String>>(&self, prefix: Option<S>) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.name_prefix = prefix.map(Into::into);
+    }
+
+    /// Treat data keys matching `pattern` as if they were tagged
+    /// `#dontprint`, e.g. `*_token` or `password*`, where `*` matches any
+    /// sequence of characters. Applies to data of every task in the tree,
+    /// including data that was added before the pattern.
+    pub fn add_dontprint_pattern<S: Into<String>>(&self, pattern: S) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.dontprint_patterns.push(pattern.into());
+    }
+
+    /// Replace the redaction rules of the tree. They apply to everything
+    /// reported from now on, including data added before the call.
+    /// see [redaction](crate::redaction)
+    pub fn set_redaction_rules(&self, rules: RedactionRules) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.redaction_rules = rules;
+    }
+
+    /// Validate data of tasks whose full name matches `pattern` when they
+    /// finish. `*` in the pattern matches any sequence of characters. Every
+    /// matching schema is checked.
+    /// see [schema](crate::schema)
+    pub fn add_data_schema<S: Into<String>>(&self, pattern: S, schema: DataSchema) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.data_schemas.push((pattern.into(), schema));
+    }
+
+    /// Whether [Task::data_serde()](crate::Task::data_serde) stores values
+    /// as a single JSON entry (default) or flattens them into one entry per
+    /// field.
+    pub fn set_serde_data_mode(&self, mode: SerdeDataMode) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.serde_data_mode = mode;
+    }
+
+    /// Where [task attachments](crate::Task::attach) are stored. There is no
+    /// store by default and attaching fails until one is set.
+    pub fn set_attachment_store(&self, store: Arc<dyn AttachmentStore>) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.attachment_store = Some(store);
+    }
+
+    /// Replace the clock used for task times, e.g. with a
+    /// [ManualClock](crate::clock::ManualClock) in tests.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.clock = clock;
+    }
+
+    pub fn now(&self) -> SystemTime {
+        self.tree_internal.read().unwrap().clock.now()
+    }
+
+    /// Tasks that are running for longer than the threshold will be flagged as
+    /// stalled and reported to reporters once, with `task_stalled()`.
+    /// This is useful to quickly spot hung operations.
+    /// Thresholds set for specific tags take precedence, see
+    /// [set_stall_threshold_for_tag()](crate::task_tree::TaskTree::set_stall_threshold_for_tag)
+    pub fn set_stall_threshold(&self, threshold: Option<Duration>) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.stall_threshold = threshold;
+    }
+
+    /// Same as [set_stall_threshold()](crate::task_tree::TaskTree::set_stall_threshold)
+    /// but only for tasks that have the given tag (e.g. `db` for `query #db`).
+    /// If a task has multiple tags with thresholds, the shortest one is used.
+    pub fn set_stall_threshold_for_tag<S: Into<String>>(
+        &self,
+        tag: S,
+        threshold: Option<Duration>,
+    ) {
+        let mut tree = self.tree_internal.write().unwrap();
+        let tag = tag.into();
+        match threshold {
+            Some(threshold) => tree.stall_threshold_by_tag.insert(tag, threshold),
+            None => tree.stall_threshold_by_tag.remove(&tag),
+        };
+    }
+
+    /// Report at most `max_per_second` tasks with the same name per second.
+    /// Tasks over the limit still run but are neither reported nor shown in
+    /// the terminal status. When the window closes, the number of
+    /// suppressed tasks is reported as a finished `#rate_limited` task with
+    /// the same name and a `suppressed` data entry. If any of the suppressed
+    /// tasks failed, the summary fails too. Protects reporters from runaway
+    /// loops spawning tasks in a tight cycle.
+    pub fn set_name_rate_limit(&self, max_per_second: Option<u32>) {
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.name_rate_limit = max_per_second;
+    }
+
     /// Add transitive data to the task tree. This transitive data will be
-    /// added to every task created in this task tree
+    /// added to every task created in this task tree. Inside an entered
+    /// [DataScope] it's only added to that scope.
     pub fn add_data_transitive<S: Into<String>, D: Into<DataValue>>(&self, key: S, value: D) {
+        match current_data_scope(self) {
+            Some(scope_id) => {
+                self.add_data_transitive_for_scope(scope_id, key.into(), value.into())
+            }
+            None => {
+                let mut tree = self.tree_internal.write().unwrap();
+                tree.data_transitive.add(key, value);
+            }
+        }
+    }
+
+    /// Open an isolated scope for transitive data, e.g. for one request or
+    /// tenant. Opened inside another scope, it starts with a copy of its
+    /// data. see [data_scope](crate::data_scope)
+    pub fn open_data_scope(self: &Arc<Self>) -> DataScope {
+        let id = UniqID::new();
+        let mut tree = self.tree_internal.write().unwrap();
+        let data = current_data_scope(self)
+            .and_then(|parent| tree.data_scopes.get(&parent))
+            .cloned()
+            .unwrap_or_else(Data::empty);
+        tree.data_scopes.insert(id, data);
+        DataScope::new(id, self.clone())
+    }
+
+    pub(crate) fn add_data_transitive_for_scope(
+        &self,
+        scope_id: UniqID,
+        key: String,
+        value: DataValue,
+    ) {
+        let mut tree = self.tree_internal.write().unwrap();
+        match tree.data_scopes.get_mut(&scope_id) {
+            Some(data) => data.add(key, value),
+            // never fall back to the tree, the data would leak to everyone.
+            // The warning is printed after unlocking the tree, see mark_done()
+            None => {
+                drop(tree);
+                eprintln!(
+                    "[ll] ignoring transitive data `{}` of a closed data scope",
+                    key
+                );
+            }
+        }
+    }
+
+    pub(crate) fn close_data_scope(&self, scope_id: UniqID) {
         let mut tree = self.tree_internal.write().unwrap();
-        tree.data_transitive.add(key, value);
+        tree.data_scopes.remove(&scope_id);
+    }
+
+    /// Add `hostname`, `pid`, `binary` and `version` (from the `LL_VERSION`
+    /// env variable, if it's set) to transitive data, so every reported task
+    /// carries where it came from. They are tagged `#dontprint` to keep them
+    /// out of console output. To use the version of the application crate
+    /// instead, add it directly:
+    /// `tt.add_data_transitive("version#dontprint", env!("CARGO_PKG_VERSION"))`
+    pub fn add_default_fields(&self) {
+        let hostname = std::env::var("HOSTNAME")
+            .ok()
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|hostname| hostname.trim().to_string())
+            .filter(|hostname| !hostname.is_empty());
+        let binary = std::env::current_exe().ok().and_then(|exe| {
+            exe.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        });
+
+        let mut tree = self.tree_internal.write().unwrap();
+        tree.data_transitive
+            .add("pid#dontprint", std::process::id());
+        if let Some(hostname) = hostname {
+            tree.data_transitive.add("hostname#dontprint", hostname);
+        }
+        if let Some(binary) = binary {
+            tree.data_transitive.add("binary#dontprint", binary);
+        }
+        if let Ok(version) = std::env::var("LL_VERSION") {
+            tree.data_transitive.add("version#dontprint", version);
+        }
     }
 
     pub fn task_progress(&self, id: UniqID, done: i64, total: i64) {
         let mut tree = self.tree_internal.write().unwrap();
         if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
             task_internal.progress = Some((done, total));
+            task_internal.progress_f64 = None;
+            tree.update_parent_progress(id);
+        }
+    }
+
+    /// see [Task::progress_f64()](crate::Task::progress_f64)
+    pub fn task_progress_f64(&self, id: UniqID, done: f64, total: f64) {
+        let mut tree = self.tree_internal.write().unwrap();
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            // never complete before `done` reaches `total`, and always once
+            // it does
+            let total_units = total.ceil().max(1.0);
+            let done_units = if done >= total {
+                total_units
+            } else {
+                done.floor()
+            };
+            task_internal.progress = Some((done_units as i64, total_units as i64));
+            task_internal.progress_f64 = Some((done, total));
+            tree.update_parent_progress(id);
+        }
+    }
+
+    pub(crate) fn progress_format_for_task(&self, id: UniqID, format: Option<ProgressFormat>) {
+        let mut tree = self.tree_internal.write().unwrap();
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            task_internal.progress_format = format;
+        }
+    }
+
+    /// When enabled, task progress is computed from its children. Every child
+    /// without its own progress counts as a single unit of work that is done
+    /// when the child is finished, children that report progress contribute
+    /// their (done, total) values. This is useful for umbrella tasks (e.g.
+    /// `compile_all`) that only spawn subtasks and never call `progress()`.
+    pub fn aggregate_progress_for_task(&self, id: UniqID, enabled: bool) {
+        let mut tree = self.tree_internal.write().unwrap();
+        let children = tree
+            .parent_to_children
+            .get(&id)
+            .cloned()
+            .unwrap_or_default();
+        let children_progress = if enabled {
+            Some(
+                children
+                    .into_iter()
+                    .filter_map(|child_id| {
+                        let child = tree.tasks_internal.get(&child_id)?;
+                        Some((child_id, child.progress_contribution()))
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+        if let Some(task_internal) = tree.tasks_internal.get_mut(&id) {
+            task_internal.children_progress = children_progress;
+            if task_internal.children_progress.is_some() {
+                task_internal.progress = task_internal.aggregated_progress();
+                task_internal.progress_f64 = None;
+            }
+            tree.update_parent_progress(id);
         }
     }
 
-    fn get_cloned_task(&self, id: UniqID) -> Option<TaskInternal> {
+    fn get_redacted_task(&self, id: UniqID) -> Option<TaskInternal> {
         let tree = self.tree_internal.read().unwrap();
-        tree.get_task(id).ok().cloned()
+        tree.get_task(id)
+            .ok()
+            .map(|task_internal| tree.redacted_clone(task_internal))
     }
 
     /// If force_flush set to true, this function will block the thread until everything
@@ -377,26 +1604,207 @@ impl TaskTree {
         }
     }
 
+    /// Block until all tasks that started or finished before this call are
+    /// delivered to every reporter. Async reporters are only waited for when
+    /// called outside of a tokio runtime (waiting on them from inside of it
+    /// can deadlock), use [flush_async()](TaskTree::flush_async) there.
+    /// Returns right away when called by a reporter of this tree from within
+    /// a delivery.
+    pub fn flush_blocking(&self) {
+        self.report_all();
+        if tokio::runtime::Handle::try_current().is_err() {
+            for async_reporter in self.get_async_reporters() {
+                async_reporter.flush_blocking();
+            }
+        }
+    }
+
+    /// Resolves once all tasks that started or finished before this call are
+    /// delivered to every reporter, including async reporters.
+    pub async fn flush_async(self: &Arc<Self>) {
+        // Reporting from a separate thread, so that full async reporter
+        // buffers can block without stalling the runtime that drains them.
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let clone = self.clone();
+        thread::spawn(move || {
+            clone.report_all();
+            sender.send(()).ok();
+        });
+        receiver.await.ok();
+        for async_reporter in self.get_async_reporters() {
+            async_reporter.flush().await;
+        }
+    }
+
+    fn get_async_reporters(&self) -> Vec<Arc<AsyncReporterBridge>> {
+        let tree = self.tree_internal.read().unwrap();
+        tree.async_reporters.values().cloned().collect()
+    }
+
     pub fn report_all(&self) {
+        // a reporter of this tree called back into it (e.g. flush_blocking()
+        // or a task finishing in a callback). The report lock is held by
+        // this very thread, what was queued is delivered by the next call.
+        let Some(_reporting) = Reporting::enter(self) else {
+            return;
+        };
+        let _report_lock = self.report_lock.lock().unwrap();
         let mut tree = self.tree_internal.write().unwrap();
-        let (start_tasks, end_tasks, reporters) = tree.get_tasks_and_reporters();
+        let (start_tasks, stalled_tasks, end_tasks, reporters) = tree.get_tasks_and_reporters();
+        let retry_policy = tree.retry_policy;
+        let dead_letter_handler = tree.dead_letter_handler.clone();
         drop(tree);
-        for reporter in reporters {
-            for task in &start_tasks {
-                reporter.task_start(task.clone());
+        let deliver = |reporter: &dyn Reporter, task: &Arc<TaskInternal>, report_type| {
+            deliver_with_retries(
+                reporter,
+                task,
+                report_type,
+                &retry_policy,
+                dead_letter_handler.as_deref(),
+            )
+        };
+        for (handle, reporter) in reporters {
+            let mut stats = ReporterStats::default();
+            let reports = start_tasks
+                .iter()
+                .map(|task| (task, TaskReportType::Start))
+                .chain(
+                    stalled_tasks
+                        .iter()
+                        .map(|task| (task, TaskReportType::Stalled)),
+                )
+                .chain(end_tasks.iter().map(|task| (task, TaskReportType::End)));
+            for (task, report_type) in reports {
+                let started = std::time::Instant::now();
+                let (delivered, errors) = deliver(&*reporter, task, report_type);
+                stats.record(delivered, errors, started.elapsed());
             }
-            for task in &end_tasks {
-                reporter.task_end(task.clone());
+            if stats != ReporterStats::default() {
+                // unless the reporter was removed while it was being
+                // delivered to
+                let tree = self.tree_internal.read().unwrap();
+                if tree.reporters.contains_key(&handle) {
+                    let mut all_stats = self.reporter_stats.lock().unwrap();
+                    all_stats.entry(handle).or_default().merge(&stats);
+                }
             }
         }
     }
+
+    /// Delivery statistics of every reporter, so lost reports don't go
+    /// unnoticed. Stats of removed reporters are discarded.
+    pub fn reporter_stats(&self) -> BTreeMap<ReporterHandle, ReporterStats> {
+        self.reporter_stats.lock().unwrap().clone()
+    }
+
+    /// Print [reporter_stats()](TaskTree::reporter_stats) to stderr every
+    /// `interval` until the returned handle or the task tree is dropped
+    pub fn log_reporter_stats_every(self: &Arc<Self>, interval: Duration) -> ReporterStatsHandle {
+        let task_tree = Arc::downgrade(self);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = stopped.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let Some(task_tree) = task_tree.upgrade() else {
+                break;
+            };
+            if stopped_clone.load(Ordering::SeqCst) {
+                break;
+            }
+            for (handle, stats) in task_tree.reporter_stats() {
+                eprintln!("[ll] reporter {}: {}", handle.0, stats);
+            }
+        });
+        ReporterStatsHandle { stopped }
+    }
 }
 
-impl TaskTreeInternal {
-    pub fn get_task(&self, id: UniqID) -> Result<&TaskInternal> {
-        self.tasks_internal.get(&id).context("task must be present")
+/// Marks the tree as being reported by the current thread until dropped
+struct Reporting(usize);
+
+impl Reporting {
+    /// None if the current thread is already reporting the tree
+    fn enter(tree: &TaskTree) -> Option<Self> {
+        let id = tree as *const TaskTree as usize;
+        REPORTING.with(|trees| {
+            let mut trees = trees.borrow_mut();
+            if trees.contains(&id) {
+                None
+            } else {
+                trees.push(id);
+                Some(Reporting(id))
+            }
+        })
+    }
+}
+
+impl Drop for Reporting {
+    fn drop(&mut self) {
+        REPORTING.with(|trees| trees.borrow_mut().retain(|id| *id != self.0));
+    }
+}
+
+/// Marks a spawned task as cancelled if its future is dropped before the
+/// task finishes, e.g. because the tokio task running it was aborted.
+/// Forgotten once the future gets to finish the task itself.
+struct CancelOnDrop<'a> {
+    tree: &'a Arc<TaskTree>,
+    id: UniqID,
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        self.tree.mark_done(self.id, Some("cancelled".to_string()));
+        self.tree.maybe_force_flush();
+    }
+}
+
+fn default_format_error(err: &anyhow::Error) -> String {
+    #[cfg(feature = "eyre")]
+    if let Some(msg) = crate::eyre_compat::format_error(err) {
+        return msg;
+    }
+    format!("{:?}", err)
+}
+
+/// Returns whether the report was delivered and how many attempts failed
+fn deliver_with_retries(
+    reporter: &dyn Reporter,
+    task: &Arc<TaskInternal>,
+    report_type: TaskReportType,
+    retry_policy: &RetryPolicy,
+    dead_letter_handler: Option<&dyn DeadLetterHandler>,
+) -> (bool, u32) {
+    let mut backoff = retry_policy.initial_backoff;
+    let mut attempt = 0;
+    loop {
+        let result = match report_type {
+            TaskReportType::Start => reporter.try_task_start(task.clone()),
+            TaskReportType::Stalled => reporter.try_task_stalled(task.clone()),
+            TaskReportType::End => reporter.try_task_end(task.clone()),
+        };
+
+        match result {
+            Ok(()) => return (true, attempt),
+            // sleeping on the runtime thread can't make room in the buffer
+            Err(err) if attempt >= retry_policy.max_retries || err.is::<BufferFull>() => {
+                if let Some(handler) = dead_letter_handler {
+                    handler.dead_letter(task.clone(), report_type, err);
+                }
+                return (false, attempt + 1);
+            }
+            Err(_) => {
+                thread::sleep(backoff);
+                backoff = std::cmp::min(backoff * 2, retry_policy.max_backoff);
+                attempt += 1;
+            }
+        }
     }
+}
 
+/// Tree structure accessors used by the terminal status
+#[cfg_attr(not(feature = "term-status"), allow(dead_code))]
+impl TaskTreeInternal {
     pub fn root_tasks(&self) -> &BTreeSet<UniqID> {
         &self.root_tasks
     }
@@ -408,6 +1816,58 @@ impl TaskTreeInternal {
     pub fn parent_to_children(&self) -> &BTreeMap<UniqID, BTreeSet<UniqID>> {
         &self.parent_to_children
     }
+}
+
+impl TaskTreeInternal {
+    pub fn get_task(&self, id: UniqID) -> Result<&TaskInternal> {
+        self.tasks_internal.get(&id).context("task must be present")
+    }
+
+    /// Whether any task under `root` (not counting `root` itself), or any
+    /// task at all if there's no root, hasn't finished yet
+    fn has_running_tasks(&self, root: Option<UniqID>) -> bool {
+        let is_running = |task: &TaskInternal| !matches!(task.status, TaskStatus::Finished(..));
+        let Some(root) = root else {
+            return self.tasks_internal.values().any(is_running);
+        };
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            for child_id in self.parent_to_children.get(&id).into_iter().flatten() {
+                if self.get_task(*child_id).is_ok_and(is_running) {
+                    return true;
+                }
+                stack.push(*child_id);
+            }
+        }
+        false
+    }
+
+    /// All tasks that are currently in the tree, including finished ones that
+    /// weren't garbage collected yet
+    pub fn tasks(&self) -> impl Iterator<Item = &TaskInternal> {
+        self.tasks_internal.values()
+    }
+
+    /// Propagate progress contribution of a task to all of its parents that
+    /// aggregate progress from their children (and further up the tree)
+    fn update_parent_progress(&mut self, id: UniqID) {
+        let contribution = match self.tasks_internal.get(&id) {
+            Some(task_internal) => task_internal.progress_contribution(),
+            None => return,
+        };
+
+        let parents = self.child_to_parents.get(&id).cloned().unwrap_or_default();
+        for parent_id in parents {
+            if let Some(parent) = self.tasks_internal.get_mut(&parent_id) {
+                if let Some(children_progress) = &mut parent.children_progress {
+                    children_progress.insert(id, contribution);
+                    parent.progress = parent.aggregated_progress();
+                    parent.progress_f64 = None;
+                    self.update_parent_progress(parent_id);
+                }
+            }
+        }
+    }
 
     fn mark_for_gc(&mut self, id: UniqID) {
         let mut stack = vec![id];
@@ -429,10 +1889,9 @@ impl TaskTreeInternal {
             .iter()
             .all(|(_, finished)| *finished)
         {
+            let now = self.clock.now();
             for id in tasks_to_finished_status.keys().copied() {
-                self.tasks_marked_for_deletion
-                    .entry(id)
-                    .or_insert_with(SystemTime::now);
+                self.tasks_marked_for_deletion.entry(id).or_insert(now);
             }
 
             // This sub branch might have been holding other parent branches that
@@ -445,10 +1904,40 @@ impl TaskTreeInternal {
         }
     }
 
+    pub(crate) fn detect_stalled(&mut self) {
+        let now = self.clock.now();
+        let stall_threshold = self.stall_threshold;
+        let stall_threshold_by_tag = &self.stall_threshold_by_tag;
+        for (id, task_internal) in &mut self.tasks_internal {
+            if task_internal.stalled
+                || !matches!(task_internal.status, TaskStatus::Running)
+                || self.rate_limited.contains(id)
+            {
+                continue;
+            }
+
+            let threshold = task_internal
+                .tags
+                .iter()
+                .filter_map(|tag| stall_threshold_by_tag.get(tag))
+                .min()
+                .or(stall_threshold.as_ref());
+
+            // time spent paused doesn't count towards stalling
+            if let Some(threshold) = threshold {
+                if task_internal.active_duration(now) > *threshold {
+                    task_internal.stalled = true;
+                    self.report_stalled.push(*id);
+                }
+            }
+        }
+    }
+
     fn garbage_collect(&mut self) {
         let mut will_delete = vec![];
+        let now = self.clock.now();
         for (id, time) in &self.tasks_marked_for_deletion {
-            if let Ok(elapsed) = time.elapsed() {
+            if let Ok(elapsed) = now.duration_since(*time) {
                 if elapsed > Duration::from_millis(self.remove_task_after_done_ms) {
                     will_delete.push(*id);
                 }
@@ -470,63 +1959,503 @@ impl TaskTreeInternal {
         }
     }
 
+    /// Violations of all data schemas matching the task and whether any of
+    /// the violated schemas is strict. `None` if the task is valid.
+    fn schema_violations(&self, id: UniqID) -> Option<(Vec<String>, bool)> {
+        if self.data_schemas.is_empty() {
+            return None;
+        }
+        let task_internal = self.get_task(id).ok()?;
+        let full_name = task_internal.path();
+        let mut violations = vec![];
+        let mut strict = false;
+        for (pattern, schema) in &self.data_schemas {
+            if !name_matches(pattern, &full_name) {
+                continue;
+            }
+            let schema_violations = schema.violations(task_internal);
+            if !schema_violations.is_empty() {
+                strict |= schema.is_strict();
+                violations.extend(schema_violations);
+            }
+        }
+        if violations.is_empty() {
+            None
+        } else {
+            Some((violations, strict))
+        }
+    }
+
+    /// Clone of the task with `{key}` placeholders in its name resolved
+    /// and values of keys denied by the
+    /// [redaction rules](TaskTree::set_redaction_rules) replaced
+    fn redacted_clone(&self, task_internal: &TaskInternal) -> TaskInternal {
+        let mut task_internal = task_internal.clone();
+        task_internal.name = task_internal.redacted_name(&self.redaction_rules);
+        if !self.redaction_rules.is_empty() {
+            self.redaction_rules.apply(&mut task_internal.data);
+            self.redaction_rules
+                .apply(&mut task_internal.data_transitive);
+        }
+        task_internal
+    }
+
+    /// Clone of the task the way reporters should see it:
+    /// - `{key}` placeholders in its own name and in its parent names are
+    ///   resolved from the data of the corresponding task. Ancestors that
+    ///   were already garbage collected keep their raw names.
+    /// - data redacted by the [redaction rules](TaskTree::set_redaction_rules)
+    ///   is replaced, in names too.
+    /// - data keys matching [dontprint patterns](TaskTree::add_dontprint_pattern)
+    ///   are tagged `#dontprint`.
+    fn report_clone(&self, task_internal: &TaskInternal) -> TaskInternal {
+        let mut task_internal = self.redacted_clone(task_internal);
+
+        if !self.dontprint_patterns.is_empty() {
+            let data = task_internal.data.map.iter_mut();
+            for (key, entry) in data.chain(task_internal.data_transitive.map.iter_mut()) {
+                if self
+                    .dontprint_patterns
+                    .iter()
+                    .any(|pattern| name_matches(pattern, key))
+                {
+                    entry.1.insert(DONTPRINT_TAG.to_string());
+                }
+            }
+        }
+
+        let mut parent_id = task_internal.parent_id;
+        for parent_name in task_internal.parent_names.iter_mut().rev() {
+            match parent_id.and_then(|id| self.tasks_internal.get(&id)) {
+                Some(parent) => {
+                    *parent_name = parent.redacted_name(&self.redaction_rules);
+                    parent_id = parent.parent_id;
+                }
+                None => break,
+            }
+        }
+        task_internal
+    }
+
+    /// Counts the task towards the rate limit of its name and returns false
+    /// if it's over the limit
+    fn allow_report_for_name(&mut self, name: &str, now: SystemTime) -> bool {
+        let Some(limit) = self.name_rate_limit else {
+            return true;
+        };
+        if self
+            .name_rate_windows
+            .get(name)
+            .is_some_and(|window| window.is_expired(now))
+        {
+            if let Some(window) = self.name_rate_windows.remove(name) {
+                self.rate_limit_summaries.extend(window.into_summary(now));
+            }
+        }
+        let window = self
+            .name_rate_windows
+            .entry(name.to_string())
+            .or_insert_with(|| NameRateWindow {
+                started_at: now,
+                reported: 0,
+                suppressed: 0,
+                failed: 0,
+                last_error: None,
+                first_suppressed: None,
+            });
+        if window.reported < limit {
+            window.reported += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn record_suppressed(&mut self, task_internal: &TaskInternal) {
+        if let Some(window) = self.name_rate_windows.get_mut(&task_internal.name) {
+            window.suppressed += 1;
+            if window.first_suppressed.is_none() {
+                window.first_suppressed = Some(task_internal.clone());
+            }
+        }
+    }
+
+    /// Counts a failed suppressed task towards the summary of its window.
+    /// Returns false if the window was already closed, in which case the
+    /// failure has to be reported on its own.
+    fn record_suppressed_failure(&mut self, name: &str, error: &str) -> bool {
+        match self.name_rate_windows.get_mut(name) {
+            Some(window) => {
+                window.failed += 1;
+                window.last_error = Some(error.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Closes rate limit windows older than a second and returns summary
+    /// tasks for the ones that suppressed anything
+    fn close_rate_windows(&mut self) -> Vec<TaskInternal> {
+        let now = self.clock.now();
+        let mut summaries = std::mem::take(&mut self.rate_limit_summaries);
+        let expired = self
+            .name_rate_windows
+            .iter()
+            .filter(|(_, window)| window.is_expired(now))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for name in expired {
+            if let Some(window) = self.name_rate_windows.remove(&name) {
+                summaries.extend(window.into_summary(now));
+            }
+        }
+        summaries.sort_by_key(|summary| summary.started_at);
+        summaries
+    }
+
     #[allow(clippy::type_complexity)]
     fn get_tasks_and_reporters(
         &mut self,
     ) -> (
         Vec<Arc<TaskInternal>>,
         Vec<Arc<TaskInternal>>,
-        Vec<Arc<dyn Reporter>>,
+        Vec<Arc<TaskInternal>>,
+        Vec<(ReporterHandle, Arc<dyn Reporter>)>,
     ) {
         let mut start_ids = vec![];
         std::mem::swap(&mut start_ids, &mut self.report_start);
+        let mut stalled_ids = vec![];
+        std::mem::swap(&mut stalled_ids, &mut self.report_stalled);
         let mut end_ids = vec![];
         std::mem::swap(&mut end_ids, &mut self.report_end);
 
         let mut start_tasks = vec![];
+        let mut stalled_tasks = vec![];
         let mut end_tasks = vec![];
 
         for id in start_ids {
             if let Ok(task_internal) = self.get_task(id) {
-                start_tasks.push(Arc::new(task_internal.clone()));
+                start_tasks.push(self.report_clone(task_internal));
+            }
+        }
+        for id in stalled_ids {
+            if let Ok(task_internal) = self.get_task(id) {
+                stalled_tasks.push(self.report_clone(task_internal));
             }
         }
         for id in end_ids {
             if let Ok(task_internal) = self.get_task(id) {
-                end_tasks.push(Arc::new(task_internal.clone()));
+                end_tasks.push(self.report_clone(task_internal));
             }
         }
 
-        let reporters = self.reporters.clone();
+        // summaries cover earlier windows, so they go before this batch
+        let mut summaries = self.close_rate_windows();
+        summaries.append(&mut end_tasks);
+        let mut end_tasks = summaries;
+
+        // in the same order report_all() delivers them
+        for task in start_tasks
+            .iter_mut()
+            .chain(&mut stalled_tasks)
+            .chain(&mut end_tasks)
+        {
+            self.report_seq += 1;
+            task.report_seq = self.report_seq;
+        }
+        let into_arcs = |tasks: Vec<TaskInternal>| tasks.into_iter().map(Arc::new).collect();
+
+        let reporters = self
+            .reporters
+            .iter()
+            .map(|(handle, reporter)| (*handle, reporter.clone()))
+            .collect();
+
+        (
+            into_arcs(start_tasks),
+            into_arcs(stalled_tasks),
+            into_arcs(end_tasks),
+            reporters,
+        )
+    }
+}
+
+/// At most two decimals, without trailing zeros
+fn format_f64(value: f64) -> String {
+    let formatted = format!("{:.2}", value);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
 
-        (start_tasks, end_tasks, reporters)
+async fn wait_for_abort(abort: &mut tokio::sync::watch::Receiver<Option<String>>) -> String {
+    loop {
+        if let Some(cause) = abort.borrow_and_update().clone() {
+            return cause;
+        }
+        if abort.changed().await.is_err() {
+            // the fail-fast task is gone, nothing can abort us anymore
+            std::future::pending::<()>().await;
+        }
     }
 }
 
 impl TaskInternal {
-    pub(crate) fn mark_done(&mut self, error_message: Option<String>) {
+    pub(crate) fn mark_done(&mut self, error_message: Option<String>, now: SystemTime) {
+        self.resume(now);
         let task_status = match error_message {
             None => TaskResult::Success,
             Some(msg) => TaskResult::Failure(msg),
         };
-        self.status = TaskStatus::Finished(task_status, SystemTime::now());
+        self.status = TaskStatus::Finished(task_status, now);
+    }
+
+    fn pause(&mut self, reason: String, now: SystemTime) {
+        match self.status {
+            TaskStatus::Running => {
+                self.status = TaskStatus::Paused(reason);
+                self.paused_at = Some(now);
+            }
+            TaskStatus::Paused(_) => self.status = TaskStatus::Paused(reason),
+            TaskStatus::Scheduled(_) | TaskStatus::Finished(..) => {}
+        }
+    }
+
+    fn resume(&mut self, now: SystemTime) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_time += now.duration_since(paused_at).unwrap_or_default();
+        }
+        if let TaskStatus::Paused(_) = self.status {
+            self.status = TaskStatus::Running;
+        }
+    }
+
+    /// Total time the task spent paused, including the current pause.
+    pub fn paused_duration(&self, now: SystemTime) -> Duration {
+        let current = self
+            .paused_at
+            .and_then(|paused_at| now.duration_since(paused_at).ok())
+            .unwrap_or_default();
+        self.paused_time + current
+    }
+
+    /// How long the task has been running (or ran for, if it's finished)
+    /// excluding the time it spent paused.
+    pub fn active_duration(&self, now: SystemTime) -> Duration {
+        let end = match self.status {
+            TaskStatus::Finished(_, finished_at) => finished_at,
+            _ => now,
+        };
+        let total = end.duration_since(self.started_at).unwrap_or_default();
+        total.saturating_sub(self.paused_duration(end))
+    }
+
+    /// (done, total) units of work this task represents in its parent's
+    /// aggregated progress.
+    fn progress_contribution(&self) -> (i64, i64) {
+        let finished = matches!(self.status, TaskStatus::Finished(..));
+        match (self.progress, finished) {
+            (Some((_, total)), true) if total > 0 => (total, total),
+            (Some((done, total)), false) if total > 0 => (done, total),
+            (_, true) => (1, 1),
+            (_, false) => (0, 1),
+        }
+    }
+
+    fn aggregated_progress(&self) -> Option<(i64, i64)> {
+        self.children_progress.as_ref().map(|children_progress| {
+            children_progress
+                .values()
+                .fold((0, 0), |(done, total), (d, t)| (done + d, total + t))
+        })
+    }
+
+    pub fn id(&self) -> UniqID {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn parent_id(&self) -> Option<UniqID> {
+        self.parent_id
+    }
+
+    /// Names of all parents, starting from the root task
+    pub fn parent_names(&self) -> &[String] {
+        &self.parent_names
+    }
+
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    pub fn status(&self) -> &TaskStatus {
+        &self.status
+    }
+
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+
+    pub fn data_transitive(&self) -> &Data {
+        &self.data_transitive
+    }
+
+    pub fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+
+    /// How many items are done out of how many items there are total, e.g.
+    /// `(1, 10)` for a task that finished 1 out of 10 pieces of work
+    pub fn progress(&self) -> Option<(i64, i64)> {
+        self.progress
+    }
+
+    /// Same as [progress()](TaskInternal::progress), but keeps fractional
+    /// values reported with [Task::progress_f64()](crate::Task::progress_f64)
+    pub fn progress_f64(&self) -> Option<(f64, f64)> {
+        self.progress_f64.or_else(|| {
+            self.progress
+                .map(|(done, total)| (done as f64, total as f64))
+        })
     }
 
+    /// Progress the way it should be displayed, e.g. `3/10` or `2.5/10`,
+    /// unless the task has a custom
+    /// [progress format](crate::Task::progress_format)
+    pub fn format_progress(&self) -> Option<String> {
+        let (done, total) = self.progress_f64()?;
+        Some(
+            match (&self.progress_format, self.progress, self.progress_f64) {
+                (Some(format), _, _) => format(done, total),
+                (None, Some((done, total)), None) => format!("{}/{}", done, total),
+                (None, _, _) => format!("{}/{}", format_f64(done), format_f64(total)),
+            },
+        )
+    }
+
+    /// Message that replaces errors of this task in reports, if they're
+    /// hidden. see [Task::hide_error_msg()](crate::Task::hide_error_msg)
+    pub fn hide_errors(&self) -> Option<&str> {
+        self.hide_errors.as_deref().map(String::as_str)
+    }
+
+    pub fn attach_transitive_data_to_errors(&self) -> bool {
+        self.attach_transitive_data_to_errors
+    }
+
+    /// see [set_stall_threshold()](crate::task_tree::TaskTree::set_stall_threshold)
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    /// Tasks this task waits on (id and full name)
+    pub fn waits_on(&self) -> &[(UniqID, String)] {
+        &self.waits_on
+    }
+
+    /// Number of subtasks created directly under this task so far,
+    /// including the ones that already finished
+    pub fn child_count(&self) -> usize {
+        self.child_count
+    }
+
+    /// Sequence number of the report this task was delivered with, starting
+    /// from 1 and increasing by one with every report of the task tree, so
+    /// gaps and reordering can be detected after transport. Reports are
+    /// delivered in sequence order, so the start report of a task always
+    /// comes before its end report. 0 for tasks that weren't reported.
+    pub fn report_seq(&self) -> u64 {
+        self.report_seq
+    }
+
+    /// Level from the `#l0`..`#l3` tags of the task, [Level::L1] if it has
+    /// none
+    pub fn level(&self) -> Level {
+        crate::reporters::utils::parse_level(self)
+    }
+
+    /// Works with both string tags and typed [Tag](crate::tag::Tag)s.
+    pub fn has_tag<T: AsRef<str>>(&self, tag: T) -> bool {
+        self.tags.contains(tag.as_ref())
+    }
+
+    /// Names of the parents and the task, e.g. `root:request:db_query`. The
+    /// format can be changed with
+    /// [set_full_name_format()](TaskTree::set_full_name_format)
     pub fn full_name(&self) -> String {
+        let format = &self.full_name_format;
+        let skip = format
+            .max_depth
+            .map_or(0, |depth| self.parent_names.len().saturating_sub(depth));
         let mut full_name = String::new();
-        for parent_name in &self.parent_names {
+        if format.with_id {
+            full_name.push_str(&format!("{}-", self.id));
+        }
+        for parent_name in &self.parent_names[skip..] {
             full_name.push_str(parent_name);
-            full_name.push(':');
+            full_name.push_str(&format.separator);
         }
         full_name.push_str(&self.name);
         full_name
     }
 
+    /// Full name in the default format, which name patterns are matched
+    /// against regardless of the configured format
+    pub(crate) fn path(&self) -> String {
+        let mut path = self.parent_names.join(":");
+        if !path.is_empty() {
+            path.push(':');
+        }
+        path.push_str(&self.name);
+        path
+    }
+
+    /// Groups of the task from its `#group:<name>` tags.
+    /// see [TaskBuilder::group()](crate::TaskBuilder::group)
+    pub fn groups(&self) -> impl Iterator<Item = &str> {
+        self.tags
+            .iter()
+            .filter_map(|tag| tag.strip_prefix(crate::tag::GROUP_TAG_PREFIX))
+    }
+
+    /// Priority from the `#p0`, `#p1`, ... tag of the task, where lower
+    /// means more important. Tasks without one get [DEFAULT_PRIORITY].
+    /// Reporters show siblings with more important tasks first.
+    pub fn priority(&self) -> u32 {
+        crate::utils::extract_priority_from_tags(&self.tags).unwrap_or(DEFAULT_PRIORITY)
+    }
+
+    /// Task name with `{key}` placeholders resolved from this task's data
+    /// (own data first, then transitive).
+    pub fn interpolated_name(&self) -> String {
+        self.redacted_name(&RedactionRules::new())
+    }
+
+    /// Same as [interpolated_name()](TaskInternal::interpolated_name), but
+    /// placeholders of redacted keys resolve to [REDACTED]
+    pub(crate) fn redacted_name(&self, rules: &RedactionRules) -> String {
+        crate::utils::interpolate(&self.name, |key| {
+            if rules.redacts(key) {
+                return Some(REDACTED.to_string());
+            }
+            self.data
+                .map
+                .get(key)
+                .or_else(|| self.data_transitive.map.get(key))
+                .map(|entry| entry.0.to_string())
+        })
+    }
+
     pub fn all_data(
         &self,
     ) -> std::iter::Chain<
-        std::collections::btree_map::Iter<String, DataEntry>,
-        std::collections::btree_map::Iter<String, DataEntry>,
+        std::collections::btree_map::Iter<'_, String, DataEntry>,
+        std::collections::btree_map::Iter<'_, String, DataEntry>,
     > {
         self.data.map.iter().chain(self.data_transitive.map.iter())
     }
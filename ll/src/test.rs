@@ -0,0 +1,231 @@
+/*!
+Helpers for testing code instrumented with ll.
+*/
+use crate::reporters::CaptureReporter;
+use crate::task_tree::TaskTree;
+use crate::trace::Trace;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Run `f` with its own task tree and a capture reporter attached to it, so
+/// tests running in parallel don't share the global `TASK_TREE`. All reports
+/// are delivered to the capture reporter by the time this function returns.
+///
+/// ```
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let capture = ll::test::with_task_tree(|tree, capture| async move {
+///     let root = tree.create_task("root");
+///     root.spawn_sync("child", |_| Ok(()))?;
+///     anyhow::Ok(capture)
+/// })
+/// .await?;
+///
+/// assert!(capture.finished("root:child").is_some());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn with_task_tree<F, FT, T>(f: F) -> T
+where
+    F: FnOnce(Arc<TaskTree>, CaptureReporter) -> FT,
+    FT: Future<Output = T>,
+{
+    let tree = TaskTree::new();
+    let capture = CaptureReporter::new();
+    tree.add_reporter(Arc::new(capture.clone()));
+    let result = f(tree.clone(), capture).await;
+    tree.flush_async().await;
+    result
+}
+
+/// Fault that can be injected into spawned tasks to exercise error handling
+/// and reporter code paths.
+/// see [inject_fault()](crate::task_tree::TaskTree::inject_fault)
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Fail the task with the given error message without running it.
+    Fail(String),
+    /// Sleep before running the task.
+    Delay(Duration),
+    /// Sleep for the given duration and then fail the task with a timeout
+    /// error without running it.
+    Timeout(Duration),
+}
+
+impl Fault {
+    pub(crate) fn error(&self) -> Option<anyhow::Error> {
+        match self {
+            Fault::Fail(msg) => Some(anyhow::anyhow!("{}", msg)),
+            Fault::Timeout(duration) => Some(anyhow::anyhow!("timed out after {:?}", duration)),
+            Fault::Delay(_) => None,
+        }
+    }
+
+    pub(crate) fn sleep_duration(&self) -> Option<Duration> {
+        match self {
+            Fault::Delay(duration) | Fault::Timeout(duration) => Some(*duration),
+            Fault::Fail(_) => None,
+        }
+    }
+
+    /// The fault without the failure, for tasks that can't fail
+    pub(crate) fn delay_only(self) -> Option<Fault> {
+        self.sleep_duration().map(Fault::Delay)
+    }
+}
+
+/// Match a full task name (e.g. `root:db:query`), a data key or a file name
+/// against a pattern where `*` matches any sequence of characters.
+pub(crate) fn name_matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    if !name.starts_with(first) {
+        return false;
+    }
+    let mut rest = &name[first.len()..];
+    let parts = parts.collect::<Vec<_>>();
+    if parts.is_empty() {
+        return rest.is_empty();
+    }
+    let (last, middle) = parts.split_last().unwrap();
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Compare a trace against a checked in golden JSON file and panic with a
+/// line diff if they're different. Ids and times are normalized before
+/// comparing (see [Trace::normalize]). Run with `LL_UPDATE_GOLDEN=1` to
+/// create or update the golden file instead.
+pub fn assert_golden_trace<P: AsRef<Path>>(trace: &Trace, golden_path: P) {
+    let path = golden_path.as_ref();
+    let mut trace = trace.clone();
+    trace.normalize();
+    let actual = format!("{}\n", trace.to_json_pretty());
+
+    if std::env::var("LL_UPDATE_GOLDEN").is_ok() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).expect("failed to create golden file directory");
+        }
+        std::fs::write(path, actual).expect("failed to write golden file");
+        return;
+    }
+
+    let expected = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read golden file {}: {}\nRun with LL_UPDATE_GOLDEN=1 to create it",
+            path.display(),
+            e
+        )
+    });
+
+    if expected != actual {
+        panic!(
+            "Trace doesn't match golden file {}\nRun with LL_UPDATE_GOLDEN=1 to update it\n\n{}",
+            path.display(),
+            line_diff(&expected, &actual)
+        );
+    }
+}
+
+/// Minimal LCS based line diff. Lines only in `expected` are prefixed with
+/// `-`, lines only in `actual` with `+`.
+fn line_diff(expected: &str, actual: &str) -> String {
+    let a = expected.lines().collect::<Vec<_>>();
+    let b = actual.lines().collect::<Vec<_>>();
+
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                std::cmp::max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            result.push_str(&format!("  {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            result.push_str(&format!("+ {}\n", b[j]));
+            j += 1;
+        } else {
+            result.push_str(&format!("- {}\n", a[i]));
+            i += 1;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k9::*;
+
+    #[test]
+    fn test_name_matches() {
+        assert!(name_matches("root:db", "root:db"));
+        assert!(!name_matches("root:db", "root:db:query"));
+        assert!(name_matches("root:db*", "root:db:query"));
+        assert!(name_matches("*query", "root:db:query"));
+        assert!(name_matches("root:*:query", "root:db:query"));
+        assert!(!name_matches("root:*:query", "root:query"));
+        assert!(name_matches("*", "anything"));
+        assert!(!name_matches("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn test_line_diff() {
+        snapshot!(
+            line_diff("a\nb\nc\nd", "a\nc\nd\ne"),
+            "
+  a
+- b
+  c
+  d
++ e
+
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_golden_trace() {
+        let capture = with_task_tree(|tree, capture| async move {
+            tree.add_data_transitive("host", "localhost");
+            let root = tree.create_task("root");
+            root.spawn_sync("fetch #net", |t| {
+                t.data("bytes", 512);
+                Ok(())
+            })
+            .unwrap();
+            root.spawn_sync("parse", |_| -> anyhow::Result<()> {
+                anyhow::bail!("bad input")
+            })
+            .ok();
+            capture
+        })
+        .await;
+
+        assert_golden_trace(
+            &capture.trace(),
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/tests/golden/simple_trace.json"
+            ),
+        );
+    }
+}
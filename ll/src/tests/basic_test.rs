@@ -1,13 +1,8 @@
-use crate::{task_tree::TaskTree, ErrorFormatter, StringReporter};
+use crate::{task_tree::TaskTree, ErrorFormatter, StringReporter, TaskInternal};
 use anyhow::Result;
 use k9::*;
 use std::{sync::Arc, time::Duration};
 
-async fn sleep() {
-    // just enough to drain the reporter tokio tasks
-    tokio::time::sleep(Duration::from_millis(100)).await;
-}
-
 fn setup() -> (Arc<TaskTree>, StringReporter) {
     let string_reporter = StringReporter::new();
     let tt = TaskTree::new();
@@ -36,7 +31,7 @@ async fn basic_events_test() -> Result<()> {
 
     root.spawn_sync("test_3", |_e| Ok(()))?;
 
-    sleep().await;
+    tt.flush_async().await;
     snapshot!(
         s.to_string(),
         "
@@ -84,7 +79,7 @@ async fn error_chain_test() -> Result<()> {
         Ok(())
     });
 
-    sleep().await;
+    tt.flush_async().await;
     snapshot!(
         format!("{:?}", result.unwrap_err()),
         "
@@ -168,7 +163,7 @@ async fn error_chain_test_no_transitive() -> Result<()> {
         Ok(())
     });
 
-    sleep().await;
+    tt.flush_async().await;
     snapshot!(
         format!("{:?}", result.unwrap_err()),
         "
@@ -258,7 +253,7 @@ async fn error_chain_test_hide_errors() -> Result<()> {
         Ok(())
     });
 
-    sleep().await;
+    tt.flush_async().await;
     snapshot!(
         format!("{:?}", result.unwrap_err()),
         "
@@ -344,7 +339,7 @@ async fn error_chain_test_error_formatter() -> Result<()> {
         Ok(())
     });
 
-    sleep().await;
+    tt.flush_async().await;
     snapshot!(
         format!("{:?}", result.unwrap_err()),
         "
@@ -432,7 +427,7 @@ async fn logger_data_test() -> Result<()> {
         Ok(())
     })?;
 
-    sleep().await;
+    tt.flush_async().await;
     snapshot!(
         s.to_string(),
         "
@@ -478,7 +473,7 @@ async fn async_test() -> Result<()> {
     })
     .await?;
 
-    sleep().await;
+    tt.flush_async().await;
     snapshot!(
         s.to_string(),
         "
@@ -492,6 +487,2580 @@ async fn async_test() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn aggregate_progress_test() -> Result<()> {
+    let (tt, _s) = setup();
+    let root = tt.create_task("root");
+    let progress = || {
+        let tree = tt.tree_internal.read().unwrap();
+        tree.get_task(root.0.id).unwrap().progress
+    };
+
+    let _before = root.create("created_before_enabling");
+    root.aggregate_progress(true);
+    snapshot!(format!("{:?}", progress()), "Some((0, 1))");
+
+    root.spawn_sync("done", |_| Ok(()))?;
+    snapshot!(format!("{:?}", progress()), "Some((1, 2))");
+
+    let with_progress = root.create("with_progress");
+    with_progress.progress(3, 10);
+    snapshot!(format!("{:?}", progress()), "Some((4, 12))");
+
+    drop(with_progress);
+    snapshot!(format!("{:?}", progress()), "Some((11, 12))");
+    Ok(())
+}
+
+#[tokio::test]
+async fn stalled_task_test() -> Result<()> {
+    let (tt, s) = setup();
+    tt.set_stall_threshold(Some(Duration::from_secs(3600)));
+    tt.set_stall_threshold_for_tag("db", Some(Duration::from_millis(10)));
+
+    let root = tt.create_task("root");
+    let tt_clone = tt.clone();
+    root.spawn("slow_query #db", |_| async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let tt = tt_clone;
+        tt.tree_internal.write().unwrap().detect_stalled();
+        tt.flush_async().await;
+        Ok(())
+    })
+    .await?;
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:slow_query
+[ ] | STALLED  | root:slow_query
+[ ] root:slow_query
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn fallible_reporter_test() -> Result<()> {
+    use crate::reporters::{DeadLetterHandler, Reporter, TaskReportType};
+    use crate::task_tree::RetryPolicy;
+    use std::sync::Mutex;
+
+    // Fails to deliver any report for tasks named `flaky` twice and tasks
+    // named `broken` always.
+    #[derive(Default)]
+    struct FlakyReporter(Mutex<Vec<String>>);
+
+    impl Reporter for FlakyReporter {
+        fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+            let mut attempts = self.0.lock().unwrap();
+            attempts.push(task.name.clone());
+            let attempt = attempts.iter().filter(|n| **n == task.name).count();
+            anyhow::ensure!(task.name != "broken", "broken sink");
+            anyhow::ensure!(attempt > 2, "attempt {} failed", attempt);
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct DeadLetters(Mutex<Vec<String>>);
+
+    impl DeadLetterHandler for DeadLetters {
+        fn dead_letter(
+            &self,
+            task: Arc<TaskInternal>,
+            report_type: TaskReportType,
+            err: anyhow::Error,
+        ) {
+            let mut dead_letters = self.0.lock().unwrap();
+            dead_letters.push(format!("{} {:?} {}", task.name, report_type, err));
+        }
+    }
+
+    let tt = TaskTree::new();
+    let reporter = Arc::new(FlakyReporter::default());
+    let dead_letters = Arc::new(DeadLetters::default());
+    let handle = tt.add_reporter(reporter.clone());
+    tt.set_dead_letter_handler(Some(dead_letters.clone()));
+    tt.set_retry_policy(RetryPolicy {
+        max_retries: 2,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    });
+
+    let root = tt.create_task("root");
+    root.spawn_sync("flaky", |_| Ok(()))?;
+    root.spawn_sync("broken", |_| Ok(()))?;
+
+    tt.flush_async().await;
+    snapshot!(
+        reporter.0.lock().unwrap().join(" "),
+        "flaky flaky flaky broken broken broken"
+    );
+    snapshot!(
+        dead_letters.0.lock().unwrap().join("\n"),
+        "broken End broken sink"
+    );
+
+    let stats = tt.reporter_stats()[&handle];
+    assert_equal!((stats.delivered, stats.errors, stats.dropped), (4, 5, 1));
+    let many = crate::task_tree::ReporterStats {
+        delivered: 1 << 32,
+        total_latency: Duration::from_secs(1 << 32),
+        ..Default::default()
+    };
+    assert_equal!(many.avg_latency(), Duration::from_secs(1));
+    tt.remove_reporter(handle);
+    assert_equal!(tt.reporter_stats().len(), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn remove_reporter_while_reporting_test() -> Result<()> {
+    use crate::reporters::{Reporter, ReporterHandle};
+    use std::sync::Mutex;
+
+    /// Removes itself from the tree while a report is delivered to it
+    struct RemovesItself {
+        tt: Arc<TaskTree>,
+        handle: Mutex<Option<ReporterHandle>>,
+    }
+
+    impl Reporter for RemovesItself {
+        fn task_start(&self, _task: Arc<TaskInternal>) {
+            if let Some(handle) = self.handle.lock().unwrap().take() {
+                self.tt.remove_reporter(handle);
+            }
+        }
+    }
+
+    let tt = TaskTree::new();
+    let reporter = Arc::new(RemovesItself {
+        tt: tt.clone(),
+        handle: Mutex::new(None),
+    });
+    let handle = tt.add_reporter(reporter.clone());
+    *reporter.handle.lock().unwrap() = Some(handle);
+
+    drop(tt.create_task("root"));
+    tt.flush_async().await;
+    // the stats of the delivered report are discarded with the reporter
+    assert_equal!(tt.reporter_stats().len(), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn async_reporter_test() -> Result<()> {
+    use crate::reporters::AsyncReporter;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct SlowReporter(Mutex<Vec<String>>);
+
+    #[async_trait::async_trait]
+    impl AsyncReporter for SlowReporter {
+        async fn task_end(&self, task: Arc<TaskInternal>) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.0.lock().unwrap().push(task.name.clone());
+        }
+    }
+
+    let tt = TaskTree::new();
+    let reporter = Arc::new(SlowReporter::default());
+    tt.add_async_reporter(reporter.clone(), 1);
+
+    let root = tt.create_task("root");
+    for i in 0..5 {
+        root.spawn_sync(format!("task_{}", i), |_| Ok(()))?;
+    }
+
+    tt.flush_async().await;
+    snapshot!(
+        reporter.0.lock().unwrap().join(" "),
+        "task_0 task_1 task_2 task_3 task_4"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn reporter_middleware_test() -> Result<()> {
+    use crate::reporters::{ReporterExt, TaskReportType};
+
+    let s = StringReporter::new();
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(
+        s.clone()
+            .map(|task| {
+                let mut task = (*task).clone();
+                task.name = task.name.to_uppercase();
+                Arc::new(task)
+            })
+            .rate_limit(2)
+            .filter(|task, report_type| {
+                report_type == TaskReportType::End && !task.name.starts_with("skip")
+            }),
+    ));
+
+    let root = tt.create_task("root");
+    root.spawn_sync("one", |_| Ok(()))?;
+    root.spawn_sync("skip_me", |_| Ok(()))?;
+    root.spawn_sync("two", |_| Ok(()))?;
+    root.spawn_sync("three", |_| Ok(()))?;
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] root:ONE
+[ ] root:TWO
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn reentrant_flush_test() -> Result<()> {
+    use crate::reporters::Reporter;
+
+    /// Flushes and starts a task from inside of a callback
+    struct Reentrant {
+        tt: Arc<TaskTree>,
+        s: StringReporter,
+    }
+
+    impl Reporter for Reentrant {
+        fn task_start(&self, task: Arc<TaskInternal>) {
+            self.s.task_start(task);
+        }
+
+        fn task_end(&self, task: Arc<TaskInternal>) {
+            let nested = task.name == "root";
+            self.s.task_end(task);
+            if nested {
+                drop(self.tt.create_task("from_reporter"));
+                self.tt.flush_blocking();
+            }
+        }
+    }
+
+    let s = StringReporter::new();
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(Reentrant {
+        tt: tt.clone(),
+        s: s.clone(),
+    }));
+
+    drop(tt.create_task("root"));
+    tokio::time::timeout(Duration::from_secs(5), tt.flush_async()).await?;
+    tokio::time::timeout(Duration::from_secs(5), tt.flush_async()).await?;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] root
+[ ] | STARTING | from_reporter
+[ ] from_reporter
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn circuit_breaker_test() -> Result<()> {
+    use crate::reporters::{Reporter, ReporterExt};
+    use std::sync::{mpsc, Mutex};
+
+    /// Blocks on the start of `slow` like a dead network sink would, until
+    /// the test releases it
+    struct Blocking {
+        s: StringReporter,
+        release: Mutex<mpsc::Receiver<()>>,
+        delivered: Mutex<mpsc::Sender<()>>,
+    }
+
+    impl Reporter for Blocking {
+        fn task_start(&self, task: Arc<TaskInternal>) {
+            let slow = task.name == "slow";
+            if slow {
+                self.release.lock().unwrap().recv().ok();
+            }
+            self.s.task_start(task);
+            if slow {
+                self.delivered.lock().unwrap().send(()).ok();
+            }
+        }
+
+        fn task_end(&self, task: Arc<TaskInternal>) {
+            self.s.task_end(task);
+        }
+    }
+
+    let s = StringReporter::new();
+    let (release, release_receiver) = mpsc::channel();
+    let (delivered_sender, delivered) = mpsc::channel();
+    let tt = TaskTree::new();
+    // a clock that doesn't move keeps the tree's gc from removing finished
+    // tasks before they are reported
+    tt.set_clock(Arc::new(crate::clock::ManualClock::new(
+        std::time::SystemTime::UNIX_EPOCH,
+    )));
+    // long enough for reports that aren't blocked to never time out
+    let breaker = Arc::new(
+        Blocking {
+            s: s.clone(),
+            release: Mutex::new(release_receiver),
+            delivered: Mutex::new(delivered_sender),
+        }
+        .circuit_breaker(Duration::from_millis(500), 1, Duration::from_secs(60)),
+    );
+    tt.add_reporter(breaker.clone());
+
+    let root = tt.create_task("root");
+    tt.flush_async().await;
+    root.spawn_sync("slow", |_| Ok(()))?;
+    drop(root);
+    tt.flush_async().await;
+
+    assert_equal!(breaker.is_open(), true);
+    // end of `slow` and `root`
+    assert_equal!(breaker.skipped(), 2);
+    // the blocked report is still delivered eventually
+    release.send(())?;
+    delivered.recv_timeout(Duration::from_secs(5))?;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:slow
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn circuit_breaker_hung_reporter_test() -> Result<()> {
+    use crate::reporters::{Reporter, ReporterExt};
+    use std::sync::{mpsc, Mutex};
+
+    /// Never returns from the start of `hung` until the test releases it
+    struct Hung(Mutex<mpsc::Receiver<()>>);
+
+    impl Reporter for Hung {
+        fn task_start(&self, task: Arc<TaskInternal>) {
+            if task.name == "hung" {
+                self.0.lock().unwrap().recv().ok();
+            }
+        }
+    }
+
+    let (release, receiver) = mpsc::channel();
+    let tt = TaskTree::new();
+    // a clock that doesn't move keeps the tree's gc from removing finished
+    // tasks before they are reported
+    tt.set_clock(Arc::new(crate::clock::ManualClock::new(
+        std::time::SystemTime::UNIX_EPOCH,
+    )));
+    // long enough for reports that aren't blocked to never time out
+    let breaker = Arc::new(Hung(Mutex::new(receiver)).circuit_breaker(
+        Duration::from_millis(500),
+        3,
+        Duration::from_secs(60),
+    ));
+    tt.add_reporter(breaker.clone());
+
+    let root = tt.create_task("root");
+    tt.flush_async().await;
+    root.spawn_sync("hung", |_| Ok(()))?;
+    tt.flush_async().await;
+    assert_equal!(breaker.is_open(), false);
+
+    // the reporter is still stuck, so the end of `hung` and the start of
+    // `one` are skipped and count as the second and third timeout
+    root.spawn_sync("one", |_| Ok(()))?;
+    tt.flush_async().await;
+    assert_equal!(breaker.is_open(), true);
+    drop(release);
+    Ok(())
+}
+
+#[tokio::test]
+async fn remove_and_replace_reporters_test() -> Result<()> {
+    let (tt, s1) = setup();
+    let s2 = StringReporter::new();
+    let s3 = StringReporter::new();
+    let handle = tt.add_reporter(Arc::new(s2.clone()));
+
+    let root = tt.create_task("root");
+    root.spawn_sync("reported_to_all", |_| Ok(()))?;
+    tt.flush_async().await;
+
+    assert_equal!(tt.remove_reporter(handle), true);
+    assert_equal!(tt.remove_reporter(handle), false);
+    root.spawn_sync("after_removal", |_| Ok(()))?;
+    tt.flush_async().await;
+
+    tt.replace_reporters(vec![Arc::new(s3.clone())]);
+    root.spawn_sync("after_replace", |_| Ok(()))?;
+    tt.flush_async().await;
+
+    snapshot!(
+        s1.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:reported_to_all
+[ ] root:reported_to_all
+[ ] | STARTING | root:after_removal
+[ ] root:after_removal
+
+"
+    );
+    snapshot!(
+        s2.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:reported_to_all
+[ ] root:reported_to_all
+
+"
+    );
+    snapshot!(
+        s3.to_string(),
+        "
+[ ] | STARTING | root:after_replace
+[ ] root:after_replace
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn deterministic_string_reporter_test() -> Result<()> {
+    let (tt, s) = setup();
+    s.set_deterministic(true);
+
+    let root = tt.create_task("root");
+    let (a, b) = tokio::join!(
+        root.spawn("b_slow", |t| async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            t.spawn_sync("child", |_| Ok(()))
+        }),
+        root.spawn(
+            "a_fast",
+            |t| async move { t.spawn_sync("child", |_| Ok(())) }
+        ),
+    );
+    a?;
+    b?;
+    drop(root);
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:a_fast
+[ ] | STARTING | root:a_fast:child
+[ ] root:a_fast:child
+[ ] root:a_fast
+[ ] | STARTING | root:b_slow
+[ ] | STARTING | root:b_slow:child
+[ ] root:b_slow:child
+[ ] root:b_slow
+[ ] root
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn manual_clock_test() -> Result<()> {
+    use crate::clock::ManualClock;
+
+    let (tt, s) = setup();
+    s.log_duration(true);
+    let clock = Arc::new(ManualClock::new(std::time::SystemTime::UNIX_EPOCH));
+    tt.set_clock(clock.clone());
+
+    let root = tt.create_task("root");
+    root.spawn_sync("takes_1500ms", |_| {
+        clock.advance(Duration::from_millis(1500));
+        Ok(())
+    })?;
+    root.spawn_sync("instant", |_| Ok(()))?;
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:takes_1500ms
+[ ] | STARTING | root:instant
+[ ] |   1500ms | root:takes_1500ms
+[ ] |      0ms | root:instant
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn fault_injection_test() -> Result<()> {
+    use crate::test::Fault;
+
+    let (tt, s) = setup();
+    tt.inject_fault("root:db:*", Fault::Fail("connection refused".into()));
+    tt.inject_fault("*slow", Fault::Delay(Duration::from_millis(1)));
+    // without a delay, so the start of `hang` can't be reported while it's
+    // still running
+    tt.inject_fault("root:hang*", Fault::Timeout(Duration::ZERO));
+
+    let root = tt.create_task("root");
+    let db = root.create("db");
+    let err = db.spawn("query", |_| async { Ok(()) }).await.unwrap_err();
+    snapshot!(format!("{:?}", err.root_cause()), r#""connection refused""#);
+    snapshot!(format!("{:?}", root.spawn_sync("slow", |_| Ok(1))?), "1");
+    snapshot!(
+        format!(
+            "{:?}",
+            root.spawn_sync("hang", |_| Ok(()))
+                .unwrap_err()
+                .root_cause()
+        ),
+        r#""timed out after 0ns""#
+    );
+
+    tt.clear_faults();
+    db.spawn_sync("query", |_| Ok(()))?;
+    drop(db);
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:db
+[ ] | STARTING | [ERR] root:db:query
+[ ] | STARTING | root:slow
+[ ] | STARTING | [ERR] root:hang
+[ ] | STARTING | root:db:query
+[ ] [ERR] root:db:query
+  |
+  |  [Task] query
+  |  
+  |  
+  |  Caused by:
+  |      connection refused
+[ ] root:slow
+[ ] [ERR] root:hang
+  |
+  |  [Task] hang
+  |  
+  |  
+  |  Caused by:
+  |      timed out after 0ns
+[ ] root:db:query
+[ ] root:db
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn task_guard_test() -> Result<()> {
+    let (tt, s) = setup();
+    tt.set_force_flush(true);
+    let root = tt.create_task("root");
+
+    let t = root.start("explicit_success");
+    t.data("hello", "hi");
+    t.success();
+
+    let t = root.start("explicit_fail");
+    let err = t.fail(anyhow::anyhow!("oops"));
+    snapshot!(format!("{:?}", err.root_cause()), r#""oops""#);
+
+    let t = root.start("passthrough");
+    let result: Result<i32> = t.finish(Ok(5));
+    snapshot!(format!("{:?}", result.ok()), "Some(5)");
+
+    fn early_return(root: &crate::Task) -> Result<()> {
+        let _t = root.start("early_return").fail_on_drop("dropped early");
+        anyhow::bail!("bail before finishing");
+    }
+    early_return(&root).ok();
+
+    drop(root.start("dropped"));
+    drop(root);
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:explicit_success
+[ ] root:explicit_success
+  |      hello: hi
+[ ] | STARTING | root:explicit_fail
+[ ] [ERR] root:explicit_fail
+  |
+  |  [Task] explicit_fail
+  |  
+  |  
+  |  Caused by:
+  |      oops
+[ ] | STARTING | root:passthrough
+[ ] root:passthrough
+[ ] | STARTING | root:early_return
+[ ] [ERR] root:early_return
+  |
+  |  dropped early
+[ ] | STARTING | root:dropped
+[ ] root:dropped
+[ ] root
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn spawn_ok_test() -> Result<()> {
+    let (tt, s) = setup();
+    // failures can't be injected into infallible tasks
+    tt.inject_fault("root:*", crate::test::Fault::Fail("injected".into()));
+    let root = tt.create_task("root");
+
+    let sum = root.spawn_sync_ok("sum", |_| 1 + 1);
+    let len = root
+        .spawn_ok("len", |t| async move {
+            t.data("input", "hello");
+            "hello".len()
+        })
+        .await;
+    snapshot!(format!("{:?}", (sum, len)), "(2, 5)");
+
+    drop(root);
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:sum
+[ ] | STARTING | root:len
+[ ] root:sum
+[ ] root:len
+  |      input: hello
+[ ] root
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn spawn_ok_fail_fast_test() -> Result<()> {
+    let (tt, _) = setup();
+    let root = tt.create_task("root");
+    root.fail_fast(true);
+
+    // the failing sibling doesn't cancel the infallible task
+    let (broken, steady) = tokio::join!(
+        root.spawn("broken", |_| async {
+            Err::<(), _>(anyhow::anyhow!("compile error"))
+        }),
+        root.spawn_ok("steady", |_| async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            7
+        }),
+    );
+    assert_equal!(broken.is_err(), true);
+    assert_equal!(steady, 7);
+    Ok(())
+}
+
+#[tokio::test]
+async fn spawn_detached_test() -> Result<()> {
+    let (tt, s) = setup();
+    let root = tt.create_task("root");
+
+    let handle = root.spawn_detached("detached", |t| async move {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        t.data("hello", "hi");
+        Ok(5)
+    });
+    let failing = root.spawn_detached("failing", |_| async move {
+        anyhow::bail!("detached failure");
+        #[allow(unreachable_code)]
+        Ok(())
+    });
+    drop(root);
+
+    snapshot!(format!("{:?}", handle.await?), "5");
+    snapshot!(
+        format!("{:?}", failing.await.unwrap_err().root_cause()),
+        r#""detached failure""#
+    );
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:detached
+[ ] | STARTING | [ERR] root:failing
+[ ] root
+[ ] [ERR] root:failing
+  |
+  |  [Task] failing
+  |  
+  |  
+  |  Caused by:
+  |      detached failure
+[ ] root:detached
+  |      hello: hi
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn scope_test() -> Result<()> {
+    let (tt, _) = setup();
+    let root = tt.create_task("root");
+
+    let results = root
+        .scope(|s| {
+            for i in 0..3 {
+                s.spawn(format!("ok_{}", i), move |_| async move { Ok(i * 10) });
+            }
+        })
+        .await?;
+    snapshot!(format!("{:?}", results), "[0, 10, 20]");
+
+    let err = root
+        .scope(|s| {
+            s.spawn("ok", |_| async { Ok(()) });
+            s.spawn("fail_1", |_| async { anyhow::bail!("first") });
+            s.spawn("fail_2", |_| async { anyhow::bail!("second") });
+        })
+        .await
+        .unwrap_err();
+    snapshot!(
+        format!("{:#}", err).replace('\n', " "),
+        "2 of 3 tasks in scope failed, other errors: [Task] fail_2 : second: [Task] fail_1 : first"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn scope_cancel_test() -> Result<()> {
+    use crate::task_tree::{TaskResult, TaskStatus};
+
+    /// Notifies when the task holding it is dropped
+    struct DropNotifier(Option<tokio::sync::oneshot::Sender<()>>);
+    impl Drop for DropNotifier {
+        fn drop(&mut self) {
+            self.0.take().unwrap().send(()).ok();
+        }
+    }
+
+    let (tt, _) = setup();
+    let root = tt.create_task("root");
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+    let (dropped_tx, dropped_rx) = tokio::sync::oneshot::channel();
+    let scope = root.scope(|s| {
+        s.spawn("forever", |_| async move {
+            let _notifier = DropNotifier(Some(dropped_tx));
+            started_tx.send(()).ok();
+            std::future::pending::<Result<()>>().await
+        });
+    });
+    tokio::select! {
+        _ = scope => unreachable!(),
+        _ = started_rx => {}
+    }
+    // the scope future is dropped here, which must abort the child
+    tokio::time::timeout(Duration::from_secs(10), dropped_rx).await??;
+    // and the aborted child must not be left running forever
+    tokio::time::timeout(Duration::from_secs(10), root.wait_idle()).await?;
+    let status = tt
+        .tree_internal
+        .read()
+        .unwrap()
+        .tasks()
+        .find(|task| task.name == "forever")
+        .map(|task| task.status.clone());
+    let error = match status {
+        Some(TaskStatus::Finished(TaskResult::Failure(error), _)) => Some(error),
+        _ => None,
+    };
+    assert_equal!(error.as_deref(), Some("cancelled"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn future_ext_test() -> Result<()> {
+    use crate::LlFutureExt;
+
+    let (tt, s) = setup();
+    let root = tt.create_task("root");
+
+    async fn add(a: i32, b: i32) -> Result<i32> {
+        Ok(a + b)
+    }
+    snapshot!(format!("{:?}", add(1, 2).in_task(&root, "add").await?), "3");
+    async { Err::<(), _>(anyhow::anyhow!("failed")) }
+        .in_task(&root, "fail")
+        .await
+        .ok();
+
+    drop(root);
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:add
+[ ] | STARTING | [ERR] root:fail
+[ ] root:add
+[ ] [ERR] root:fail
+  |
+  |  [Task] fail
+  |  
+  |  
+  |  Caused by:
+  |      failed
+[ ] root
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn progress_adapters_test() -> Result<()> {
+    use crate::progress::{ProgressIteratorExt, ProgressStreamExt};
+    use tokio_stream::StreamExt;
+
+    let tt = TaskTree::new();
+    let root = tt.create_task("root");
+    let progress = |task: &crate::Task| {
+        let tree = tt.tree_internal.read().unwrap();
+        tree.get_task(task.0.id).unwrap().progress
+    };
+
+    let mut iter = vec![1, 2, 3].into_iter().ll_progress(&root, 3);
+    snapshot!(format!("{:?}", progress(&root)), "Some((0, 3))");
+    iter.next();
+    snapshot!(format!("{:?}", progress(&root)), "Some((1, 3))");
+    snapshot!(format!("{:?}", iter.collect::<Vec<_>>()), "[2, 3]");
+    snapshot!(format!("{:?}", progress(&root)), "Some((3, 3))");
+
+    let streamed = root.create("streamed");
+    let mut stream = tokio_stream::iter(vec!["a", "b"]).ll_progress(&streamed);
+    stream.next().await;
+    snapshot!(format!("{:?}", progress(&streamed)), "Some((1, 2))");
+    while stream.next().await.is_some() {}
+    snapshot!(format!("{:?}", progress(&streamed)), "Some((2, 2))");
+    Ok(())
+}
+
+#[cfg(feature = "rayon")]
+#[tokio::test]
+async fn rayon_test() -> Result<()> {
+    let (tt, s) = setup();
+    let root = tt.create_task("root");
+
+    let squares = root.par_spawn_each("squares", 0..10, None, |_, i| Ok(i * i))?;
+    snapshot!(
+        format!("{:?}", squares),
+        "[0, 1, 4, 9, 16, 25, 36, 49, 64, 81]"
+    );
+
+    let chunked = root.par_spawn_each("chunked", vec![1, 2, 3, 4, 5], Some(2), |task, i| {
+        task.data("last_item", i);
+        Ok(i * 10)
+    })?;
+    snapshot!(format!("{:?}", chunked), "[10, 20, 30, 40, 50]");
+
+    let err = root
+        .par_spawn_each("failing", 0..4, None, |_, i| {
+            if i == 3 {
+                anyhow::bail!("bad item {}", i);
+            }
+            Ok(i)
+        })
+        .unwrap_err();
+    snapshot!(format!("{:?}", err.root_cause()), r#""bad item 3""#);
+
+    drop(root);
+    tt.flush_async().await;
+    let mut lines = s
+        .to_string()
+        .lines()
+        .filter(|line| line.starts_with("[ ]") && !line.contains("STARTING"))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    lines.sort();
+    snapshot!(
+        lines.join("\n"),
+        "
+[ ] [ERR] root:failing
+[ ] root
+[ ] root:chunked
+[ ] root:chunked:chunk_0
+[ ] root:chunked:chunk_1
+[ ] root:chunked:chunk_2
+[ ] root:squares
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn task_builder_test() -> Result<()> {
+    use crate::reporters::Level;
+    use crate::Task;
+
+    let (tt, s) = setup();
+    tt.set_force_flush(true);
+    let root = tt.create_task("root");
+
+    Task::builder("query #extra")
+        .parent(&root)
+        .level(Level::L2)
+        .tag("db")
+        .data("shard", 3)
+        .no_status()
+        .spawn(|task| async move {
+            let tree = task.0.task_tree.tree_internal.read().unwrap();
+            let internal = tree.get_task(task.0.id)?;
+            snapshot!(
+                format!("{:?}", internal.tags),
+                r#"{"db", "extra", "l2", "nostatus"}"#
+            );
+            Ok(())
+        })
+        .await?;
+
+    let created = Task::builder("created").parent(&root).create();
+    created.spawn_sync("child", |_| Ok(()))?;
+    drop(created);
+
+    Task::builder("started").parent(&root).start().success();
+
+    Task::builder("synced")
+        .parent(&root)
+        .spawn_sync(|_| Ok(()))?;
+    Task::builder("detached")
+        .parent(&root)
+        .spawn_detached(|_| async { Ok(()) })
+        .await?;
+
+    drop(root);
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:query
+[ ] root:query
+  |      shard: 3
+[ ] | STARTING | root:created
+[ ] | STARTING | root:created:child
+[ ] root:created:child
+[ ] | STARTING | root:started
+[ ] root:created
+[ ] root:started
+[ ] | STARTING | root:synced
+[ ] root:synced
+[ ] | STARTING | root:detached
+[ ] root:detached
+[ ] root
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn typed_tags_test() -> Result<()> {
+    use crate::reporters::{ReporterExt, TaskReportType};
+    use crate::{Tag, Task};
+
+    const DB: Tag = Tag::new("db");
+    const CACHE: Tag = Tag::new("cache");
+
+    let s = StringReporter::new();
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(s.clone().filter(|task, report_type| {
+        report_type == TaskReportType::End && task.has_tag(DB)
+    })));
+    let root = tt.create_task("root");
+
+    Task::builder("typed")
+        .parent(&root)
+        .tag(DB)
+        .spawn_sync(|_| Ok(()))?;
+    root.spawn_sync("hashtag #db", |_| Ok(()))?;
+    Task::builder("both")
+        .parent(&root)
+        .tags(vec![DB, CACHE])
+        .spawn_sync(|_| Ok(()))?;
+    Task::builder("other")
+        .parent(&root)
+        .tag(CACHE)
+        .spawn_sync(|_| Ok(()))?;
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] root:typed
+[ ] root:hashtag
+[ ] root:both
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn name_prefix_test() -> Result<()> {
+    let (tt, s) = setup();
+    tt.set_name_prefix(Some("my_service"));
+
+    let db = tt.create_task("root").nest("db");
+    db.spawn_sync("connect", |_| Ok(()))?;
+    db.nest("pool")
+        .spawn("acquire #pool", |_| async { Ok(()) })
+        .await?;
+    crate::Task::builder("query")
+        .parent(&db)
+        .spawn_sync(|t| t.spawn_sync("unprefixed", |_| Ok(())))?;
+    drop(db);
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | my_service.root
+[ ] | STARTING | my_service.root:db.connect
+[ ] | STARTING | my_service.root:db.pool.acquire
+[ ] | STARTING | my_service.root:db.query
+[ ] | STARTING | my_service.root:db.query:unprefixed
+[ ] my_service.root:db.connect
+[ ] my_service.root:db.pool.acquire
+[ ] my_service.root:db.query:unprefixed
+[ ] my_service.root:db.query
+[ ] my_service.root
+
+"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "eyre")]
+#[tokio::test]
+async fn eyre_test() -> Result<()> {
+    use eyre::WrapErr;
+
+    let (tt, s) = setup();
+    let root = tt.create_task("root");
+
+    let value = root.spawn_sync_eyre("ok", |_| Ok(5)).unwrap();
+    snapshot!(format!("{:?}", value), "5");
+
+    let err = root
+        .spawn_eyre("fails", |_| async {
+            Err::<(), _>(eyre::eyre!("disk full")).wrap_err("failed to write")
+        })
+        .await
+        .unwrap_err();
+    snapshot!(format!("{}", err.root_cause()), "disk full");
+
+    drop(root);
+    tt.flush_async().await;
+    // eyre reports include the source location, which changes with edits
+    let output = s
+        .to_string()
+        .lines()
+        .filter(|line| !line.contains("basic_test.rs"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    snapshot!(
+        output,
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:ok
+[ ] | STARTING | [ERR] root:fails
+[ ] root:ok
+[ ] [ERR] root:fails
+  |
+  |  [Task] fails
+  |  
+  |  
+  |  failed to write
+  |  
+  |  Caused by:
+  |      disk full
+  |  
+  |  Location:
+[ ] root
+"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn tower_layer_test() -> Result<()> {
+    use crate::clock::ManualClock;
+    use crate::tower::TaskLayer;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    struct Echo(Arc<ManualClock>);
+
+    impl Service<http::Request<()>> for Echo {
+        type Response = http::Response<()>;
+        type Error = String;
+        type Future = Pin<Box<dyn Future<Output = Result<http::Response<()>, String>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), String>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<()>) -> Self::Future {
+            self.0.advance(Duration::from_millis(25));
+            let task = req.extensions().get::<crate::Task>().cloned().unwrap();
+            let path = req.uri().path().to_string();
+            Box::pin(async move {
+                task.spawn_sync("handler", |_| Ok(()))
+                    .map_err(|e| e.to_string())?;
+                match path.as_str() {
+                    "/error" => Err("connection reset".to_string()),
+                    "/fail" => Ok(http::Response::builder().status(503).body(()).unwrap()),
+                    _ => Ok(http::Response::new(())),
+                }
+            })
+        }
+    }
+
+    let (tt, s) = setup();
+    let clock = Arc::new(ManualClock::new(std::time::UNIX_EPOCH));
+    tt.set_clock(clock.clone());
+    let root = tt.create_task("server");
+    let mut service = TaskLayer::new(&root).layer(Echo(clock));
+
+    for path in ["/ok", "/fail", "/error"] {
+        let req = http::Request::post(path).body(()).unwrap();
+        service.call(req).await.ok();
+    }
+
+    drop(root);
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | server
+[ ] | STARTING | server:POST /ok
+[ ] | STARTING | server:POST /ok:handler
+[ ] | STARTING | [ERR] server:POST /fail
+[ ] | STARTING | server:POST /fail:handler
+[ ] | STARTING | [ERR] server:POST /error
+[ ] | STARTING | server:POST /error:handler
+[ ] server:POST /ok:handler
+[ ] server:POST /ok
+  |      latency_ms: 25
+  |      method: POST
+  |      path: /ok
+  |      status: 200
+[ ] server:POST /fail:handler
+[ ] [ERR] server:POST /fail
+  |      latency_ms: 25
+  |      method: POST
+  |      path: /fail
+  |      status: 503
+  |
+  |  [Task] POST /fail
+  |    latency_ms: 25
+  |    method: POST
+  |    path: /fail
+  |    status: 503
+  |  
+  |  
+  |  Caused by:
+  |      request failed with status 503 Service Unavailable
+[ ] server:POST /error:handler
+[ ] [ERR] server:POST /error
+  |      latency_ms: 25
+  |      method: POST
+  |      path: /error
+  |
+  |  [Task] POST /error
+  |    latency_ms: 25
+  |    method: POST
+  |    path: /error
+  |  
+  |  
+  |  Caused by:
+  |      connection reset
+
+"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn axum_request_task_test() -> Result<()> {
+    use crate::axum::RequestTask;
+    use crate::tower::TaskLayer;
+    use axum_core::extract::FromRequestParts;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    struct Handler;
+
+    impl Service<http::Request<()>> for Handler {
+        type Response = http::Response<()>;
+        type Error = String;
+        type Future = Pin<Box<dyn Future<Output = Result<http::Response<()>, String>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), String>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<()>) -> Self::Future {
+            let (mut parts, _) = req.into_parts();
+            Box::pin(async move {
+                let RequestTask(task) = RequestTask::from_request_parts(&mut parts, &())
+                    .await
+                    .map_err(|(_, msg)| msg.to_string())?;
+                task.spawn_sync("load_user", |_| Ok(()))
+                    .map_err(|e| e.to_string())?;
+                Ok(http::Response::new(()))
+            })
+        }
+    }
+
+    let (tt, s) = setup();
+    tt.set_clock(Arc::new(crate::clock::ManualClock::new(
+        std::time::UNIX_EPOCH,
+    )));
+    let root = tt.create_task("server");
+    let mut service = TaskLayer::new(&root).layer(Handler);
+    let req = http::Request::get("/users/1")
+        .header(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .body(())
+        .unwrap();
+    service.call(req).await.unwrap();
+
+    let err = Handler
+        .call(http::Request::get("/").body(()).unwrap())
+        .await
+        .unwrap_err();
+    snapshot!(err, "ll::tower::TaskLayer is not installed");
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | server
+[ ] | STARTING | server:GET /users/1
+[ ] | STARTING | server:GET /users/1:load_user
+[ ] server:GET /users/1:load_user
+  |      parent_span_id: 00f067aa0ba902b7
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+[ ] server:GET /users/1
+  |      latency_ms: 0
+  |      method: GET
+  |      path: /users/1
+  |      status: 200
+  |      parent_span_id: 00f067aa0ba902b7
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn instrument_query_test() -> Result<()> {
+    use crate::instrument_query;
+
+    let (tt, s) = setup();
+    let root = tt.create_task("root");
+
+    let rows = instrument_query(&root, "SELECT id\n  FROM users\n  WHERE active", async {
+        anyhow::Ok(vec![1, 2])
+    })
+    .await?;
+    snapshot!(format!("{:?}", rows), "[1, 2]");
+
+    let long_sql = format!("UPDATE t SET {}", "x = 1, ".repeat(40));
+    instrument_query(&root, &long_sql, async { anyhow::Ok(3u64) }).await?;
+
+    instrument_query(&root, "DELETE FROM t", async {
+        Err::<(), _>(std::io::Error::other("db is gone"))
+    })
+    .await
+    .ok();
+
+    drop(root);
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:select
+[ ] | STARTING | root:update
+[ ] | STARTING | [ERR] root:delete
+[ ] root:select
+  |      rows: 2
+  |      sql: SELECT id FROM users WHERE active
+[ ] root:update
+  |      rows: 3
+  |      sql: UPDATE t SET x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1, x = 1...
+[ ] [ERR] root:delete
+  |      sql: DELETE FROM t
+  |
+  |  [Task] delete
+  |    sql: DELETE FROM t
+  |  
+  |  
+  |  Caused by:
+  |      db is gone
+[ ] root
+
+"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "reqwest")]
+#[tokio::test]
+async fn http_client_test() -> Result<()> {
+    use crate::http_client::Client;
+    use crate::task_tree::RetryPolicy;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Responds with 503 to the first request and to all requests to `/down`,
+    // and with 200 to the rest
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let requests = Arc::new(Mutex::new(vec![]));
+    let requests_clone = requests.clone();
+    tokio::spawn(async move {
+        for i in 0.. {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = socket.read(&mut buf).await.unwrap();
+            requests_clone
+                .lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(&buf[..n]).to_string());
+            let down = buf.starts_with(b"GET /down ");
+            let response = if i == 0 || down {
+                "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello"
+            };
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    let (tt, s) = setup();
+    let root = tt.create_task("root");
+    root.data_transitive("trace_id", "4bf92f3577b34da6a3ce929d0e0e4736");
+    let client = Client::new(reqwest::Client::new()).with_retry_policy(RetryPolicy {
+        max_retries: 2,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    });
+    let response = client.get(&root, &format!("http://{}/hello", addr)).await?;
+    snapshot!(response.text().await?, "hello");
+
+    let requests = requests.lock().unwrap().clone();
+    snapshot!(format!("{:?}", requests.len()), "2");
+    let traceparents = requests
+        .iter()
+        .filter_map(|request| {
+            request
+                .lines()
+                .find_map(|line| line.strip_prefix("traceparent: "))
+                .map(|header| crate::trace_context::parse_traceparent(header).unwrap().0)
+        })
+        .collect::<Vec<_>>();
+    snapshot!(
+        format!("{:?}", traceparents),
+        r#"["4bf92f3577b34da6a3ce929d0e0e4736", "4bf92f3577b34da6a3ce929d0e0e4736"]"#
+    );
+
+    drop(root);
+    tt.flush_async().await;
+    let output = s
+        .to_string()
+        .lines()
+        .filter(|line| !line.contains("span_id") && !line.contains("url"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    snapshot!(
+        output,
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:GET /hello
+[ ] root:GET /hello
+  |      bytes: 5
+  |      method: GET
+  |      retries: 1
+  |      status: 200
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+[ ] root
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+"
+    );
+
+    // the last attempt still gets a 5xx
+    let root = tt.create_task("root");
+    let down = client.get(&root, &format!("http://{}/down", addr)).await;
+    snapshot!(
+        down.unwrap_err().root_cause().to_string(),
+        "503 Service Unavailable after 2 retries"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "tonic")]
+#[tokio::test]
+async fn grpc_test() -> Result<()> {
+    use crate::grpc::{client_call, server_call};
+    use tonic::{Request, Response, Status};
+
+    let (tt, s) = setup();
+    let client = tt.create_task("client");
+    client.data_transitive("trace_id", "4bf92f3577b34da6a3ce929d0e0e4736");
+    let server = tt.create_task("server");
+
+    let handler = |task: crate::Task, req: Request<String>| async move {
+        let name = req.into_inner();
+        if name.is_empty() {
+            return Err(Status::invalid_argument("name is empty"));
+        }
+        task.spawn_sync("greet", |_| Ok(()))
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(format!("hello {}", name)))
+    };
+
+    let response = client_call(
+        &client,
+        "greeter.Greeter/Greet",
+        Request::new("ll".to_string()),
+        |req| server_call(&server, "greeter.Greeter/Greet", req, handler),
+    )
+    .await?;
+    snapshot!(response.into_inner(), "hello ll");
+
+    let status = client_call(
+        &client,
+        "greeter.Greeter/Greet",
+        Request::new(String::new()),
+        |req| server_call(&server, "greeter.Greeter/Greet", req, handler),
+    )
+    .await
+    .unwrap_err();
+    snapshot!(format!("{:?}", status.code()), "InvalidArgument");
+
+    drop((client, server));
+    tt.flush_async().await;
+    let output = s
+        .to_string()
+        .lines()
+        .filter(|line| !line.contains("span_id"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    snapshot!(
+        output,
+        "
+[ ] | STARTING | client
+[ ] | STARTING | server
+[ ] | STARTING | client:greeter.Greeter/Greet
+[ ] | STARTING | server:greeter.Greeter/Greet
+[ ] | STARTING | server:greeter.Greeter/Greet:greet
+[ ] | STARTING | [ERR] client:greeter.Greeter/Greet
+[ ] | STARTING | [ERR] server:greeter.Greeter/Greet
+[ ] server:greeter.Greeter/Greet:greet
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+[ ] server:greeter.Greeter/Greet
+  |      request_bytes: 4
+  |      response_bytes: 10
+  |      status: Ok
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+[ ] client:greeter.Greeter/Greet
+  |      request_bytes: 4
+  |      response_bytes: 10
+  |      status: Ok
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+[ ] [ERR] server:greeter.Greeter/Greet
+  |      request_bytes: 0
+  |      status: InvalidArgument
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+  |
+  |  [Task] greeter.Greeter/Greet
+  |    request_bytes: 0
+  |    status: InvalidArgument
+  |    trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+  |  
+  |  
+  |  Caused by:
+  |      RPC failed with InvalidArgument: name is empty
+[ ] [ERR] client:greeter.Greeter/Greet
+  |      request_bytes: 0
+  |      status: InvalidArgument
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+  |
+  |  [Task] greeter.Greeter/Greet
+  |    request_bytes: 0
+  |    status: InvalidArgument
+  |    trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+  |  
+  |  
+  |  Caused by:
+  |      RPC failed with InvalidArgument: name is empty
+[ ] client
+  |      trace_id: 4bf92f3577b34da6a3ce929d0e0e4736
+[ ] server
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn data_timeline_test() -> Result<()> {
+    use crate::clock::ManualClock;
+
+    let (tt, s) = setup();
+    s.show_data_offsets(true);
+    let capture = crate::reporters::CaptureReporter::new();
+    tt.add_reporter(Arc::new(capture.clone()));
+    let clock = Arc::new(ManualClock::new(std::time::SystemTime::UNIX_EPOCH));
+    tt.set_clock(clock.clone());
+
+    let root = tt.create_task("root");
+    root.data("step", "connect");
+    clock.advance(Duration::from_millis(2300));
+    root.data("step", "query");
+    root.data_transitive("request_id", 1);
+    drop(root);
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] root
+  |      step: query (+2.3s)
+  |      request_id: 1 (+2.3s)
+
+"
+    );
+
+    let trace = capture.trace();
+    let timeline = trace.tasks[0]
+        .data_timeline
+        .iter()
+        .map(|change| format!("{}={} +{}ms", change.key, change.value, change.offset_ms))
+        .collect::<Vec<_>>();
+    assert_equal!(
+        timeline,
+        vec![
+            "step=connect +0ms",
+            "step=query +2300ms",
+            "request_id=1 +2300ms"
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn attachment_test() -> Result<()> {
+    use crate::attachment::{AttachmentStore, DirStore};
+    use crate::uniq_id::UniqID;
+
+    let (tt, _s) = setup();
+    let capture = crate::reporters::CaptureReporter::new();
+    tt.add_reporter(Arc::new(capture.clone()));
+    let dir = std::env::temp_dir().join(format!("ll-attachment-test-{}", std::process::id()));
+
+    let task = tt.create_task("build");
+    // no store by default
+    assert!(task.attach("stdout", b"compiling...".to_vec()).is_err());
+    tt.set_attachment_store(Arc::new(DirStore::new(&dir)));
+    task.attach("stdout", b"compiling...".to_vec())?;
+    task.attach("report", std::path::Path::new("/tmp/report.html"))?;
+    drop(task);
+    tt.flush_async().await;
+
+    let finished = capture.finished("build").unwrap();
+    let stdout = &finished.data.map["stdout"];
+    assert_equal!(stdout.1.iter().collect::<Vec<_>>(), vec!["attachment"]);
+    assert_equal!(
+        std::fs::read_to_string(stdout.0.to_string())?,
+        "compiling..."
+    );
+    assert_equal!(
+        finished.data.map["report"].0,
+        crate::DataValue::String("/tmp/report.html".into())
+    );
+    std::fs::remove_dir_all(dir)?;
+
+    // a temp store removes its directory once it's dropped
+    let store = DirStore::temp();
+    let path = store.store(UniqID::new(), "stdout", b"compiling...".to_vec().into())?;
+    let temp_dir = std::path::Path::new(&path).parent().unwrap().to_path_buf();
+    assert!(temp_dir.exists());
+    drop(store);
+    assert!(!temp_dir.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn default_fields_test() -> Result<()> {
+    let (tt, s) = setup();
+    let capture = crate::reporters::CaptureReporter::new();
+    tt.add_reporter(Arc::new(capture.clone()));
+    tt.add_default_fields();
+
+    tt.create_task("root");
+    tt.flush_async().await;
+
+    let data = capture.data_of("root").unwrap();
+    assert_equal!(
+        data["pid"],
+        crate::DataValue::Int(std::process::id() as i64)
+    );
+    assert_equal!(data.contains_key("binary"), true);
+    // provenance fields don't clutter console output
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] root
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn name_interpolation_test() -> Result<()> {
+    let (tt, s) = setup();
+
+    let root = tt.create_task("fetch {url}");
+    root.data("url", "example.com");
+    let child = root.create("parse {file} for {url} {unknown}");
+    child.data("file", "index.html");
+    drop(child);
+    drop(root);
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | fetch example.com
+[ ] | STARTING | fetch example.com:parse index.html for {url} {unknown}
+[ ] fetch example.com:parse index.html for {url} {unknown}
+  |      file: index.html
+[ ] fetch example.com
+  |      url: example.com
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn clear_data_transitive_test() -> Result<()> {
+    let (tt, s) = setup();
+    tt.add_data_transitive("session", "abc");
+
+    let root = tt.create_task("root");
+    root.data_transitive("request_id", 1);
+    let scoped = root.data_transitive_scoped("request_id", 2, || root.create("retry"));
+    root.clear_data_transitive("request_id");
+    root.clear_data_transitive("session");
+    let background = root.create("background");
+    let nested = background.create("nested");
+    nested.data_transitive("session", "def");
+    let leaf = nested.create("leaf");
+
+    assert_equal!(scoped.get_data("request_id"), Some(2.into()));
+    assert_equal!(root.get_data("request_id"), None);
+    assert_equal!(background.get_data("request_id"), None);
+    assert_equal!(background.get_data("session"), None);
+    assert_equal!(nested.get_data("session"), Some("def".into()));
+    assert_equal!(leaf.get_data("session"), Some("def".into()));
+
+    drop(leaf);
+    drop(nested);
+    drop(background);
+    drop(scoped);
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:retry
+[ ] | STARTING | root:background
+[ ] | STARTING | root:background:nested
+[ ] | STARTING | root:background:nested:leaf
+[ ] root:background:nested:leaf
+  |      session: def
+[ ] root:background:nested
+  |      session: def
+[ ] root:background
+[ ] root:retry
+  |      request_id: 2
+  |      session: abc
+
+"
+    );
+    drop(root);
+    Ok(())
+}
+
+#[tokio::test]
+async fn dontprint_patterns_test() -> Result<()> {
+    let (tt, s) = setup();
+    tt.add_dontprint_pattern("*_token");
+    tt.add_dontprint_pattern("password*");
+
+    let task = tt.create_task("login");
+    task.data("user", "admin");
+    task.data("password", "hunter2");
+    task.data_transitive("github_token", "ghp_xxx");
+    drop(task);
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | login
+[ ] login
+  |      user: admin
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn data_schema_test() -> Result<()> {
+    use crate::schema::{DataSchema, DataType};
+
+    let (tt, s) = setup();
+    tt.add_data_schema(
+        "*request",
+        DataSchema::new()
+            .required("url", DataType::String)
+            .required("status", DataType::Int)
+            .strict(true),
+    );
+
+    let root = tt.create_task("root");
+    root.spawn_sync("request", |task| {
+        task.data("url", "/");
+        task.data("status", 200);
+        Ok(())
+    })?;
+    root.spawn_sync("request", |task| {
+        task.data("status", "ok");
+        Ok(())
+    })?;
+    drop(root);
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:request
+[ ] | STARTING | [ERR] root:request
+[ ] root:request
+  |      status: 200
+  |      url: /
+[ ] [ERR] root:request
+  |      status: ok
+  |
+  |  missing required data `url`, data `status` is expected to be Int, got `ok`
+[ ] root
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn pause_resume_test() -> Result<()> {
+    use crate::clock::{Clock, ManualClock};
+    use crate::task_tree::TaskStatus;
+    use std::time::{Duration, SystemTime};
+
+    let (tt, _s) = setup();
+    let clock = Arc::new(ManualClock::new(SystemTime::UNIX_EPOCH));
+    tt.set_clock(clock.clone());
+    let capture = crate::reporters::CaptureReporter::new();
+    tt.add_reporter(Arc::new(capture.clone()));
+
+    let task = tt.create_task("task");
+    clock.advance(Duration::from_secs(1));
+    task.pause("waiting for lock");
+    clock.advance(Duration::from_secs(5));
+    let status = tt
+        .tree_internal
+        .read()
+        .unwrap()
+        .get_task(task.0.id)?
+        .status
+        .clone();
+    let reason = match status {
+        TaskStatus::Paused(reason) => Some(reason),
+        _ => None,
+    };
+    assert_equal!(reason.as_deref(), Some("waiting for lock"));
+    task.resume();
+    clock.advance(Duration::from_secs(2));
+    drop(task);
+    tt.flush_async().await;
+
+    let finished = capture.finished("task").unwrap();
+    let now = clock.now();
+    assert_equal!(finished.paused_duration(now), Duration::from_secs(5));
+    assert_equal!(finished.active_duration(now), Duration::from_secs(3));
+    Ok(())
+}
+
+#[tokio::test]
+async fn concurrency_limit_test() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let (tt, _s) = setup();
+    let root = tt.create_task("root");
+    root.concurrency_limit(2);
+
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    let results = root
+        .scope(|s| {
+            for i in 0..5 {
+                let running = running.clone();
+                let max_running = max_running.clone();
+                s.spawn(format!("job_{}", i), move |_| async move {
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(i)
+                });
+            }
+        })
+        .await?;
+
+    assert_equal!(results, vec![0, 1, 2, 3, 4]);
+    assert_equal!(max_running.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+// with the clock paused, the sleep below only completes once both subtasks
+// got as far as they can
+#[tokio::test(start_paused = true)]
+async fn concurrency_limit_pending_test() -> Result<()> {
+    use crate::task_tree::{TaskStatus, PENDING_REASON};
+
+    let (tt, _s) = setup();
+    let root = tt.create_task("root");
+    root.concurrency_limit(1);
+
+    let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+    let first = root.spawn_detached("first", |_| async move {
+        rx.await.ok();
+        Ok(())
+    });
+    let second = root.spawn_detached("second", |_| async { Ok(()) });
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+    let statuses = {
+        let tree = tt.tree_internal.read().unwrap();
+        tree.tasks()
+            .filter(|task| task.parent_id.is_some())
+            .map(|task| {
+                let pending =
+                    matches!(&task.status, TaskStatus::Paused(reason) if reason == PENDING_REASON);
+                (task.name.clone(), pending)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_equal!(
+        statuses,
+        vec![("first".to_string(), false), ("second".to_string(), true)]
+    );
+
+    tx.send(()).unwrap();
+    first.await?;
+    second.await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn spawn_map_test() -> Result<()> {
+    let (tt, _s) = setup();
+    let capture = crate::reporters::CaptureReporter::new();
+    tt.add_reporter(Arc::new(capture.clone()));
+
+    let root = tt.create_task("root");
+    let result = root
+        .spawn_map("process", 0..4, 2, |item, _task| async move {
+            if item == 2 {
+                anyhow::bail!("bad item {}", item);
+            }
+            Ok(item * 10)
+        })
+        .await;
+    drop(root);
+    tt.flush_async().await;
+
+    let err = format!("{:#}", result.unwrap_err());
+    assert_equal!(err.contains("1 of 4 tasks in scope failed"), true);
+    assert_equal!(capture.failed().len(), 2);
+    assert_equal!(capture.finished("root:process:item_3").is_some(), true);
+    assert_equal!(
+        capture.finished("root:process").unwrap().progress,
+        Some((4, 4))
+    );
+
+    let root = tt.create_task("root");
+    let results = root
+        .spawn_map("process", vec!["a", "bb"], 0, |item, _task| async move {
+            Ok(item.len())
+        })
+        .await?;
+    assert_equal!(results, vec![1, 2]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn fail_fast_test() -> Result<()> {
+    let (tt, s) = setup();
+    s.set_deterministic(true);
+
+    let root = tt.create_task("root");
+    let tt_clone = tt.clone();
+    let result = root
+        .spawn("build", |task| async move {
+            task.fail_fast(true);
+            task.scope(|s| {
+                s.spawn("slow", |task| async move {
+                    task.spawn("nested", |_| async {
+                        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                        Ok(())
+                    })
+                    .await
+                });
+                s.spawn("broken", |_| async move {
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                    // the other tasks are reported as running before they
+                    // get cancelled
+                    tt_clone.flush_async().await;
+                    anyhow::bail!("compile error")
+                });
+            })
+            .await
+        })
+        .await;
+    drop(root);
+    tt.flush_async().await;
+
+    let err = format!("{:#}", result.unwrap_err());
+    assert_equal!(
+        err.contains("aborted after subtask root:build:broken failed"),
+        true
+    );
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:build
+[ ] | STARTING | root:build:broken
+[ ] [ERR] root:build:broken
+  |
+  |  [Task] broken
+  |  
+  |  
+  |  Caused by:
+  |      compile error
+[ ] | STARTING | root:build:slow
+[ ] | STARTING | root:build:slow:nested
+[ ] [ERR] root:build:slow:nested
+  |
+  |  cancelled because root:build:broken failed
+[ ] [ERR] root:build:slow
+  |
+  |  [Task] slow
+  |  
+  |  
+  |  Caused by:
+  |      cancelled because root:build:broken failed
+[ ] [ERR] root:build
+  |
+  |  [Task] build
+  |  
+  |  
+  |  Caused by:
+  |      0: aborted after subtask root:build:broken failed
+  |      1: 2 of 2 tasks in scope failed, other errors:
+  |         [Task] broken
+  |         : compile error
+  |      2: [Task] slow
+  |         
+  |      3: cancelled because root:build:broken failed
+[ ] root
+
+"
+    );
+    Ok(())
+}
+
+// the clock is paused, so sleeps and timeouts don't depend on how fast the
+// machine is
+#[tokio::test(start_paused = true)]
+async fn wait_idle_test() -> Result<()> {
+    use std::time::Duration;
+
+    let (tt, _s) = setup();
+    let root = tt.create_task("root");
+    for i in 0..3 {
+        root.spawn_detached(format!("background_{}", i), move |_| async move {
+            tokio::time::sleep(Duration::from_millis(10 * (i + 1))).await;
+            Ok(())
+        });
+    }
+    let other = tt.create_task("other");
+
+    tokio::time::timeout(Duration::from_secs(5), root.wait_idle()).await?;
+    let running = tt
+        .tree_internal
+        .read()
+        .unwrap()
+        .tasks()
+        .filter(|task| task.parent_id.is_some())
+        .filter(|task| !matches!(task.status, crate::task_tree::TaskStatus::Finished(..)))
+        .count();
+    assert_equal!(running, 0);
+
+    // the whole tree is busy until every task is done
+    let idle = tokio::time::timeout(Duration::from_millis(20), tt.wait_idle()).await;
+    assert_equal!(idle.is_err(), true);
+    drop(root);
+    drop(other);
+    tokio::time::timeout(Duration::from_secs(5), tt.wait_idle()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn recurring_test() -> Result<()> {
+    let (tt, s) = setup();
+
+    let root = tt.create_task("root");
+    let poll = root.recurring("poll_queue");
+    for i in 0..4 {
+        poll.run_sync(|_| {
+            if i == 2 {
+                anyhow::bail!("queue unavailable");
+            }
+            Ok(())
+        })
+        .ok();
+    }
+    drop(poll);
+    drop(root);
+    tt.flush_async().await;
+
+    // average duration isn't deterministic
+    let output = s
+        .to_string()
+        .lines()
+        .filter(|line| !line.contains("avg: "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    snapshot!(
+        output,
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:poll_queue
+[ ] [ERR] root:poll_queue:3
+  |
+  |  [Task] 3
+  |  
+  |  
+  |  Caused by:
+  |      queue unavailable
+[ ] root:poll_queue
+  |      occurrences: 4
+[ ] root
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn name_rate_limit_test() -> Result<()> {
+    use crate::clock::ManualClock;
+
+    let (tt, s) = setup();
+    let clock = Arc::new(ManualClock::new(std::time::SystemTime::UNIX_EPOCH));
+    tt.set_clock(clock.clone());
+    tt.set_name_rate_limit(Some(2));
+
+    let root = tt.create_task("root");
+    for _ in 0..5 {
+        root.spawn_sync("tick", |_| Ok(()))?;
+    }
+    root.spawn_sync("other", |_| Ok(()))?;
+    tt.flush_async().await;
+
+    clock.advance(Duration::from_secs(1));
+    root.spawn_sync("tick", |_| Ok(()))?;
+    tt.flush_async().await;
+
+    // failures of suppressed tasks fail the summary
+    for i in 0..3 {
+        root.spawn_sync("tick", |_| -> Result<()> { anyhow::bail!("error {}", i) })
+            .ok();
+    }
+    clock.advance(Duration::from_secs(1));
+    root.spawn_sync("tick", |_| Ok(()))?;
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:tick
+[ ] | STARTING | root:tick
+[ ] | STARTING | root:other
+[ ] root:tick
+[ ] root:tick
+[ ] root:other
+[ ] | STARTING | root:tick
+[ ] root:tick
+  |      suppressed: 3
+[ ] root:tick
+[ ] | STARTING | [ERR] root:tick
+[ ] | STARTING | root:tick
+[ ] [ERR] root:tick
+  |      suppressed: 2
+  |      suppressed_failed: 2
+  |
+  |  2 of the suppressed tasks failed, last error:
+  |  [Task] tick
+  |  
+  |  
+  |  Caused by:
+  |      error 2
+[ ] [ERR] root:tick
+  |
+  |  [Task] tick
+  |  
+  |  
+  |  Caused by:
+  |      error 0
+[ ] root:tick
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn name_rate_limit_prefix_test() -> Result<()> {
+    use crate::clock::ManualClock;
+
+    let (tt, s) = setup();
+    let clock = Arc::new(ManualClock::new(std::time::SystemTime::UNIX_EPOCH));
+    tt.set_clock(clock.clone());
+    tt.set_name_prefix(Some("my_service"));
+    tt.set_name_rate_limit(Some(1));
+
+    tt.spawn_sync("job".to_string(), |_| Ok(()), None)?;
+    tt.spawn_sync("job".to_string(), |_| Ok(()), None)?;
+    tt.spawn_sync(
+        "job".to_string(),
+        |_| -> Result<()> { anyhow::bail!("failed") },
+        None,
+    )
+    .ok();
+    clock.advance(Duration::from_secs(1));
+    tt.spawn_sync("job".to_string(), |_| Ok(()), None)?;
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | my_service.job
+[ ] | STARTING | my_service.job
+[ ] [ERR] my_service.job
+  |      suppressed: 2
+  |      suppressed_failed: 1
+  |
+  |  1 of the suppressed tasks failed, last error:
+  |  [Task] my_service.job
+  |  
+  |  
+  |  Caused by:
+  |      failed
+[ ] my_service.job
+[ ] my_service.job
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn interrupt_running_test() -> Result<()> {
+    let (tt, s) = setup();
+
+    let root = tt.create_task("root");
+    root.spawn_sync("done", |_| Ok(()))?;
+    let running = root.create("running");
+    tt.interrupt_running("SIGTERM");
+    let trace = tt.trace();
+    // interrupted tasks aren't reported again when their handles are dropped
+    drop(running);
+    drop(root);
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | [ERR] root
+[ ] | STARTING | root:done
+[ ] | STARTING | [ERR] root:running
+[ ] root:done
+[ ] [ERR] root
+  |
+  |  interrupted: SIGTERM
+[ ] [ERR] root:running
+  |
+  |  interrupted: SIGTERM
+
+"
+    );
+    assert_equal!(trace.tasks.len(), 1);
+    assert_equal!(trace.tasks[0].children.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn data_scope_test() -> Result<()> {
+    let (tt, _s) = setup();
+    tt.add_data_transitive("service", "api");
+
+    let tenant_a = tt.open_data_scope();
+    let tenant_b = tt.open_data_scope();
+    tenant_a.add_data_transitive("tenant_id", "a");
+    // tree level data added inside a scope stays in that scope
+    tenant_b.enter(|| tt.add_data_transitive("tenant_id", "b"));
+
+    let request_a = tenant_a.create_task("request");
+    let child_a = request_a.create("child");
+    let request_b = tenant_b.run(async { tt.create_task("request") }).await;
+    let unscoped = tt.create_task("health_check");
+
+    assert_equal!(child_a.get_data("tenant_id"), Some("a".into()));
+    assert_equal!(child_a.get_data("service"), Some("api".into()));
+    assert_equal!(request_b.get_data("tenant_id"), Some("b".into()));
+    assert_equal!(unscoped.get_data("tenant_id"), None);
+
+    // tasks keep the data after the scope is closed
+    tenant_a.close();
+    assert_equal!(
+        request_a.create("late").get_data("tenant_id"),
+        Some("a".into())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn redaction_rules_test() -> Result<()> {
+    use crate::redaction::RedactionRules;
+
+    let (tt, s) = setup();
+    tt.add_data_transitive("api_token", "abc123");
+    let root = tt.create_task("root");
+    root.data("user", "ann");
+    root.spawn_sync("login {email}", |task| -> Result<()> {
+        task.data("email", "ann@example.com");
+        tt.set_redaction_rules(RedactionRules::parse("deny:*_token, deny:email")?);
+        anyhow::bail!("wrong password")
+    })
+    .ok();
+    tt.flush_async().await;
+
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | [ERR] root:login <redacted>
+[ ] [ERR] root:login <redacted>
+  |      email: <redacted>
+  |      api_token: <redacted>
+  |
+  |  [Task] login <redacted>
+  |    email: <redacted>
+  |    api_token: <redacted>
+  |  
+  |  
+  |  Caused by:
+  |      wrong password
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn task_internal_accessors_test() -> Result<()> {
+    use crate::reporters::Level;
+
+    let tt = TaskTree::new();
+    let capture = crate::reporters::CaptureReporter::new();
+    tt.add_reporter(Arc::new(capture.clone()));
+
+    let root = tt.create_task("root");
+    root.spawn_sync("first #l2", |_| Ok(()))?;
+    root.spawn_sync("second", |_| Ok(()))?;
+    drop(root);
+    tt.flush_async().await;
+
+    let root = capture.finished("root").unwrap();
+    let first = capture.finished("first").unwrap();
+    assert_equal!(root.child_count(), 2);
+    assert_equal!(first.child_count(), 0);
+    assert_equal!(first.parent_id(), Some(root.id()));
+    assert_equal!(first.parent_names(), ["root".to_string()]);
+    assert!(first.level() == Level::L2);
+    assert!(root.level() == Level::L1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn full_name_format_test() -> Result<()> {
+    use crate::task_tree::FullNameFormat;
+
+    let (tt, s) = setup();
+    tt.set_full_name_format(FullNameFormat {
+        separator: " > ".to_string(),
+        max_depth: Some(1),
+        ..FullNameFormat::default()
+    });
+    let root = tt.create_task("root");
+    root.spawn("request", |task| async move {
+        task.spawn_sync("db_query", |_| Ok(()))
+    })
+    .await?;
+    let early = root.create("early");
+
+    // tasks that were already created keep their format
+    tt.set_full_name_format(FullNameFormat::default());
+    drop(early);
+    root.spawn_sync("late", |_| Ok(()))?;
+
+    tt.flush_async().await;
+    snapshot!(
+        s.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root > request
+[ ] | STARTING | request > db_query
+[ ] | STARTING | root > early
+[ ] | STARTING | root:late
+[ ] request > db_query
+[ ] root > request
+[ ] root > early
+[ ] root:late
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn float_progress_test() -> Result<()> {
+    let tt = TaskTree::new();
+    let task = tt.create_task("download");
+    let format_progress = |task: &crate::Task| {
+        let tree = tt.tree_internal.read().unwrap();
+        let task_internal = tree.get_task(task.0.id).unwrap();
+        format!(
+            "{:?} {:?}",
+            task_internal.progress,
+            task_internal.format_progress()
+        )
+    };
+
+    task.progress(3, 10);
+    snapshot!(format_progress(&task), r#"Some((3, 10)) Some("3/10")"#);
+    task.progress_f64(2.5, 10.0);
+    snapshot!(format_progress(&task), r#"Some((2, 10)) Some("2.5/10")"#);
+    task.progress_format(|done, total| format!("{:.0}%", done / total * 100.0));
+    snapshot!(format_progress(&task), r#"Some((2, 10)) Some("25%")"#);
+    task.progress_format(|done, total| format!("{}/{}", done, total));
+
+    // fractional totals
+    task.progress_f64(2.0, 2.5);
+    snapshot!(format_progress(&task), r#"Some((2, 3)) Some("2/2.5")"#);
+    task.progress_f64(2.5, 2.5);
+    snapshot!(format_progress(&task), r#"Some((3, 3)) Some("2.5/2.5")"#);
+    task.progress_f64(0.2, 0.4);
+    snapshot!(format_progress(&task), r#"Some((0, 1)) Some("0.2/0.4")"#);
+    task.progress_f64(0.4, 0.4);
+    snapshot!(format_progress(&task), r#"Some((1, 1)) Some("0.4/0.4")"#);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn capture_child_test() -> Result<()> {
+    let tt = TaskTree::new();
+    let capture = crate::reporters::CaptureReporter::new();
+    tt.add_reporter(Arc::new(capture.clone()));
+
+    let root = tt.create_task("root");
+    let err = root
+        .spawn("script", |task| async move {
+            let mut command = tokio::process::Command::new("sh");
+            command.args(["-c", "echo one; printf 't\\377o\\n'; echo oops >&2; exit 3"]);
+            task.capture_child(&mut command).await
+        })
+        .await
+        .unwrap_err();
+    tt.flush_async().await;
+
+    snapshot!(
+        err.root_cause().to_string(),
+        "`sh` exited with exit status: 3"
+    );
+    let script = capture.finished("script").unwrap();
+    // stdout and stderr are read concurrently, so only the order within
+    // each of them is stable
+    let lines = |key: &str| {
+        script
+            .data
+            .timeline
+            .iter()
+            .filter(|change| change.key == key)
+            .map(|change| change.value.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_equal!(lines("stdout"), vec!["one", "t\u{fffd}o"]);
+    assert_equal!(lines("stderr"), vec!["oops"]);
+    assert_equal!(lines("exit_code"), vec!["3"]);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn run_command_test() -> Result<()> {
+    use crate::process::CommandOptions;
+    use crate::task_tree::RetryPolicy;
+
+    let tt = TaskTree::new();
+    let capture = crate::reporters::CaptureReporter::new();
+    tt.add_reporter(Arc::new(capture.clone()));
+    let root = tt.create_task("root");
+
+    // fails the first time it runs and succeeds the second time
+    let marker = std::env::temp_dir().join(format!("ll-run-command-test-{}", std::process::id()));
+    let mut command = tokio::process::Command::new("sh");
+    command.args([
+        "-c",
+        "if [ -f \"$0\" ]; then echo fetched; else touch \"$0\"; echo failed >&2; exit 1; fi",
+    ]);
+    command.arg(&marker);
+    let options = CommandOptions {
+        retry_policy: RetryPolicy {
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        },
+        ..CommandOptions::default()
+    };
+    root.run_command_with("fetch", command, options).await?;
+    std::fs::remove_file(&marker)?;
+
+    let mut command = tokio::process::Command::new("sh");
+    command.args(["-c", "echo started; sleep 5"]);
+    let options = CommandOptions {
+        timeout: Some(Duration::from_millis(200)),
+        ..CommandOptions::default()
+    };
+    let err = root
+        .run_command_with("hang", command, options)
+        .await
+        .unwrap_err();
+    tt.flush_async().await;
+
+    snapshot!(err.root_cause().to_string(), "`sh` timed out after 200ms");
+    let get = |task: &TaskInternal, key: &str| task.data.map.get(key).map(|e| e.0.to_string());
+    let fetch = capture.finished("fetch").unwrap();
+    assert!(fetch.has_tag(crate::process::COMMAND));
+    assert_equal!(get(&fetch, "retries"), Some("1".to_string()));
+    assert_equal!(get(&fetch, "output"), Some("fetched".to_string()));
+    assert_equal!(get(&fetch, "exit_code"), Some("0".to_string()));
+    assert!(get(&fetch, "duration_ms").is_some());
+    let hang = capture.finished("hang").unwrap();
+    assert_equal!(get(&hang, "output"), Some("started".to_string()));
+    assert_equal!(get(&hang, "exit_code"), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn data_transitive_scoped_panic_test() -> Result<()> {
+    let (tt, _s) = setup();
+    let root = tt.create_task("root");
+    root.data_transitive("request_id", 1);
+
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        root.data_transitive_scoped("request_id", 2, || panic!("scoped panic"))
+    }));
+    assert!(panicked.is_err());
+    assert_equal!(root.get_data("request_id"), Some(1.into()));
+    Ok(())
+}
+
 // #[test]
 // fn custom_drain_test() {
 //     let s = Arc::new(Mutex::new(String::new()));
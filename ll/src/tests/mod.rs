@@ -1 +1,4 @@
+// `err.chain().into_iter()` in the error formatter tests predates this lint
+#[allow(clippy::useless_conversion)]
 mod basic_test;
+mod reporters_test;
@@ -0,0 +1,613 @@
+use crate::reporters::text::{strip_ansi, TimestampFormat};
+use crate::reporters::{
+    CaptureReporter, ChannelReporter, GithubActionsReporter, GroupStatsReporter, JUnitReporter,
+    JsonlReporter, NullReporter, PeriodicSummaryReporter, Reporter, RingBufferReporter,
+    StringReporter, TapReporter, TaskReportType, TeamCityReporter,
+};
+use crate::task_tree::TaskTree;
+use anyhow::Result;
+use k9::*;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Writer that can be handed to a reporter and inspected later
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SharedBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let output = String::from_utf8_lossy(&self.0.lock().unwrap()).to_string();
+        // durations are not deterministic
+        let output = strip_ansi(&output)
+            .lines()
+            .map(|line| match line.find("ms | ") {
+                Some(i) => format!("| <duration> | {}", &line[i + 5..]),
+                None => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        write!(f, "{}", output)
+    }
+}
+
+#[tokio::test]
+async fn github_actions_reporter_test() -> Result<()> {
+    let buffer = SharedBuffer::default();
+    let mut reporter = GithubActionsReporter::with_writer(Box::new(buffer.clone()));
+    reporter.group_depth = 1;
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(reporter));
+    // deliver reports as they happen, otherwise starts and ends of sibling
+    // tasks can get batched together
+    tt.set_force_flush(true);
+
+    let root = tt.create_task("root");
+    root.spawn_sync("build", |t| {
+        t.spawn_sync("compile", |_| Ok(()))?;
+        Ok(())
+    })?;
+    root.spawn_sync("test", |t| {
+        t.spawn_sync("unit", |_| -> Result<()> {
+            anyhow::bail!("1 test failed\n50%")
+        })
+    })
+    .ok();
+
+    tt.flush_async().await;
+    snapshot!(
+        buffer.to_string(),
+        "
+::group::root:build
+| <duration> | root:build:compile
+| <duration> | root:build
+::endgroup::
+::group::root:test
+| <duration> | [ERR] root:test:unit
+  |
+  |  [Task] unit
+  |  
+  |  
+  |  Caused by:
+  |      1 test failed
+  |      50%
+::error title=root%3Atest%3Aunit::[Task] unit%0A%0A%0ACaused by:%0A    1 test failed%0A    50%25
+| <duration> | [ERR] root:test
+  |
+  |  [Task] test
+  |  
+  |  
+  |  Caused by:
+  |      0: [Task] unit
+  |         
+  |      1: 1 test failed
+  |         50%
+::endgroup::
+::error title=root%3Atest::[Task] test%0A%0A%0ACaused by:%0A    0: [Task] unit%0A       %0A    1: 1 test failed%0A       50%25
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn teamcity_reporter_test() -> Result<()> {
+    let buffer = SharedBuffer::default();
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(TeamCityReporter::with_writer(Box::new(
+        buffer.clone(),
+    ))));
+    tt.set_force_flush(true);
+
+    let root = tt.create_task("root");
+    root.spawn_sync("[check]", |_| -> Result<()> {
+        anyhow::bail!("it's | broken")
+    })
+    .ok();
+    drop(root);
+
+    tt.flush_async().await;
+    // ids are not deterministic
+    let output = buffer
+        .to_string()
+        .split("flowId='")
+        .enumerate()
+        .map(|(i, part)| match i {
+            0 => part.to_string(),
+            _ => format!("flowId='<id>{}", part.trim_start_matches(char::is_numeric)),
+        })
+        .collect::<String>();
+    snapshot!(
+        output,
+        "
+##teamcity[blockOpened name='root' flowId='<id>']
+##teamcity[blockOpened name='root:|[check|]' flowId='<id>']
+##teamcity[message text='root:|[check|] failed' errorDetails='|[Task|] |[check|]|n|n|nCaused by:|n    it|'s || broken' status='ERROR' flowId='<id>']
+##teamcity[blockClosed name='root:|[check|]' flowId='<id>']
+##teamcity[blockClosed name='root' flowId='<id>']
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn jsonl_reporter_test() -> Result<()> {
+    let buffer = SharedBuffer::default();
+    let tt = TaskTree::new();
+    let direct = StringReporter::new();
+    direct.set_deterministic(true);
+    tt.add_reporter(Arc::new(direct.clone()));
+    tt.add_reporter(Arc::new(JsonlReporter::with_writer(Box::new(
+        buffer.clone(),
+    ))));
+    tt.set_force_flush(true);
+
+    let root = tt.create_task("root #l1");
+    root.data("user#dontprint", "ann");
+    root.data_transitive("request_id", 5);
+    root.spawn_sync("fails", |task| -> Result<()> {
+        task.data("attempt", 2);
+        anyhow::bail!("broken")
+    })
+    .ok();
+    drop(root);
+    tt.flush_async().await;
+
+    // rendering the parsed output should be the same as reporting directly
+    let replayed = StringReporter::new();
+    replayed.set_deterministic(true);
+    let output = String::from_utf8_lossy(&buffer.0.lock().unwrap()).to_string();
+    let mut seqs = vec![];
+    for line in output.lines() {
+        let event = crate::reporters::jsonl::JsonlEvent::parse(line)?;
+        seqs.push(event.seq);
+        let task = Arc::new(event.to_task_internal());
+        match event.report_type() {
+            TaskReportType::Start => replayed.task_start(task),
+            TaskReportType::Stalled => replayed.task_stalled(task),
+            TaskReportType::End => replayed.task_end(task),
+        }
+    }
+    assert_equal!(output.lines().count(), 4);
+    assert_equal!(seqs, vec![1, 2, 3, 4]);
+    assert_equal!(replayed.to_string(), direct.to_string());
+    snapshot!(
+        replayed.to_string(),
+        "
+[ ] | STARTING | root
+[ ] | STARTING | root:fails
+[ ] [ERR] root:fails
+  |      attempt: 2
+  |      request_id: 5
+  |
+  |  [Task] fails
+  |    attempt: 2
+  |    request_id: 5
+  |  
+  |  
+  |  Caused by:
+  |      broken
+[ ] root
+  |      request_id: 5
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn junit_reporter_test() -> Result<()> {
+    let mut reporter = JUnitReporter::new("checks");
+    reporter.tag_filter = Some("test".to_string());
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(reporter.clone()));
+
+    let root = tt.create_task("root");
+    root.spawn_sync("lint #test", |_| Ok(()))?;
+    root.spawn_sync("not_a_test", |_| Ok(()))?;
+    root.spawn_sync("compare <a> & <b> #test", |_| -> Result<()> {
+        anyhow::bail!("\"a\" != \"b\"")
+    })
+    .ok();
+
+    tt.flush_async().await;
+    // durations are not deterministic
+    let xml = reporter
+        .to_xml()
+        .split(" time=\"")
+        .enumerate()
+        .map(|(i, part)| match i {
+            0 => part.to_string(),
+            _ => format!(" time=\"<t>{}", &part[part.find('"').unwrap()..]),
+        })
+        .collect::<String>();
+    snapshot!(
+        xml,
+        r#"
+<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="checks" tests="2" failures="1" time="<t>">
+    <testcase classname="root" name="lint" time="<t>" />
+    <testcase classname="root" name="compare &lt;a&gt; &amp; &lt;b&gt;" time="<t>">
+      <failure message="[Task] compare &lt;a&gt; &amp; &lt;b&gt;">[Task] compare &lt;a&gt; &amp; &lt;b&gt;
+
+
+Caused by:
+    &quot;a&quot; != &quot;b&quot;</failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+
+"#
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn tap_reporter_test() -> Result<()> {
+    let buffer = SharedBuffer::default();
+    let reporter = Arc::new(TapReporter::with_writer(Box::new(buffer.clone())));
+    let tt = TaskTree::new();
+    tt.add_reporter(reporter.clone());
+
+    let root = tt.create_task("root");
+    root.spawn_sync("first", |_| Ok(()))?;
+    root.spawn_sync("second", |_| -> Result<()> { anyhow::bail!("nope") })
+        .ok();
+
+    tt.flush_async().await;
+    reporter.finish();
+    snapshot!(
+        buffer.to_string(),
+        "
+TAP version 13
+ok 1 - root:first
+not ok 2 - root:second
+  ---
+  message: |
+    [Task] second
+    
+    
+    Caused by:
+        nope
+  ...
+1..2
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn null_reporter_test() -> Result<()> {
+    let reporter = Arc::new(NullReporter::new());
+    let tt = TaskTree::new();
+    tt.add_reporter(reporter.clone());
+
+    let root = tt.create_task("root");
+    for _ in 0..10 {
+        root.spawn_sync("task", |_| Ok(()))?;
+    }
+
+    tt.flush_async().await;
+    let stats = reporter.stats();
+    assert_equal!((stats.starts, stats.ends, stats.stalled), (11, 10, 0));
+    assert!(stats.mean_latency <= stats.max_latency);
+    Ok(())
+}
+
+#[tokio::test]
+async fn capture_reporter_test() -> Result<()> {
+    let capture = CaptureReporter::new();
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(capture.clone()));
+    tt.add_data_transitive("host", "localhost");
+
+    let root = tt.create_task("root");
+    root.spawn_sync("fetch", |t| {
+        t.data("url", "https://example.com");
+        t.data("bytes", 512);
+        Ok(())
+    })?;
+    root.spawn_sync("parse", |_| -> Result<()> { anyhow::bail!("bad input") })
+        .ok();
+
+    tt.flush_async().await;
+    assert!(capture.finished("root:fetch").is_some());
+    assert!(capture.finished("root").is_none());
+    snapshot!(
+        format!("{:?}", capture.data_of("fetch").unwrap()),
+        r#"{"bytes": Int(512), "host": String("localhost"), "url": String("https://example.com")}"#
+    );
+    snapshot!(
+        capture
+            .failed()
+            .iter()
+            .map(|t| t.full_name())
+            .collect::<Vec<_>>()
+            .join(", "),
+        "root:parse"
+    );
+    assert_equal!(capture.records().len(), 5);
+    Ok(())
+}
+
+#[tokio::test]
+async fn channel_reporter_test() -> Result<()> {
+    let reporter = ChannelReporter::new(16);
+    let mut events = reporter.subscribe();
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(reporter));
+
+    let root = tt.create_task("root");
+    tokio::spawn(async move {
+        root.spawn("background", |_| async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok(())
+        })
+        .await
+    });
+
+    let task = events.finished("root:background").await.unwrap();
+    assert_equal!(task.name, "background");
+    Ok(())
+}
+
+#[tokio::test]
+async fn bounded_output_test() -> Result<()> {
+    let string_reporter = StringReporter::new();
+    string_reporter.set_max_lines(Some(3));
+    let mut ring_buffer = RingBufferReporter::new(2);
+    ring_buffer.timestamp_format = TimestampFormat::None;
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(string_reporter.clone()));
+    tt.add_reporter(Arc::new(ring_buffer.clone()));
+
+    let root = tt.create_task("root");
+    for i in 0..5 {
+        root.spawn_sync(format!("task_{}", i), |_| Ok(()))?;
+    }
+
+    tt.flush_async().await;
+    snapshot!(
+        string_reporter.to_string(),
+        "
+[ ] root:task_2
+[ ] root:task_3
+[ ] root:task_4
+
+"
+    );
+    snapshot!(string_reporter.tail(1).join("\n"), "[ ] root:task_4");
+    // durations are not deterministic
+    snapshot!(
+        ring_buffer
+            .tail(10)
+            .iter()
+            .map(|line| line.split("ms | ").last().unwrap())
+            .collect::<Vec<_>>()
+            .join(", "),
+        "root:task_3, root:task_4"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn group_stats_reporter_test() -> Result<()> {
+    let reporter = GroupStatsReporter::new();
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(reporter.clone()));
+
+    let root = tt.create_task("root");
+    root.spawn_sync("read #group:io", |task| {
+        task.spawn_sync("parse #group:cpu", |_| Ok(()))?;
+        task.spawn_sync("write #group:io #group:cpu", |_| -> Result<()> {
+            anyhow::bail!("disk full")
+        })
+    })
+    .ok();
+    crate::Task::builder("checksum")
+        .parent(&root)
+        .group("cpu")
+        .spawn_sync(|_| Ok(()))?;
+
+    tt.flush_async().await;
+    let stats = reporter.stats();
+    assert_equal!(stats.keys().collect::<Vec<_>>(), vec!["cpu", "io"]);
+    assert_equal!((stats["cpu"].succeeded, stats["cpu"].failed), (2, 1));
+    assert_equal!((stats["io"].succeeded, stats["io"].failed), (0, 2));
+
+    let prometheus = reporter.to_prometheus();
+    assert!(prometheus.contains("ll_group_tasks_total{group=\"cpu\",status=\"success\"} 2\n"));
+    assert!(prometheus.contains("ll_group_task_duration_seconds_total{group=\"io\"} "));
+    Ok(())
+}
+
+#[cfg(feature = "audit")]
+#[tokio::test]
+async fn audit_reporter_test() -> Result<()> {
+    use crate::reporters::audit::{self, AuditReporter};
+
+    let buffer = SharedBuffer::default();
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(AuditReporter::with_writer(Box::new(
+        buffer.clone(),
+    ))));
+    tt.set_force_flush(true);
+
+    let root = tt.create_task("root");
+    root.spawn_sync("transfer", |task| {
+        task.data("amount", 100);
+        Ok(())
+    })?;
+    drop(root);
+    tt.flush_async().await;
+
+    let log = String::from_utf8_lossy(&buffer.0.lock().unwrap()).to_string();
+    assert_equal!(audit::verify(log.as_bytes())?, 4);
+
+    let tampered = log.replace("\"amount\":100", "\"amount\":1000");
+    assert_equal!(
+        audit::verify(tampered.as_bytes()).unwrap_err().to_string(),
+        "line 3: hash mismatch, the record was modified"
+    );
+
+    let mut lines = log.lines().collect::<Vec<_>>();
+    lines.remove(1);
+    assert_equal!(
+        audit::verify(lines.join("\n").as_bytes())
+            .unwrap_err()
+            .to_string(),
+        "line 2: expected record 2, found 3"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+#[tokio::test]
+async fn encrypted_jsonl_test() -> Result<()> {
+    use crate::reporters::encryption::{self, EncryptedWriter};
+
+    let key = [7; 32];
+    let buffer = SharedBuffer::default();
+    let tt = TaskTree::new();
+    tt.add_reporter(Arc::new(JsonlReporter::with_writer(Box::new(
+        EncryptedWriter::new(buffer.clone(), &key),
+    ))));
+    tt.set_force_flush(true);
+
+    let root = tt.create_task("root");
+    root.data("password_hint", "hunter");
+    drop(root);
+    tt.flush_async().await;
+
+    let encrypted = buffer.0.lock().unwrap().clone();
+    assert!(!String::from_utf8_lossy(&encrypted).contains("hunter"));
+
+    let decrypted = String::from_utf8(encryption::decrypt(encrypted.as_slice(), &key)?)?;
+    assert_equal!(decrypted.lines().count(), 2);
+    assert!(decrypted.contains("\"password_hint\":\"hunter\""));
+
+    assert!(encryption::decrypt(encrypted.as_slice(), &[8; 32]).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn periodic_summary_reporter_test() -> Result<()> {
+    use crate::clock::ManualClock;
+
+    let buffer = SharedBuffer::default();
+    let reporter = Arc::new(
+        PeriodicSummaryReporter::with_writer(Duration::from_secs(300), Box::new(buffer.clone()))
+            .slowest_count(2),
+    );
+    let tt = TaskTree::new();
+    tt.add_reporter(reporter.clone());
+    let clock = Arc::new(ManualClock::new(std::time::SystemTime::UNIX_EPOCH));
+    tt.set_clock(clock.clone());
+
+    let root = tt.create_task("root");
+    for (name, secs) in [("fast", 1), ("slow", 5), ("medium", 3)] {
+        root.spawn_sync(name, |_| {
+            clock.advance(Duration::from_secs(secs));
+            Ok(())
+        })?;
+    }
+    root.spawn_sync("fails", |_| -> Result<()> { anyhow::bail!("broken") })
+        .ok();
+    tt.flush_async().await;
+    reporter.write_now();
+
+    drop(root);
+    tt.flush_async().await;
+    reporter.write_now();
+
+    snapshot!(
+        String::from_utf8_lossy(&buffer.0.lock().unwrap()).to_string(),
+        "
+[ll] last 300s: 5 started, 4 finished (1 failed), 1 running
+[ll]   slowest: root:slow 5.0s, root:medium 3.0s
+[ll] last 300s: 0 started, 1 finished (0 failed), 0 running
+[ll]   slowest: root 9.0s
+
+"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn durable_queue_test() -> Result<()> {
+    use crate::reporters::ReporterExt;
+    use crate::task_tree::TaskInternal;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Network sink that can go down
+    struct Sink {
+        down: Arc<AtomicBool>,
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Reporter for Sink {
+        fn try_task_start(&self, _task: Arc<TaskInternal>) -> Result<()> {
+            if self.down.load(Ordering::SeqCst) {
+                anyhow::bail!("sink is down");
+            }
+            Ok(())
+        }
+
+        fn try_task_end(&self, task: Arc<TaskInternal>) -> Result<()> {
+            if self.down.load(Ordering::SeqCst) {
+                anyhow::bail!("sink is down");
+            }
+            self.received.lock().unwrap().push(task.full_name());
+            Ok(())
+        }
+    }
+
+    let path = std::env::temp_dir().join(format!("ll-durable-queue-test-{}", std::process::id()));
+    let down = Arc::new(AtomicBool::new(true));
+    let received = Arc::new(Mutex::new(vec![]));
+    let sink = || Sink {
+        down: down.clone(),
+        received: received.clone(),
+    };
+
+    let queue = Arc::new(sink().durable_queue(&path)?);
+    let tt = TaskTree::new();
+    let handle = tt.add_reporter(queue.clone());
+    let root = tt.create_task("root");
+    root.spawn_sync("a", |_| Ok(()))?;
+    root.spawn_sync("b", |_| Ok(()))?;
+    tt.flush_async().await;
+    // all events wait on disk for the sink to come back
+    assert_equal!(queue.replay()?, 5);
+    assert_equal!(received.lock().unwrap().len(), 0);
+
+    // the process "restarts" while the sink is still down, once the
+    // reporting thread lets go of the queue
+    let dropped = Arc::downgrade(&queue);
+    tt.remove_reporter(handle);
+    drop(queue);
+    while dropped.strong_count() > 0 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    let queue = sink().durable_queue(&path)?;
+    assert_equal!(queue.pending(), 5);
+
+    down.store(false, Ordering::SeqCst);
+    assert_equal!(queue.replay()?, 0);
+    assert_equal!(*received.lock().unwrap(), vec!["root:a", "root:b"]);
+    assert_equal!(std::fs::read_to_string(&path)?, "");
+
+    drop(queue);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
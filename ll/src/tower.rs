@@ -0,0 +1,114 @@
+/*!
+[tower](https://docs.rs/tower) middleware, enabled with the `tower` feature.
+
+[TaskLayer] wraps every HTTP request in a task named after the request
+method and path, records the response status and latency as task data, and
+marks the task as failed for 5xx responses and service errors. The request
+task is inserted into request extensions, so handlers can spawn subtasks
+under it.
+
+If the request has a W3C `traceparent` header, its trace id and parent span
+id are added to the request task as transitive data (`trace_id`,
+`parent_span_id`), so all subtasks of the request can be correlated with the
+caller.
+*/
+use crate::task::Task;
+use crate::trace_context::{continue_trace, TRACEPARENT_HEADER};
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[derive(Clone, Default)]
+pub struct TaskLayer {
+    parent: Option<Task>,
+}
+
+impl TaskLayer {
+    /// Create request tasks as subtasks of `parent`. Without a parent
+    /// ([TaskLayer::default()]) they're top level tasks of the global tree.
+    pub fn new(parent: &Task) -> Self {
+        Self {
+            parent: Some(parent.clone()),
+        }
+    }
+}
+
+impl<S> tower_layer::Layer<S> for TaskLayer {
+    type Service = TaskService<S>;
+
+    fn layer(&self, inner: S) -> TaskService<S> {
+        TaskService {
+            inner,
+            parent: self.parent.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TaskService<S> {
+    inner: S,
+    parent: Option<Task>,
+}
+
+impl<S, ReqBody, ResBody> tower_service::Service<http::Request<ReqBody>> for TaskService<S>
+where
+    S: tower_service::Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Display,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<ReqBody>) -> Self::Future {
+        let name = format!("{} {}", req.method(), req.uri().path());
+        let mut builder = Task::builder(name)
+            .data("method", req.method().as_str())
+            .data("path", req.uri().path());
+        if let Some(parent) = &self.parent {
+            builder = builder.parent(parent);
+        }
+        let task = builder.start();
+        if let Some(header) = req
+            .headers()
+            .get(TRACEPARENT_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            continue_trace(&task, header);
+        }
+        req.extensions_mut().insert(Task::clone(&task));
+
+        let task_tree = task.0.task_tree.clone();
+        let started_at = task_tree.now();
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let result = future.await;
+            let latency = task_tree
+                .now()
+                .duration_since(started_at)
+                .unwrap_or_default();
+            task.data("latency_ms", latency.as_millis() as i64);
+            match &result {
+                Ok(response) => {
+                    let status = response.status();
+                    task.data("status", status.as_u16() as i64);
+                    if status.is_server_error() {
+                        task.fail(anyhow::anyhow!("request failed with status {}", status));
+                    } else {
+                        task.success();
+                    }
+                }
+                Err(err) => {
+                    task.fail(anyhow::anyhow!("{}", err));
+                }
+            }
+            result
+        })
+    }
+}
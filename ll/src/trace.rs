@@ -0,0 +1,226 @@
+/*!
+Serializable snapshot of a task tree: every task with its data, result,
+timing and children.
+*/
+use crate::data::DataValue;
+use crate::output_schema::{check_schema_version, unversioned, SCHEMA_VERSION};
+use crate::reporters::capture::TaskRecord;
+use crate::task_tree::{TaskInternal, TaskResult, TaskStatus};
+use crate::uniq_id::UniqID;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trace {
+    /// see [output_schema](crate::output_schema)
+    #[serde(default = "unversioned")]
+    pub schema_version: u32,
+    pub tasks: Vec<TraceTask>,
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            tasks: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceTask {
+    pub id: u64,
+    pub name: String,
+    pub tags: Vec<String>,
+    pub data: BTreeMap<String, DataValue>,
+    pub status: TraceTaskStatus,
+    /// Milliseconds since the first task in the trace was started
+    pub started_at_ms: u64,
+    pub duration_ms: Option<u64>,
+    /// Values in the order they were added to the task, see
+    /// [Data::timeline](crate::data::Data::timeline)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub data_timeline: Vec<TraceDataChange>,
+    /// Ids of tasks this task waited on, see
+    /// [Task::waits_on()](crate::Task::waits_on)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub waits_on: Vec<u64>,
+    pub children: Vec<TraceTask>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceDataChange {
+    pub key: String,
+    pub value: DataValue,
+    /// Milliseconds since the task was started
+    pub offset_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceTaskStatus {
+    Running,
+    Paused { reason: String },
+    Scheduled,
+    Success,
+    Failure { error: String },
+}
+
+impl Trace {
+    /// Build a trace from reports captured by a
+    /// [CaptureReporter](crate::reporters::CaptureReporter). The latest
+    /// report of every task is used. Children are ordered by start time.
+    pub fn from_records(records: &[TaskRecord]) -> Self {
+        let mut latest: BTreeMap<UniqID, &TaskInternal> = BTreeMap::new();
+        let mut order = vec![];
+        for record in records {
+            if latest.insert(record.task.id, &record.task).is_none() {
+                order.push(record.task.id);
+            }
+        }
+
+        let start = latest
+            .values()
+            .map(|task| task.started_at)
+            .min()
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let mut children: BTreeMap<Option<UniqID>, Vec<UniqID>> = BTreeMap::new();
+        for id in &order {
+            let parent_id = latest[id].parent_id.filter(|id| latest.contains_key(id));
+            children.entry(parent_id).or_default().push(*id);
+        }
+
+        fn build(
+            id: UniqID,
+            latest: &BTreeMap<UniqID, &TaskInternal>,
+            children: &BTreeMap<Option<UniqID>, Vec<UniqID>>,
+            start: SystemTime,
+        ) -> TraceTask {
+            let task = latest[&id];
+            let (status, duration_ms) = match &task.status {
+                TaskStatus::Running => (TraceTaskStatus::Running, None),
+                TaskStatus::Scheduled(_) => (TraceTaskStatus::Scheduled, None),
+                TaskStatus::Paused(reason) => (
+                    TraceTaskStatus::Paused {
+                        reason: reason.clone(),
+                    },
+                    None,
+                ),
+                TaskStatus::Finished(result, finished_at) => {
+                    let status = match result {
+                        TaskResult::Success => TraceTaskStatus::Success,
+                        TaskResult::Failure(error) => TraceTaskStatus::Failure {
+                            error: error.clone(),
+                        },
+                    };
+                    let duration = finished_at.duration_since(task.started_at);
+                    (status, duration.ok().map(|d| d.as_millis() as u64))
+                }
+            };
+            TraceTask {
+                id: id.as_u64(),
+                name: task.name.clone(),
+                tags: task.tags.iter().cloned().collect(),
+                data: task
+                    .all_data()
+                    .map(|(k, entry)| (k.clone(), entry.0.clone()))
+                    .collect(),
+                status,
+                started_at_ms: task
+                    .started_at
+                    .duration_since(start)
+                    .map_or(0, |d| d.as_millis() as u64),
+                duration_ms,
+                data_timeline: data_timeline(task),
+                waits_on: task.waits_on.iter().map(|(id, _)| id.as_u64()).collect(),
+                children: children
+                    .get(&Some(id))
+                    .into_iter()
+                    .flatten()
+                    .map(|child_id| build(*child_id, latest, children, start))
+                    .collect(),
+            }
+        }
+
+        Self {
+            schema_version: SCHEMA_VERSION,
+            tasks: children
+                .get(&None)
+                .into_iter()
+                .flatten()
+                .map(|id| build(*id, &latest, &children, start))
+                .collect(),
+        }
+    }
+
+    /// Replace ids with sequence numbers (in tree order) and zero out all
+    /// times, so traces of different runs of the same code can be compared.
+    pub fn normalize(&mut self) {
+        fn assign_ids(task: &TraceTask, ids: &mut BTreeMap<u64, u64>) {
+            let next_id = ids.len() as u64;
+            ids.insert(task.id, next_id);
+            for child in &task.children {
+                assign_ids(child, ids);
+            }
+        }
+
+        fn normalize_task(task: &mut TraceTask, ids: &BTreeMap<u64, u64>) {
+            task.id = ids[&task.id];
+            task.started_at_ms = 0;
+            task.duration_ms = task.duration_ms.map(|_| 0);
+            for change in &mut task.data_timeline {
+                change.offset_ms = 0;
+            }
+            // dependencies outside of the trace can't be given stable ids
+            task.waits_on = task
+                .waits_on
+                .iter()
+                .filter_map(|id| ids.get(id).copied())
+                .collect();
+            for child in &mut task.children {
+                normalize_task(child, ids);
+            }
+        }
+
+        let mut ids = BTreeMap::new();
+        for task in &self.tasks {
+            assign_ids(task, &mut ids);
+        }
+        for task in &mut self.tasks {
+            normalize_task(task, &ids);
+        }
+    }
+
+    pub fn to_json_pretty(&self) -> String {
+        serde_json::to_string_pretty(self).expect("trace is always serializable")
+    }
+
+    /// Parse a trace written with [to_json_pretty()](Trace::to_json_pretty).
+    /// Fails for traces of a newer [schema version](crate::output_schema).
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let trace: Self = serde_json::from_str(json)?;
+        check_schema_version(trace.schema_version)?;
+        Ok(trace)
+    }
+}
+
+fn data_timeline(task: &TaskInternal) -> Vec<TraceDataChange> {
+    let mut timeline = task
+        .data
+        .timeline
+        .iter()
+        .chain(&task.data_transitive.timeline)
+        .map(|change| TraceDataChange {
+            key: change.key.clone(),
+            value: change.value.clone(),
+            offset_ms: change
+                .at
+                .duration_since(task.started_at)
+                .map_or(0, |d| d.as_millis() as u64),
+        })
+        .collect::<Vec<_>>();
+    timeline.sort_by_key(|change| change.offset_ms);
+    timeline
+}
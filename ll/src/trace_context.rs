@@ -0,0 +1,118 @@
+/*!
+W3C trace context (`traceparent` header) helpers shared by the HTTP and gRPC
+integrations. The trace id is stored as `trace_id` transitive data, so it is
+inherited by all subtasks of the task that received or started the trace.
+*/
+use crate::data::DataValue;
+use crate::task::Task;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Parse `version-trace_id-parent_id-flags` from a `traceparent` header
+/// into `(trace_id, parent_id)`.
+pub fn parse_traceparent(header: &str) -> Option<(String, String)> {
+    let parts = header.trim().split('-').collect::<Vec<_>>();
+    match parts.as_slice() {
+        [version, trace_id, parent_id, flags]
+            if is_hex(version, 2)
+                && *version != "ff"
+                && is_hex(trace_id, 32)
+                && is_hex(parent_id, 16)
+                && is_hex(flags, 2)
+                && trace_id.chars().any(|c| c != '0')
+                && parent_id.chars().any(|c| c != '0') =>
+        {
+            Some((trace_id.to_lowercase(), parent_id.to_lowercase()))
+        }
+        _ => None,
+    }
+}
+
+/// Record the trace context of an incoming request on its task
+pub fn continue_trace(task: &Task, header: &str) -> bool {
+    match parse_traceparent(header) {
+        Some((trace_id, parent_span_id)) => {
+            task.data_transitive("trace_id", trace_id);
+            task.data_transitive("parent_span_id", parent_span_id);
+            true
+        }
+        None => false,
+    }
+}
+
+/// `traceparent` header value for an outgoing request made from `task`.
+/// If `task` isn't part of a trace yet, a new trace is started on it. A new
+/// span id is generated for every call and recorded as `span_id` data.
+pub fn traceparent(task: &Task) -> String {
+    let trace_id = match task.get_data("trace_id") {
+        Some(DataValue::String(trace_id)) if is_hex(&trace_id, 32) => trace_id,
+        _ => {
+            let trace_id = format!("{:016x}{:016x}", random_u64(), random_u64());
+            task.data_transitive("trace_id", trace_id.as_str());
+            trace_id
+        }
+    };
+    let span_id = format!("{:016x}", random_u64());
+    task.data("span_id", span_id.as_str());
+    format!("00-{}-{}-01", trace_id, span_id)
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Non zero random number, good enough for trace and span ids
+fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.finish().max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k9::*;
+
+    #[test]
+    fn test_parse_traceparent() {
+        snapshot!(
+            format!(
+                "{:?}",
+                parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+            ),
+            r#"Some(("4bf92f3577b34da6a3ce929d0e0e4736", "00f067aa0ba902b7"))"#
+        );
+        snapshot!(
+            format!(
+                "{:?}",
+                parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01")
+            ),
+            "None"
+        );
+        snapshot!(format!("{:?}", parse_traceparent("garbage")), "None");
+    }
+
+    #[tokio::test]
+    async fn test_traceparent() {
+        let task = crate::task_tree::TaskTree::new().create_task("root");
+        let header = traceparent(&task);
+        let (trace_id, span_id) = parse_traceparent(&header).unwrap();
+        assert_equal!(
+            task.get_data("trace_id"),
+            Some(DataValue::String(trace_id.clone()))
+        );
+        assert_equal!(task.get_data("span_id"), Some(DataValue::String(span_id)));
+
+        // the same trace is continued by subtasks
+        let child = task.create("child");
+        let (child_trace_id, _) = parse_traceparent(&traceparent(&child)).unwrap();
+        assert_equal!(child_trace_id, trace_id);
+    }
+}
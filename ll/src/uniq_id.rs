@@ -1,15 +1,55 @@
+use std::collections::hash_map::RandomState;
 use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::sync::atomic::{AtomicU64, Ordering};
 
+/// Number of low bits of an id taken by the per-process counter. The rest
+/// is a random per-process component.
+const COUNTER_BITS: u32 = 40;
+const COUNTER_MASK: u64 = (1 << COUNTER_BITS) - 1;
+
 lazy_static::lazy_static! {
     static ref INCREMENTAL_UNIQ_ID: AtomicU64 = AtomicU64::new(0);
+    /// Random component shared by all ids created in this process, so ids
+    /// from reports of different processes (e.g. merged JSONL files) don't
+    /// collide.
+    static ref PROCESS_COMPONENT: u64 = random_process_component();
+}
+
+fn random_process_component() -> u64 {
+    // RandomState is seeded from OS randomness, which is enough to tell
+    // processes apart without pulling in a dependency for it
+    let mut hasher = RandomState::new().build_hasher();
+    std::process::id().hash(&mut hasher);
+    std::time::SystemTime::now().hash(&mut hasher);
+    hasher.finish() >> COUNTER_BITS
 }
+
+/// Task (and reporter) id. Ids created in the same process share a random
+/// component in their high bits and are ordered by creation in their low
+/// bits.
 #[derive(Clone, Copy, Hash, PartialOrd, PartialEq, Ord, Eq, Debug)]
 pub struct UniqID(u64);
 
 impl UniqID {
     pub fn new() -> Self {
-        UniqID(INCREMENTAL_UNIQ_ID.fetch_add(1, Ordering::SeqCst))
+        let counter = INCREMENTAL_UNIQ_ID.fetch_add(1, Ordering::SeqCst) & COUNTER_MASK;
+        UniqID((*PROCESS_COMPONENT << COUNTER_BITS) | counter)
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+
+    /// Random component of the process that created this id
+    pub fn process_component(&self) -> u64 {
+        self.0 >> COUNTER_BITS
+    }
+
+    /// Only for rebuilding tasks from serialized reports. IDs created this
+    /// way are not guaranteed to be unique within the process.
+    pub(crate) fn from_u64(id: u64) -> Self {
+        UniqID(id)
     }
 }
 
@@ -18,3 +58,18 @@ impl fmt::Display for UniqID {
         write!(f, "{}", self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_component_test() {
+        let a = UniqID::new();
+        let b = UniqID::new();
+        k9::assert_equal!(a.process_component(), b.process_component());
+        k9::assert_equal!(a.process_component(), *PROCESS_COMPONENT);
+        // ids are still ordered by creation within a process
+        k9::assert_equal!(a < b, true);
+    }
+}
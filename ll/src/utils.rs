@@ -59,11 +59,85 @@ pub(crate) fn extract_log_level_from_tags(tags: &BTreeSet<String>) -> Option<Lev
     result_level
 }
 
+// Priority from `p0`, `p1`, ... tags. If more than one is present, the most
+// important (lowest) one wins.
+pub(crate) fn extract_priority_from_tags(tags: &BTreeSet<String>) -> Option<u32> {
+    tags.iter()
+        .filter_map(|tag| tag.strip_prefix('p'))
+        .filter(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+        .filter_map(|digits| digits.parse().ok())
+        .min()
+}
+
+// Replace `{key}` placeholders in a task name with values returned by
+// `lookup`. Placeholders that can't be resolved (or aren't closed) are left
+// untouched so a typo in the key is still visible in the output.
+pub(crate) fn interpolate<F>(template: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    if !template.contains('{') {
+        return template.to_string();
+    }
+
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) => {
+                let key = &after[..end];
+                match lookup(key) {
+                    Some(value) if !key.is_empty() => result.push_str(&value),
+                    _ => {
+                        result.push('{');
+                        result.push_str(key);
+                        result.push('}');
+                    }
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use k9::*;
 
+    #[test]
+    fn test_priority_extraction() {
+        let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect();
+        assert_equal!(
+            extract_priority_from_tags(&tags(&["db", "p2", "p1"])),
+            Some(1)
+        );
+        assert_equal!(extract_priority_from_tags(&tags(&["p", "p2p", "pg"])), None);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let lookup = |key: &str| match key {
+            "url" => Some("https://example.com".to_string()),
+            "n" => Some("5".to_string()),
+            _ => None,
+        };
+        assert_equal!(interpolate("plain", lookup), "plain");
+        assert_equal!(
+            interpolate("fetch {url} x{n}", lookup),
+            "fetch https://example.com x5"
+        );
+        assert_equal!(interpolate("{missing} {} {n", lookup), "{missing} {} {n");
+    }
+
     #[test]
     fn test_tags_extraction() {
         let mut result = String::new();